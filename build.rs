@@ -0,0 +1,30 @@
+fn main() {
+    // Generate the tonic service from the proto definition with a vendored protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/captcha.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/captcha.proto"], &["proto"])
+            .expect("failed to compile proto/captcha.proto");
+    }
+
+    // Generate the C header of the ffi module into OUT_DIR, the source tree is read-only
+    // when the package is verified by `cargo publish`
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        let config = cbindgen::Config::from_root_or_default(&dir);
+        cbindgen::Builder::new()
+            .with_crate(&dir)
+            .with_config(config)
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(std::path::Path::new(&out_dir).join("ic_captcha.h"));
+    }
+}
@@ -0,0 +1,41 @@
+//! Regenerates the subset of the embedded font containing only the default charset glyphs.
+//! Other fonts, such as the bundled alternatives, can be subset by passing their paths.
+//!
+//! ```sh
+//! cargo run --example subset_font
+//! cargo run --example subset_font -- DejaVuSerif-Bold.ttf fonts/dejavu-serif-bold-subset.ttf
+//! ```
+
+use ab_glyph::{Font, FontRef};
+
+const FULL_FONT: &str = "fonts/arial-rounded-bold.ttf";
+const SUBSET_FONT: &str = "fonts/arial-rounded-bold-subset.ttf";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (full_font, subset_font) = match args.as_slice() {
+        [] => (FULL_FONT, SUBSET_FONT),
+        [full, subset] => (full.as_str(), subset.as_str()),
+        _ => panic!("usage: subset_font [FULL_FONT SUBSET_FONT]"),
+    };
+
+    let data = std::fs::read(full_font).expect("failed to read the full font");
+    let font = FontRef::try_from_slice(&data).expect("invalid font");
+
+    // keep .notdef and the glyphs of the default charset
+    let mut glyphs = vec![0u16];
+    glyphs.extend(ic_captcha::BASIC_CHARS.iter().map(|c| font.glyph_id(*c).0));
+    glyphs.sort_unstable();
+    glyphs.dedup();
+
+    let sub = subsetter::subset(&data, 0, subsetter::Profile::pdf(&glyphs))
+        .expect("failed to subset the font");
+    std::fs::write(subset_font, &sub).expect("failed to write the subset font");
+    println!(
+        "{}: {} bytes -> {}: {} bytes",
+        full_font,
+        data.len(),
+        subset_font,
+        sub.len()
+    );
+}
@@ -0,0 +1,80 @@
+#ifndef IC_CAPTCHA_H
+#define IC_CAPTCHA_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * An opaque generated captcha, freed by `icc_free`.
+ */
+typedef struct IccCaptcha IccCaptcha;
+
+/**
+ * The options of `icc_generate`, the defaults of `CaptchaBuilder::new` are used if NULL.
+ */
+typedef struct IccOptions {
+  uint8_t length;
+  uint32_t width;
+  uint32_t height;
+  uint8_t mode;
+  uint32_t complexity;
+} IccOptions;
+
+/**
+ * Generates a captcha with the given random seed, options and text, returns NULL on failure.
+ *
+ * # Safety
+ *
+ * `seed` must point to `seed_len` readable bytes, `options` must be NULL or a valid
+ * `IccOptions`, and `text` must be NULL or a NUL-terminated UTF-8 string.
+ */
+struct IccCaptcha *icc_generate(const uint8_t *seed,
+                                size_t seed_len,
+                                const struct IccOptions *options,
+                                const char *text);
+
+/**
+ * Returns the verification code string, valid until the captcha is freed.
+ *
+ * # Safety
+ *
+ * `captcha` must be a pointer returned by `icc_generate` and not freed yet.
+ */
+const char *icc_text(const struct IccCaptcha *captcha);
+
+/**
+ * Encodes the image in JPEG with the given quality (10-80, values out of
+ * the range are clamped), writes the length to `out_len`
+ * and returns the bytes, freed by `icc_free_bytes`. Returns NULL on failure.
+ *
+ * # Safety
+ *
+ * `captcha` must be a pointer returned by `icc_generate` and not freed yet,
+ * `out_len` must be a valid pointer.
+ */
+uint8_t *icc_to_jpeg(const struct IccCaptcha *captcha, uint8_t quality, size_t *out_len);
+
+/**
+ * Frees the bytes returned by `icc_to_jpeg`.
+ *
+ * # Safety
+ *
+ * `data` and `len` must be returned by `icc_to_jpeg` and not freed yet, or `data` is NULL.
+ */
+void icc_free_bytes(uint8_t *data, size_t len);
+
+/**
+ * Frees a captcha returned by `icc_generate`.
+ *
+ * # Safety
+ *
+ * `captcha` must be returned by `icc_generate` and not freed yet, or NULL.
+ */
+void icc_free(struct IccCaptcha *captcha);
+
+#endif  /* IC_CAPTCHA_H */
@@ -0,0 +1,153 @@
+#[cfg(feature = "gif")]
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, DynamicImage, Frame,
+};
+use image::{ImageBuffer, Rgb};
+use sha3::{Digest, Sha3_256};
+
+#[cfg(any(feature = "gif", feature = "apng"))]
+use crate::CaptchaError;
+use crate::{Captcha, CaptchaBuilder};
+
+// Domain separation for the interference seeds of the animation frames
+const FRAME_DOMAIN: &[u8] = b"ic-captcha:frame-seed";
+
+// The inputs of a rendered captcha, to render the other frames of its animation
+pub(crate) struct FrameSource {
+    pub(crate) builder: CaptchaBuilder,
+    pub(crate) seed: Vec<u8>,
+    pub(crate) text: Option<String>,
+}
+
+// Return the seed the interference of the given frame is drawn from, frame 0 uses the
+// random stream of the captcha itself
+pub(crate) fn frame_seed(seed: &[u8], frame: u32) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(FRAME_DOMAIN);
+    hasher.update(seed);
+    hasher.update(frame.to_be_bytes());
+    hasher.finalize().into()
+}
+
+impl Captcha {
+    /// Returns `count` frames of an animation of the captcha, the characters stay fixed while
+    /// the interference lines, ellipses and noise are re-drawn in every frame from a seed
+    /// derived from the captcha seed and the frame index. The first frame is the image of the
+    /// captcha and the frames are deterministic. A captcha not generated by a builder, e.g.
+    /// an empty one, repeats its image.
+    pub fn frames(&self, count: u32) -> Vec<ImageBuffer<Rgb<u8>, Vec<u8>>> {
+        (0..count)
+            .map(|frame| match (frame, self.frame_source()) {
+                (1.., Some(source)) => {
+                    let mut builder = source.builder.clone();
+                    builder.frame = frame;
+                    builder
+                        .generate(&source.seed, source.text.clone())
+                        .image()
+                        .clone()
+                }
+                _ => self.image().clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the animation of the captcha encoded as a looping GIF with `frames` frames,
+    /// each shown for `delay_ms` milliseconds, see [`Captcha::frames`].
+    /// The colors of every frame are quantized to a 256 color palette.
+    #[cfg(feature = "gif")]
+    pub fn to_gif(&self, frames: u32, delay_ms: u32) -> Result<Vec<u8>, CaptchaError> {
+        let mut buf = Vec::new();
+        {
+            let mut encoder = GifEncoder::new_with_speed(&mut buf, 10);
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .map_err(|err| CaptchaError::Encode(err.to_string()))?;
+            encoder
+                .encode_frames(self.frames(frames).into_iter().map(|image| {
+                    Frame::from_parts(
+                        DynamicImage::ImageRgb8(image).into_rgba8(),
+                        0,
+                        0,
+                        Delay::from_numer_denom_ms(delay_ms, 1),
+                    )
+                }))
+                .map_err(|err| CaptchaError::Encode(err.to_string()))?;
+        }
+        Ok(buf)
+    }
+
+    /// Returns the animation of the captcha encoded as a looping APNG with `frames` frames,
+    /// each shown for `delay_ms` milliseconds (up to 65535), see [`Captcha::frames`].
+    /// Unlike GIF, the colors are not limited to a 256 color palette.
+    #[cfg(feature = "apng")]
+    pub fn to_apng(&self, frames: u32, delay_ms: u32) -> Result<Vec<u8>, CaptchaError> {
+        let encode_err = |err: png::EncodingError| CaptchaError::Encode(err.to_string());
+        let frames = self.frames(frames.max(1));
+        let (width, height) = frames[0].dimensions();
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(frames.len() as u32, 0)
+            .map_err(encode_err)?;
+        encoder
+            .set_frame_delay(delay_ms.min(u16::MAX as u32) as u16, 1000)
+            .map_err(encode_err)?;
+        let mut writer = encoder.write_header().map_err(encode_err)?;
+        for frame in &frames {
+            writer
+                .write_image_data(frame.as_raw())
+                .map_err(encode_err)?;
+        }
+        writer.finish().map_err(encode_err)?;
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CaptchaBuilder;
+
+    #[test]
+    fn it_renders_frames() {
+        let builder = CaptchaBuilder::new();
+        let captcha = builder.generate(b"seed", None);
+        let frames = captcha.frames(3);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(&frames[0], captcha.image());
+        assert_ne!(frames[1], frames[0]);
+        assert_ne!(frames[2], frames[1]);
+        assert_eq!(frames, builder.generate_lazy(b"seed", None).frames(3));
+
+        let text = builder.generate(b"seed", Some("AbCd".to_string()));
+        assert_eq!(text.frames(2)[0], *text.image());
+    }
+
+    #[test]
+    #[cfg(feature = "gif")]
+    fn it_encodes_gif() {
+        let captcha = CaptchaBuilder::new().generate(b"seed", None);
+        let gif = captcha.to_gif(4, 200).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(gif, captcha.to_gif(4, 200).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "apng")]
+    fn it_encodes_apng() {
+        let captcha = CaptchaBuilder::new().generate(b"seed", None);
+        let apng = captcha.to_apng(4, 200).unwrap();
+        assert_eq!(&apng[..8], b"\x89PNG\r\n\x1a\n");
+        assert!(apng.windows(4).any(|chunk| chunk == b"acTL"));
+        assert_eq!(apng.windows(4).filter(|chunk| *chunk == b"fcTL").count(), 4);
+        assert_eq!(apng, captcha.to_apng(4, 200).unwrap());
+
+        // The first frame is the default image of the APNG
+        let mut reader = png::Decoder::new(apng.as_slice()).read_info().unwrap();
+        let mut frame = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut frame).unwrap();
+        assert_eq!(&frame, captcha.image().as_raw());
+    }
+}
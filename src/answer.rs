@@ -0,0 +1,192 @@
+use sha3::{Digest, Sha3_256};
+
+use crate::{token::constant_time_eq, token::normalize_answer, CaptchaError, ChallengeId};
+
+// Domain separation for the answer hashes
+const ANSWER_DOMAIN: &[u8] = b"ic-captcha:answer-hash";
+
+/// A stored hash of a challenge answer, salted with the challenge id and peppered with
+/// a server-side secret identified by `key_id`, see [`PepperRing`].
+/// Unlike [`crate::ChallengeToken`], it is kept by the server (e.g. in stable memory),
+/// and the key id lets the stored hashes survive a rotation of the pepper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnswerHash {
+    /// The id of the pepper the hash was computed with.
+    pub key_id: u32,
+    /// The hash of the normalized answer.
+    #[cfg_attr(feature = "serde", serde(with = "serde_bytes"))]
+    pub hash: [u8; 32],
+}
+
+impl AnswerHash {
+    /// The length of the hash in bytes.
+    pub const LEN: usize = 4 + 32;
+
+    /// Returns the hash in bytes.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut buf = [0u8; Self::LEN];
+        buf[..4].copy_from_slice(&self.key_id.to_be_bytes());
+        buf[4..].copy_from_slice(&self.hash);
+        buf
+    }
+
+    /// Parses a hash from bytes.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CaptchaError> {
+        if data.len() != Self::LEN {
+            return Err(CaptchaError::Decode(format!(
+                "expected {} bytes, got {}",
+                Self::LEN,
+                data.len()
+            )));
+        }
+
+        let mut key_id = [0u8; 4];
+        key_id.copy_from_slice(&data[..4]);
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&data[4..]);
+        Ok(AnswerHash {
+            key_id: u32::from_be_bytes(key_id),
+            hash,
+        })
+    }
+}
+
+// A pepper with its id
+#[derive(Clone)]
+struct Pepper {
+    key_id: u32,
+    secret: Vec<u8>,
+}
+
+/// The server-side peppers of [`AnswerHash`]es: the current pepper hashes new answers,
+/// the previous one is still accepted until the end of its rotation window.
+#[derive(Clone)]
+pub struct PepperRing {
+    current: Pepper,
+    // The previous pepper and the end of its rotation window in unix milliseconds
+    previous: Option<(Pepper, u64)>,
+}
+
+impl PepperRing {
+    /// Returns a ring with the given current pepper and its id.
+    pub fn new(key_id: u32, pepper: &[u8]) -> Self {
+        PepperRing {
+            current: Pepper {
+                key_id,
+                secret: pepper.to_vec(),
+            },
+            previous: None,
+        }
+    }
+
+    /// Rotates to a new pepper at the given time in unix milliseconds. The current pepper
+    /// becomes the previous one, accepted for `window_ms` milliseconds, which should be
+    /// at least the lifetime of the stored hashes. An older previous pepper is dropped.
+    pub fn rotate(&mut self, key_id: u32, pepper: &[u8], now_ms: u64, window_ms: u64) {
+        let previous = std::mem::replace(
+            &mut self.current,
+            Pepper {
+                key_id,
+                secret: pepper.to_vec(),
+            },
+        );
+        self.previous = Some((previous, now_ms.saturating_add(window_ms)));
+    }
+
+    /// Returns the id of the current pepper.
+    pub fn current_key_id(&self) -> u32 {
+        self.current.key_id
+    }
+
+    /// Returns the hash of the answer of a challenge with the current pepper.
+    /// The answer is case-insensitive, as with [`crate::ChallengeToken`].
+    pub fn hash(&self, id: &ChallengeId, answer: &str) -> AnswerHash {
+        AnswerHash {
+            key_id: self.current.key_id,
+            hash: answer_hash(&self.current.secret, id, answer),
+        }
+    }
+
+    /// Verifies the answer of a challenge against a stored hash at the given time in unix
+    /// milliseconds. Hashes of the previous pepper are accepted within its rotation window;
+    /// hashes of unknown or retired peppers are rejected with [`CaptchaError::InvalidToken`].
+    pub fn verify(
+        &self,
+        stored: &AnswerHash,
+        id: &ChallengeId,
+        answer: &str,
+        now_ms: u64,
+    ) -> Result<(), CaptchaError> {
+        let pepper = match &self.previous {
+            _ if stored.key_id == self.current.key_id => &self.current,
+            Some((previous, until)) if stored.key_id == previous.key_id => {
+                if now_ms > *until {
+                    return Err(CaptchaError::InvalidToken(format!(
+                        "pepper {} is retired",
+                        stored.key_id
+                    )));
+                }
+                previous
+            }
+            _ => {
+                return Err(CaptchaError::InvalidToken(format!(
+                    "unknown pepper {}",
+                    stored.key_id
+                )))
+            }
+        };
+
+        if constant_time_eq(&answer_hash(&pepper.secret, id, answer), &stored.hash) {
+            Ok(())
+        } else {
+            Err(CaptchaError::AnswerMismatch)
+        }
+    }
+}
+
+fn answer_hash(pepper: &[u8], id: &ChallengeId, answer: &str) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(ANSWER_DOMAIN);
+    hasher.update((pepper.len() as u64).to_be_bytes());
+    hasher.update(pepper);
+    hasher.update(id.0);
+    hasher.update(normalize_answer(answer).as_bytes());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_verifies_answers_across_rotation() {
+        let id = ChallengeId::from_seed(b"seed");
+        let mut ring = PepperRing::new(1, b"pepper 1");
+        let stored = ring.hash(&id, "AbCd");
+        assert_eq!(stored.key_id, 1);
+        assert_ne!(stored, ring.hash(&ChallengeId::from_seed(b"other"), "AbCd"));
+        assert_eq!(AnswerHash::from_bytes(&stored.to_bytes()).unwrap(), stored);
+        assert!(ring.verify(&stored, &id, " abcd ", 0).is_ok());
+        assert_eq!(
+            ring.verify(&stored, &id, "abce", 0),
+            Err(CaptchaError::AnswerMismatch)
+        );
+
+        ring.rotate(2, b"pepper 2", 1000, 60_000);
+        assert_eq!(ring.current_key_id(), 2);
+        assert_eq!(ring.hash(&id, "abcd").key_id, 2);
+        assert_ne!(ring.hash(&id, "abcd").hash, stored.hash);
+        assert!(ring.verify(&stored, &id, "abcd", 61_000).is_ok());
+        assert!(matches!(
+            ring.verify(&stored, &id, "abcd", 61_001),
+            Err(CaptchaError::InvalidToken(_))
+        ));
+
+        ring.rotate(3, b"pepper 3", 2000, 60_000);
+        assert!(matches!(
+            ring.verify(&stored, &id, "abcd", 2000),
+            Err(CaptchaError::InvalidToken(_))
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use crate::{Captcha, CaptchaBuilder, CaptchaError, CaptchaGenerator};
+
+impl CaptchaBuilder {
+    /// Generate a [`Captcha`] like [`CaptchaBuilder::try_generate`] on tokio's blocking thread pool,
+    /// so that async handlers don't block the executor with the CPU-bound rendering.
+    pub async fn generate_async(
+        &self,
+        seed: Vec<u8>,
+        text: Option<String>,
+    ) -> Result<Captcha, CaptchaError> {
+        let builder = self.clone();
+        tokio::task::spawn_blocking(move || builder.try_generate(&seed, text))
+            .await
+            .map_err(|err| CaptchaError::Internal(err.to_string()))?
+    }
+}
+
+impl CaptchaGenerator {
+    /// Generate a [`Captcha`] like [`CaptchaGenerator::try_generate`] on tokio's blocking thread pool.
+    pub async fn generate_async(
+        self: &Arc<Self>,
+        seed: Vec<u8>,
+        text: Option<String>,
+    ) -> Result<Captcha, CaptchaError> {
+        let generator = self.clone();
+        tokio::task::spawn_blocking(move || generator.try_generate(&seed, text))
+            .await
+            .map_err(|err| CaptchaError::Internal(err.to_string()))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_generates_captcha_async() {
+        let builder = CaptchaBuilder::new().complexity(2);
+        let captcha = builder
+            .generate_async(b"seed".to_vec(), None)
+            .await
+            .unwrap();
+        assert_eq!(captcha.image(), builder.generate(b"seed", None).image());
+
+        let generator = Arc::new(builder.clone().build());
+        let captcha = generator
+            .generate_async(b"seed".to_vec(), None)
+            .await
+            .unwrap();
+        assert_eq!(captcha.image(), builder.generate(b"seed", None).image());
+    }
+}
@@ -0,0 +1,158 @@
+//! Measures the generation cost of a configuration, so canister developers can pick settings
+//! that fit their cycle budget. Natively the cost is the wall time in nanoseconds; in a canister
+//! (`wasm32` with the `ic` feature) it is the number of instructions from the
+//! `ic0.performance_counter` system API, as returned by `ic_cdk::api::instruction_counter`.
+//!
+//! ```rust
+//! use ic_captcha::{bench, CaptchaBuilder};
+//!
+//! let report = bench::measure(&CaptchaBuilder::new().complexity(8), 3);
+//! println!("{:?} per captcha: {}", report.unit, report.generate.mean);
+//! ```
+
+#[cfg(feature = "jpeg")]
+use crate::OutputFormat;
+use crate::{CaptchaBuilder, CaptchaError};
+
+/// The unit of the measured costs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostUnit {
+    /// Wall time in nanoseconds.
+    Nanos,
+    /// Instructions executed by a canister.
+    Instructions,
+}
+
+/// The statistics of a measured stage over the runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CostStats {
+    pub min: u64,
+    pub max: u64,
+    pub mean: u64,
+}
+
+/// The measured cost of a configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchReport {
+    /// The unit of the costs.
+    pub unit: CostUnit,
+    /// The number of measured captchas.
+    pub runs: u32,
+    /// The cost of generating a captcha.
+    pub generate: CostStats,
+    /// The cost of encoding a captcha in JPEG with quality 30.
+    #[cfg(feature = "jpeg")]
+    pub encode: CostStats,
+    /// The mean size of the encoded images in bytes.
+    #[cfg(feature = "jpeg")]
+    pub encoded_bytes: u64,
+}
+
+/// Measures the cost of generating `runs` captchas with the builder, at least one.
+/// The captchas are generated with [`CaptchaBuilder::try_generate`], so the size limits
+/// and the work budget of the builder are enforced.
+pub fn try_measure(builder: &CaptchaBuilder, runs: u32) -> Result<BenchReport, CaptchaError> {
+    let runs = runs.max(1);
+    let mut generate = Vec::with_capacity(runs as usize);
+    #[cfg(feature = "jpeg")]
+    let (mut encode, mut encoded_bytes) = (Vec::with_capacity(runs as usize), 0u64);
+    for i in 0..runs {
+        let seed = [b"ic-captcha:bench".as_slice(), &i.to_be_bytes()].concat();
+        let start = counter();
+        let captcha = builder.try_generate(&seed, None)?;
+        generate.push(counter() - start);
+
+        #[cfg(feature = "jpeg")]
+        {
+            let mut buf = Vec::new();
+            let start = counter();
+            captcha.encode_into(&mut buf, OutputFormat::Jpeg, 30)?;
+            encode.push(counter() - start);
+            encoded_bytes += buf.len() as u64;
+        }
+        #[cfg(not(feature = "jpeg"))]
+        drop(captcha);
+    }
+
+    Ok(BenchReport {
+        unit: UNIT,
+        runs,
+        generate: stats(&generate),
+        #[cfg(feature = "jpeg")]
+        encode: stats(&encode),
+        #[cfg(feature = "jpeg")]
+        encoded_bytes: encoded_bytes / runs as u64,
+    })
+}
+
+/// Measures the cost like [`try_measure`], but neither the size limits nor the work budget
+/// are enforced, as [`CaptchaBuilder::generate`].
+pub fn measure(builder: &CaptchaBuilder, runs: u32) -> BenchReport {
+    let builder = builder
+        .clone()
+        .work_budget(None)
+        .max_width(u32::MAX)
+        .max_height(u32::MAX)
+        .max_pixels(u64::MAX);
+    try_measure(&builder, runs).expect("generation without limits is infallible")
+}
+
+fn stats(costs: &[u64]) -> CostStats {
+    CostStats {
+        min: costs.iter().copied().min().unwrap_or_default(),
+        max: costs.iter().copied().max().unwrap_or_default(),
+        mean: costs.iter().sum::<u64>() / costs.len().max(1) as u64,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const UNIT: CostUnit = CostUnit::Nanos;
+
+// Return the nanoseconds since the first call
+#[cfg(not(target_arch = "wasm32"))]
+fn counter() -> u64 {
+    use std::{sync::OnceLock, time::Instant};
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+#[cfg(target_arch = "wasm32")]
+const UNIT: CostUnit = CostUnit::Instructions;
+
+// Return the instructions executed in the current message, the same as
+// `ic_cdk::api::instruction_counter`
+#[cfg(target_arch = "wasm32")]
+fn counter() -> u64 {
+    #[link(wasm_import_module = "ic0")]
+    extern "C" {
+        fn performance_counter(counter_type: u32) -> u64;
+    }
+    unsafe { performance_counter(0) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_measures_generation_cost() {
+        let report = measure(&CaptchaBuilder::new().complexity(1), 2);
+        assert_eq!(report.unit, CostUnit::Nanos);
+        assert_eq!(report.runs, 2);
+        assert!(report.generate.min <= report.generate.mean);
+        assert!(report.generate.mean <= report.generate.max);
+        assert!(report.generate.max > 0);
+        #[cfg(feature = "jpeg")]
+        assert!(report.encoded_bytes > 0);
+
+        let report = measure(
+            &CaptchaBuilder::new().width(100_000).work_budget(Some(1)),
+            1,
+        );
+        assert_eq!(report.runs, 1);
+        assert!(matches!(
+            try_measure(&CaptchaBuilder::new().work_budget(Some(1)), 1),
+            Err(CaptchaError::BudgetExceeded { .. })
+        ));
+    }
+}
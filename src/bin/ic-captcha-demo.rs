@@ -0,0 +1,129 @@
+//! A preview server rendering captchas with the options of the sliders on its page.
+//!
+//! ```sh
+//! cargo run --features demo --bin ic-captcha-demo -- 127.0.0.1:8080
+//! ```
+
+use ic_captcha::{BundledFont, CaptchaBuilder, OutputFormat};
+use tiny_http::{Header, Request, Response, Server};
+
+const INDEX: &str = include_str!("demo.html");
+
+fn main() {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let server = Server::http(&addr).expect("failed to bind the address");
+    println!("ic-captcha demo listening on http://{}", addr);
+    for req in server.incoming_requests() {
+        handle(req);
+    }
+}
+
+fn handle(req: Request) {
+    let url = req.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let res = match path {
+        "/" => Response::from_string(INDEX.replace("{{FONTS}}", &font_options()))
+            .with_header(header("content-type", "text/html; charset=utf-8")),
+        "/captcha" => match render(query) {
+            Ok((text, image)) => Response::from_data(image)
+                .with_header(header("content-type", OutputFormat::Jpeg.mime_type()))
+                .with_header(header("cache-control", "no-store"))
+                .with_header(header("x-captcha-text", &text)),
+            Err(err) => Response::from_string(err).with_status_code(400),
+        },
+        _ => Response::from_string("not found").with_status_code(404),
+    };
+    if let Err(err) = req.respond(res) {
+        eprintln!("failed to respond: {}", err);
+    }
+}
+
+// Render the captcha with the options in the query string
+fn render(query: &str) -> Result<(String, Vec<u8>), String> {
+    let mut builder = CaptchaBuilder::new();
+    let mut seed = String::new();
+    let mut text = None;
+    let mut quality = 30;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value);
+        builder = match name {
+            "seed" => {
+                seed = value;
+                builder
+            }
+            "text" => {
+                text = Some(value).filter(|text| !text.is_empty());
+                builder
+            }
+            "quality" => {
+                quality = parse(name, &value)?;
+                builder
+            }
+            "length" => builder.length(parse(name, &value)?),
+            "width" => builder.width(parse(name, &value)?),
+            "height" => builder.height(parse(name, &value)?),
+            "mode" => builder.mode(parse(name, &value)?),
+            "complexity" => builder.complexity(parse(name, &value)?),
+            "letter_spacing" => {
+                let spacing: u32 = parse(name, &value)?;
+                builder.letter_spacing(Some(spacing).filter(|spacing| *spacing > 0))
+            }
+            "font" => builder.bundled_font(value.parse().map_err(|err| format!("{}", err))?),
+            _ => builder,
+        };
+    }
+
+    let captcha = builder
+        .try_generate(seed.as_bytes(), text)
+        .map_err(|err| err.to_string())?;
+    let mut image = Vec::new();
+    captcha
+        .encode_into(&mut image, OutputFormat::Jpeg, quality)
+        .map_err(|err| err.to_string())?;
+    Ok((captcha.text(), image))
+}
+
+fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {}: {:?}", name, value))
+}
+
+fn font_options() -> String {
+    BundledFont::ALL
+        .iter()
+        .map(|font| format!("<option>{}</option>", font))
+        .collect()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("invalid header")
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn hex(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
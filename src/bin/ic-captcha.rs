@@ -0,0 +1,187 @@
+use clap::{Args, Parser, Subcommand};
+use ic_captcha::{CaptchaBuilder, ChallengePool, ChallengeToken, OutputFormat};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// Generate captchas and verify answers, for tuning visuals and test fixtures.
+#[derive(Parser)]
+#[command(name = "ic-captcha", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a captcha image for every seed and print the file names with the answers
+    Generate {
+        /// The random seeds of the captchas
+        #[arg(required = true)]
+        seeds: Vec<String>,
+        /// The text of the captchas instead of a random text
+        #[arg(long)]
+        text: Option<String>,
+        /// The output directory
+        #[arg(short, long, default_value = ".")]
+        out: PathBuf,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Write N challenges derived from a master seed into a directory with a manifest.csv
+    /// of the file names, answers and challenge tokens
+    Batch {
+        /// The master seed of the challenges
+        master_seed: String,
+        /// The number of challenges
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+        /// The secret key of the challenge tokens
+        #[arg(long)]
+        key: String,
+        /// The expiration time of the challenge tokens in unix milliseconds
+        #[arg(long, default_value_t = u64::MAX)]
+        expire_at: u64,
+        /// The output directory
+        #[arg(short, long)]
+        out: PathBuf,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Check an answer against a challenge token
+    Verify {
+        /// The challenge token
+        token: String,
+        /// The answer
+        answer: String,
+        /// The secret key of the challenge tokens
+        #[arg(long)]
+        key: String,
+        /// The current time in unix milliseconds, 0 to skip the expiration check
+        #[arg(long, default_value_t = 0)]
+        now_ms: u64,
+    },
+}
+
+#[derive(Args)]
+struct Options {
+    /// The length of the text
+    #[arg(long, default_value_t = 4)]
+    length: u8,
+    /// The width of the image
+    #[arg(long, default_value_t = 140)]
+    width: u32,
+    /// The height of the image
+    #[arg(long, default_value_t = 40)]
+    height: u32,
+    /// The color mode: 0 dark on light, 1 colorful on light, 2 colorful on dark
+    #[arg(long, default_value_t = 1)]
+    mode: u8,
+    /// The complexity of the noise, 1-10
+    #[arg(long, default_value_t = 5)]
+    complexity: u32,
+    /// The JPEG quality, 10-80
+    #[arg(long, default_value_t = 30)]
+    quality: u8,
+}
+
+impl Options {
+    fn builder(&self) -> CaptchaBuilder {
+        CaptchaBuilder::new()
+            .length(self.length)
+            .width(self.width)
+            .height(self.height)
+            .mode(self.mode)
+            .complexity(self.complexity)
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    match command {
+        Command::Generate {
+            seeds,
+            text,
+            out,
+            options,
+        } => {
+            fs::create_dir_all(&out)?;
+            let builder = options.builder();
+            for (i, seed) in seeds.iter().enumerate() {
+                let captcha = builder.try_generate(seed.as_bytes(), text.clone())?;
+                let path = out.join(format!("captcha-{}.jpeg", i));
+                write_image(&path, &captcha, options.quality)?;
+                println!("{}\t{}", path.display(), captcha.text());
+            }
+        }
+        Command::Batch {
+            master_seed,
+            count,
+            key,
+            expire_at,
+            out,
+            options,
+        } => {
+            fs::create_dir_all(&out)?;
+            // Issue at time 0 so that the tokens expire at `expire_at`
+            let pool = ChallengePool::new(
+                options.builder().build(),
+                key.as_bytes(),
+                master_seed.as_bytes(),
+                0,
+                expire_at,
+            );
+            let mut manifest = fs::File::create(out.join("manifest.csv"))?;
+            writeln!(manifest, "file,id,answer,token")?;
+            for i in 0..count {
+                let challenge = pool.issue(0);
+                let file = format!("captcha-{}.jpeg", i);
+                write_image(&out.join(&file), &challenge.captcha, options.quality)?;
+                writeln!(
+                    manifest,
+                    "{},{},{},{}",
+                    file,
+                    challenge.captcha.id(),
+                    challenge.captcha.text(),
+                    challenge.token
+                )?;
+            }
+            println!("{} challenges written to {}", count, out.display());
+        }
+        Command::Verify {
+            token,
+            answer,
+            key,
+            now_ms,
+        } => {
+            let token: ChallengeToken = token.parse()?;
+            if let Err(err) = token.verify(key.as_bytes(), &answer, now_ms) {
+                println!("invalid: {}", err);
+                return Ok(ExitCode::FAILURE);
+            }
+            println!("valid");
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn write_image(
+    path: &Path,
+    captcha: &ic_captcha::Captcha,
+    quality: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    captcha.save_with(path, OutputFormat::Jpeg, quality)?;
+    Ok(())
+}
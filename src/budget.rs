@@ -0,0 +1,53 @@
+use crate::CaptchaError;
+
+// An approximate work counter checked between the pipeline stages.
+// One step is roughly one pixel operation.
+pub(crate) struct WorkBudget {
+    limit: Option<u64>,
+    spent: u64,
+}
+
+impl WorkBudget {
+    pub(crate) fn new(limit: Option<u64>) -> Self {
+        WorkBudget { limit, spent: 0 }
+    }
+
+    // Account for the next stage, returns an error if it would exceed the budget
+    pub(crate) fn spend(&mut self, steps: u64) -> Result<(), CaptchaError> {
+        let required = self.spent.saturating_add(steps);
+        if let Some(budget) = self.limit {
+            if required > budget {
+                return Err(CaptchaError::BudgetExceeded { budget, required });
+            }
+        }
+        self.spent = required;
+        Ok(())
+    }
+}
+
+// Estimated steps of the pipeline stages for an image with the given dimensions
+pub(crate) fn background_steps(width: u32, height: u32) -> u64 {
+    width as u64 * height as u64
+}
+
+pub(crate) fn characters_steps(width: u32, height: u32) -> u64 {
+    width as u64 * height as u64
+}
+
+pub(crate) fn interference_steps(width: u32, height: u32) -> u64 {
+    4 * (width as u64 + height as u64)
+}
+
+pub(crate) fn negative_space_steps(width: u32, height: u32) -> u64 {
+    2 * width as u64 * height as u64
+}
+
+#[cfg(feature = "jpeg")]
+pub(crate) fn quality_jitter_steps(width: u32, height: u32) -> u64 {
+    8 * width as u64 * height as u64
+}
+
+#[cfg(feature = "noise")]
+pub(crate) fn noise_steps(width: u32, height: u32) -> u64 {
+    4 * width as u64 * height as u64
+}
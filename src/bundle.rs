@@ -0,0 +1,172 @@
+use crate::{CaptchaError, Challenge, ChallengeToken, OutputFormat};
+
+/// A challenge with its encoded media as one unit, so it can be cached, stored in stable
+/// memory, or returned over any transport. With the `serde` feature it implements serde's
+/// `Serialize` and `Deserialize` with the media as byte strings, and with the `cbor` feature
+/// it is encoded in CBOR with [`ChallengeBundle::to_cbor`]. The fields match the record below,
+/// so it maps one to one to the candid type declared by a canister, and with the `candid`
+/// feature it derives `CandidType` of that record:
+///
+/// ```candid
+/// type ChallengeBundle = record {
+///   id : text;
+///   token : blob;
+///   expire_at : nat64;
+///   image : blob;
+///   image_type : text;
+///   audio : opt blob;
+///   audio_type : opt text;
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+pub struct ChallengeBundle {
+    /// The id of the challenge, see [`crate::ChallengeId`].
+    pub id: String,
+    /// The challenge token, serialized as its bytes.
+    pub token: ChallengeToken,
+    /// The expiration time of the challenge in unix milliseconds.
+    pub expire_at: u64,
+    /// The encoded captcha image.
+    #[cfg_attr(feature = "serde", serde(with = "serde_bytes"))]
+    pub image: Vec<u8>,
+    /// The MIME type of the image.
+    pub image_type: String,
+    /// The encoded audio of the same answer, if any.
+    #[cfg_attr(feature = "serde", serde(with = "opt_bytes"))]
+    pub audio: Option<Vec<u8>>,
+    /// The MIME type of the audio.
+    pub audio_type: Option<String>,
+}
+
+impl ChallengeBundle {
+    /// Returns the bundle of a challenge with its captcha image encoded in the given format.
+    pub fn new(
+        challenge: &Challenge,
+        format: OutputFormat,
+        quality: u8,
+    ) -> Result<Self, CaptchaError> {
+        let mut image = Vec::new();
+        challenge.captcha.encode_into(&mut image, format, quality)?;
+        Ok(ChallengeBundle {
+            id: challenge.token.id().to_string(),
+            token: challenge.token.clone(),
+            expire_at: challenge.token.expire_at,
+            image,
+            image_type: format.mime_type().to_string(),
+            audio: None,
+            audio_type: None,
+        })
+    }
+
+    /// Set the encoded audio of the same answer and its MIME type.
+    pub fn audio(mut self, audio: Vec<u8>, mime_type: impl Into<String>) -> Self {
+        self.audio = Some(audio);
+        self.audio_type = Some(mime_type.into());
+        self
+    }
+
+    /// Returns the bundle encoded in CBOR.
+    #[cfg(feature = "cbor")]
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(self, &mut buf).expect("encoding into a vec is infallible");
+        buf
+    }
+
+    /// Decodes a bundle from CBOR.
+    #[cfg(feature = "cbor")]
+    pub fn from_cbor(data: &[u8]) -> Result<Self, CaptchaError> {
+        ciborium::from_reader(data).map_err(|err| CaptchaError::Decode(err.to_string()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ChallengeToken {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ChallengeToken {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = serde_bytes::ByteBuf::deserialize(deserializer)?;
+        ChallengeToken::from_bytes(&data).map_err(serde::de::Error::custom)
+    }
+}
+
+// serde_bytes of an optional byte string. The candid derive takes fields with serde_bytes for
+// blobs and its decoder only accepts serde's own option visitor, so the option is unwrapped here.
+#[cfg(feature = "serde")]
+mod opt_bytes {
+    use serde::Deserialize;
+    use serde_bytes::ByteBuf;
+
+    pub(super) use serde_bytes::serialize;
+
+    pub(super) fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Ok(Option::<ByteBuf>::deserialize(deserializer)?.map(ByteBuf::into_vec))
+    }
+}
+
+// The token is a blob in candid, like in serde
+#[cfg(feature = "candid")]
+impl candid::CandidType for ChallengeToken {
+    fn _ty() -> candid::types::Type {
+        candid::types::TypeInner::Vec(candid::types::TypeInner::Nat8.into()).into()
+    }
+
+    fn idl_serialize<S: candid::types::Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        serializer.serialize_blob(&self.to_bytes())
+    }
+}
+
+#[cfg(all(test, feature = "jpeg"))]
+mod tests {
+    use super::*;
+    use crate::{CaptchaBuilder, ChallengePool};
+
+    #[test]
+    fn it_bundles_challenges() {
+        let generator = CaptchaBuilder::new().complexity(1).build();
+        let pool = ChallengePool::new(generator, b"secret", b"master seed", 0, 60_000);
+        let challenge = pool.issue(1000);
+        let bundle = ChallengeBundle::new(&challenge, OutputFormat::Jpeg, 30)
+            .unwrap()
+            .audio(vec![1, 2, 3], "audio/wav");
+        assert_eq!(bundle.id, challenge.captcha.id().to_string());
+        assert_eq!(bundle.expire_at, 61_000);
+        assert_eq!(bundle.image_type, "image/jpeg");
+        assert!(bundle.image.starts_with(&[0xff, 0xd8]));
+
+        #[cfg(feature = "cbor")]
+        {
+            let data = bundle.to_cbor();
+            let decoded = ChallengeBundle::from_cbor(&data).unwrap();
+            assert_eq!(decoded, bundle);
+            assert!(pool
+                .verify(&decoded.token, &challenge.captcha.text(), 2000)
+                .is_ok());
+            assert!(matches!(
+                ChallengeBundle::from_cbor(&data[..data.len() - 1]),
+                Err(CaptchaError::Decode(_))
+            ));
+        }
+
+        #[cfg(feature = "candid")]
+        {
+            use candid::{CandidType, Decode, Encode};
+
+            let data = Encode!(&bundle).unwrap();
+            assert_eq!(Decode!(&data, ChallengeBundle).unwrap(), bundle);
+            let ty = ChallengeBundle::ty().to_string();
+            for field in ["token : blob", "image : blob", "audio : opt blob"] {
+                assert!(ty.contains(field), "{} in {}", field, ty);
+            }
+        }
+    }
+}
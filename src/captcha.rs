@@ -1,15 +1,34 @@
-use base64::{engine::general_purpose, Engine};
-use image::{ImageBuffer, ImageOutputFormat::Jpeg, Rgb};
-use imageproc::{
-    drawing::{draw_cubic_bezier_curve_mut, draw_hollow_ellipse_mut, draw_text_mut, text_size},
-    noise::{gaussian_noise_mut, salt_and_pepper_noise_mut},
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont};
+#[cfg(feature = "jpeg")]
+use image::codecs::jpeg::{JpegDecoder, JpegEncoder};
+#[cfg(feature = "jpeg")]
+use image::ImageDecoder;
+use image::{ImageBuffer, Luma, Pixel, Rgb};
+use std::sync::Arc;
+
+#[cfg(feature = "animation")]
+use crate::animation::FrameSource;
+use crate::draw::{
+    draw_cubic_bezier_curve_mut, draw_filled_rotated_rect_mut, draw_hollow_ellipse_mut,
+    draw_line_segment_mut, draw_stamp_mut, STAMP_ADVANCE, STAMP_HEIGHT,
+};
+use crate::font::VariableFace;
+use crate::glyph::{scale_index, Glyph, GlyphCache, SCALES};
+use crate::lazy::LazyRender;
+#[cfg(feature = "noise")]
+use crate::noise::{
+    gaussian_noise_mut, salt_and_pepper_noise_mut, sparse_salt_and_pepper_noise_mut,
+    tiled_gaussian_noise_mut,
 };
-use rusttype::{Font, Scale};
-use std::io::Cursor;
+#[cfg(feature = "svg")]
+use crate::svg::VectorShape;
+use crate::{Border, CanvasShape, CaptchaBuilder, ChallengeId, Theme};
+#[cfg(feature = "jpeg")]
+use crate::{CaptchaError, OutputFormat};
 
 // Define the verification code characters.
 // Remove 0, O, I, L and other easily confusing letters
-const BASIC_CHAR: [char; 54] = [
+pub(crate) const BASIC_CHAR: [char; 54] = [
     '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'M',
     'N', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'a', 'b', 'c', 'd', 'e', 'f', 'g',
     'h', 'j', 'k', 'm', 'n', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
@@ -34,100 +53,879 @@ const DARK_BASIC_COLOR: [[u8; 3]; 5] = [
 // Define background color
 const LIGHT: [u8; 3] = [248, 248, 248];
 const DARK: [u8; 3] = [18, 18, 18];
+// The range of gray levels of the text and interference in grayscale mode,
+// the half of the gray scale away from the background
+const GRAY_RANGE: u32 = 110;
+
+// The percentage of the tint color mixed into the background color of a character tile
+const TILE_TINT: u32 = 25;
+
+// The canvas area per stroke of the negative space pattern, in pixels
+const NEGATIVE_STROKE_AREA: u64 = 3;
+
+// The maximum fraction of a glyph mask covered by the interference bars
+const BAR_OCCLUSION_CAP: f32 = 0.3;
+
+// The minimum luma difference between a visible character pixel and the background
+const MIN_CONTRAST: i32 = 48;
 
-// Define font size
-const SCALE_SM: Scale = Scale { x: 38.0, y: 35.0 };
-const SCALE_MD: Scale = Scale { x: 45.0, y: 42.0 };
-const SCALE_LG: Scale = Scale { x: 53.0, y: 50.0 };
+/// The interference drawn over the characters, see [`crate::CaptchaBuilder::interference`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Interference {
+    /// Alternating bezier curves and hollow ellipses.
+    #[default]
+    Curves,
+    /// Barcode-like groups of vertical or slanted bars at varying spacing and opacity
+    /// crossing the text band, covering at most 30% of every character.
+    Bars,
+}
 
 /// A captcha should be created using the [`CaptchaBuilder`].
+/// The default value is an empty captcha that can be passed to
+/// [`CaptchaBuilder::generate_into`] to reuse its allocations.
+#[derive(Default)]
 pub struct Captcha {
     mode: u8, // 0: dark on light, 1: colorful on light, 2: colorful on dark
+    // Rendered with gray levels only, see `CaptchaBuilder::grayscale`
+    grayscale: bool,
+    // The background is transparent in the RGBA outputs, see `CaptchaBuilder::transparent`
+    transparent: bool,
+    id: ChallengeId,
     chars: Vec<char>,
+    // The answer and the prompt if the answer differs from the text, see `Captcha::answer`
+    answer: Option<(String, Option<String>)>,
+    // The color each character was drawn with
+    colors: Vec<Rgb<u8>>,
     image: ImageBuffer<Rgb<u8>, Vec<u8>>,
+    // The colors replacing the palettes of the mode
+    theme: Option<Arc<Theme>>,
+    // The shape of the canvas, pixels outside of it are filled with the background color
+    shape: CanvasShape,
+    // The vector layout of the glyphs and the interference, see `Captcha::to_svg`
+    #[cfg(feature = "svg")]
+    vectors: Vec<VectorShape>,
+    // The inputs of the render, to render the other frames of an animation
+    #[cfg(feature = "animation")]
+    frame_source: Option<Arc<FrameSource>>,
+    // The deferred render of a captcha from `CaptchaBuilder::generate_lazy`
+    lazy: Option<Box<LazyRender>>,
 }
 
 impl Captcha {
+    /// Returns the id derived from the seed of the captcha, see [`ChallengeId`].
+    pub fn id(&self) -> ChallengeId {
+        self.id
+    }
+
+    // Set the id derived from the seed of the captcha
+    pub(crate) fn set_id(&mut self, id: ChallengeId) {
+        self.id = id;
+    }
+
     /// Retures the verification code string
     pub fn text(&self) -> String {
         self.chars.iter().collect()
     }
 
+    /// Returns the expected answer of the captcha: the verification code string, the digits
+    /// of the spelled numbers, see [`crate::CaptchaBuilder::spelled_numbers`], or for the
+    /// pictogram captchas the names of the pictograms or the position of the one named by
+    /// [`Captcha::prompt`], see [`crate::PictogramAnswer`].
+    pub fn answer(&self) -> String {
+        match &self.answer {
+            Some((answer, _)) => answer.clone(),
+            None => self.text(),
+        }
+    }
+
+    /// Returns the name of the pictogram whose position is the answer, shown to the user
+    /// with the image, e.g. "Which position is the cat?". None for other captchas.
+    pub fn prompt(&self) -> Option<&str> {
+        self.answer
+            .as_ref()
+            .and_then(|(_, prompt)| prompt.as_deref())
+    }
+
+    // Set the answer and the prompt if the answer differs from the text
+    pub(crate) fn set_answer(&mut self, answer: Option<(String, Option<String>)>) {
+        self.answer = answer;
+    }
+
     /// Returns the verification code image in base64 format
     /// params `compression` - specify image quality, range 10-80, default is 30
+    /// It panics if the encoding fails, use [`Captcha::try_to_base64`] in canisters
+    /// where a panic traps the whole call.
+    #[cfg(feature = "jpeg")]
     pub fn to_base64(&self, compression: u8) -> String {
-        let compression = if compression > 80 {
-            80
-        } else if compression < 10 {
-            30
-        } else {
-            compression
-        };
-        let mut buf = Cursor::new(Vec::new());
-        self.image.write_to(&mut buf, Jpeg(compression)).unwrap();
-        let res_base64 = general_purpose::STANDARD.encode(buf.into_inner());
-        format!("data:image/jpeg;base64,{}", res_base64)
+        self.try_to_base64(compression)
+            .expect("failed to encode the captcha image")
+    }
+
+    /// Returns the verification code image in base64 format like [`Captcha::to_base64`],
+    /// but returns an error if the encoding fails.
+    /// params `compression` - specify image quality, range 10-80, default is 30
+    #[cfg(feature = "jpeg")]
+    pub fn try_to_base64(&self, compression: u8) -> Result<String, CaptchaError> {
+        let mut buf = String::new();
+        self.to_base64_into(compression, &mut buf)?;
+        Ok(buf)
     }
 
-    // Create a new captcha instance with the given text, width, height and dark mode
-    pub(crate) fn new(text: String, width: u32, height: u32, mode: u8) -> Self {
+    /// Appends the verification code image in base64 format to the given buffer.
+    /// The image is encoded and base64 encoded in one pass without intermediate buffers,
+    /// the buffer can be cleared and reused across generations. Together with
+    /// [`crate::CaptchaBuilder::generate_into`] reusing the image buffer, a hot loop
+    /// allocates nothing but the encoder state once the buffers have grown.
+    /// params `compression` - specify image quality, range 10-80, default is 30
+    /// See [`Captcha::to_data_url_into`] for the other formats.
+    #[cfg(feature = "jpeg")]
+    #[doc(alias = "encode_base64_into")]
+    pub fn to_base64_into(&self, compression: u8, buf: &mut String) -> Result<(), CaptchaError> {
+        self.to_data_url_into(OutputFormat::Jpeg, compression, buf)
+    }
+
+    /// Returns each character of the answer with the color it was drawn with, in order,
+    /// e.g. for color-based prompts ("type the red characters") or contrast audits.
+    pub fn char_colors(&self) -> Vec<(char, Rgb<u8>)> {
+        self.chars
+            .iter()
+            .copied()
+            .zip(self.rendered().colors.iter().copied())
+            .collect()
+    }
+
+    /// Stamps a short label, e.g. a challenge id or the issue time, in the bottom-right corner
+    /// of the image with a small low-contrast 3x5 font, so screenshots in support tickets
+    /// can be correlated with server logs. The label is not part of the answer.
+    /// Only digits, the letters of Crockford's base32 (see [`crate::ChallengeId`]), `-`, `:`
+    /// and `.` are drawn, other characters are left blank.
+    /// The label of a lazy captcha not yet rendered is stamped when it is rendered.
+    pub fn stamp(&mut self, label: &str) {
+        if let Some(lazy) = self.lazy.as_mut().filter(|lazy| !lazy.is_rendered()) {
+            lazy.stamp = Some(label.to_string());
+            return;
+        }
+        self.render();
+        let len = label.chars().count() as i32;
+        let x = self.image.width() as i32 - 2 - len * STAMP_ADVANCE + 1;
+        let y = self.image.height() as i32 - 2 - STAMP_HEIGHT;
+        draw_stamp_mut(&mut self.image, label, x, y);
+    }
+
+    /// Returns the verification code image, a lazy captcha is rendered on the first call.
+    pub fn image(&self) -> &ImageBuffer<Rgb<u8>, Vec<u8>> {
+        &self.rendered().image
+    }
+
+    /// Renders the image of a captcha from [`crate::CaptchaBuilder::generate_lazy`] if it is
+    /// not yet rendered, so that the cost is paid at a chosen point rather than on the first
+    /// access. Other captchas are always rendered, it does nothing for them.
+    pub fn render(&mut self) {
+        if let Some(lazy) = self.lazy.take() {
+            let rendered = lazy.into_rendered();
+            self.mode = rendered.mode;
+            self.grayscale = rendered.grayscale;
+            self.transparent = rendered.transparent;
+            self.shape = rendered.shape;
+            #[cfg(feature = "svg")]
+            {
+                self.vectors = rendered.vectors;
+            }
+            #[cfg(feature = "animation")]
+            {
+                self.frame_source = rendered.frame_source;
+            }
+            self.colors = rendered.colors;
+            self.image = rendered.image;
+        }
+    }
+
+    /// Returns false if the captcha is lazy and its image is not yet rendered.
+    pub fn is_rendered(&self) -> bool {
+        self.lazy.as_ref().is_none_or(|lazy| lazy.is_rendered())
+    }
+
+    pub(crate) fn lazy(text: &str, id: ChallengeId, lazy: LazyRender) -> Self {
         Captcha {
+            id,
             chars: text.chars().collect(),
-            image: ImageBuffer::from_fn(width, height, |_, _| {
-                if mode > 1 {
-                    return image::Rgb(DARK);
+            lazy: Some(Box::new(lazy)),
+            ..Default::default()
+        }
+    }
+
+    // Return the rendered captcha, itself unless it is lazy
+    fn rendered(&self) -> &Captcha {
+        match &self.lazy {
+            Some(lazy) => lazy.get(),
+            None => self,
+        }
+    }
+
+    // Create an empty captcha that will reuse the given buffer for its image
+    pub(crate) fn with_buffer(buf: Vec<u8>) -> Self {
+        Captcha {
+            mode: 0,
+            grayscale: false,
+            transparent: false,
+            id: ChallengeId::default(),
+            chars: Vec::new(),
+            answer: None,
+            colors: Vec::new(),
+            image: ImageBuffer::from_raw(0, 0, buf).unwrap_or_default(),
+            theme: None,
+            shape: CanvasShape::Rectangle,
+            #[cfg(feature = "svg")]
+            vectors: Vec::new(),
+            #[cfg(feature = "animation")]
+            frame_source: None,
+            lazy: None,
+        }
+    }
+
+    // Consume the captcha and return its image buffer
+    pub(crate) fn into_buffer(mut self) -> Vec<u8> {
+        if self.lazy.is_some() && self.is_rendered() {
+            self.render();
+        }
+        self.image.into_raw()
+    }
+
+    // Reset the captcha with the given text, width, height and dark mode, reusing the allocations
+    pub(crate) fn reset(&mut self, text: &str, width: u32, height: u32, mode: u8) {
+        self.chars.clear();
+        self.chars.extend(text.chars());
+        self.reset_image(width, height, mode);
+    }
+
+    // Reset the captcha with random text, width, height and dark mode, reusing the allocations
+    pub(crate) fn reset_random<R>(
+        &mut self,
+        get_rnd: &mut R,
+        builder: &CaptchaBuilder,
+        width: u32,
+        height: u32,
+        mode: u8,
+    ) where
+        R: FnMut(u32) -> u32,
+    {
+        builder.random_code(get_rnd, &mut self.chars);
+        self.reset_image(width, height, mode);
+    }
+
+    // Set the inputs of the render, see `Captcha::frames`
+    #[cfg(feature = "animation")]
+    pub(crate) fn set_frame_source(&mut self, source: Option<Arc<FrameSource>>) {
+        self.frame_source = source;
+    }
+
+    // Returns the inputs of the render, see `Captcha::frames`
+    #[cfg(feature = "animation")]
+    pub(crate) fn frame_source(&self) -> Option<&FrameSource> {
+        self.rendered().frame_source.as_deref()
+    }
+
+    // Set the theme of the next generation, see `CaptchaBuilder::theme`
+    pub(crate) fn set_theme(&mut self, theme: Option<Arc<Theme>>) {
+        self.theme = theme;
+    }
+
+    // Set the grayscale mode of the next generation, see `CaptchaBuilder::grayscale`
+    pub(crate) fn set_grayscale(&mut self, grayscale: bool) {
+        self.grayscale = grayscale;
+    }
+
+    // Set the transparent mode of the next generation, see `CaptchaBuilder::transparent`
+    pub(crate) fn set_transparent(&mut self, transparent: bool) {
+        self.transparent = transparent;
+    }
+
+    /// Returns true if the background of the captcha is transparent in the RGBA outputs,
+    /// see [`crate::CaptchaBuilder::transparent`].
+    pub fn is_transparent(&self) -> bool {
+        self.rendered().transparent
+    }
+
+    /// Returns true if the captcha is rendered with gray levels only,
+    /// see [`crate::CaptchaBuilder::grayscale`].
+    pub fn is_grayscale(&self) -> bool {
+        self.rendered().grayscale
+    }
+
+    /// Returns the verification code image as a single channel image, the luma of every pixel.
+    /// It is lossless for a grayscale captcha, see [`crate::CaptchaBuilder::grayscale`].
+    pub fn luma_image(&self) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let image = self.image();
+        ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            image.get_pixel(x, y).to_luma()
+        })
+    }
+
+    // Replace every pixel with its gray level, the last stage of the grayscale mode
+    pub(crate) fn desaturate(&mut self) {
+        for pixel in self.image.pixels_mut() {
+            *pixel = gray(*pixel);
+        }
+    }
+
+    // Returns the shape of the canvas, see `CaptchaBuilder::shape`
+    pub(crate) fn shape(&self) -> CanvasShape {
+        self.rendered().shape
+    }
+
+    // Fill the pixels outside of the shape with the background color,
+    // and the pixels inside of it along its outline with the border color
+    pub(crate) fn apply_shape(&mut self, shape: CanvasShape, border: Option<Border>) {
+        self.shape = shape;
+        let border = border.filter(|border| border.width > 0);
+        if shape == CanvasShape::Rectangle && border.is_none() {
+            return;
+        }
+        let background = Rgb(self.background());
+        let (width, height) = self.image.dimensions();
+        for (x, y, pixel) in self.image.enumerate_pixels_mut() {
+            if !shape.contains(width, height, x, y) {
+                *pixel = background;
+            } else if let Some(border) = border {
+                if !shape.contains_inset(width, height, x, y, border.width) {
+                    *pixel = border.color;
                 }
-                image::Rgb(LIGHT)
-            }),
-            mode,
+            }
+        }
+    }
+
+    // Fill the image with the background color, reusing the pixel buffer
+    fn reset_image(&mut self, width: u32, height: u32, mode: u8) {
+        self.mode = mode;
+        let background = self.background();
+        let mut buf = std::mem::take(&mut self.image).into_raw();
+        buf.clear();
+        buf.reserve(width as usize * height as usize * 3);
+        for _ in 0..width as usize * height as usize {
+            buf.extend_from_slice(&background);
+        }
+        self.image = ImageBuffer::from_raw(width, height, buf)
+            .expect("buffer length matches the image dimensions");
+        self.shape = CanvasShape::Rectangle;
+        #[cfg(feature = "svg")]
+        self.vectors.clear();
+        self.lazy = None;
+    }
+
+    // Returns the left of the first character slot and the width of every slot.
+    // With a difficulty above 0.5, the slots are squeezed together and kept centered.
+    fn char_slots(&self, difficulty: Option<f32>) -> (i32, i32) {
+        let mut x = self.image.width().saturating_sub(10) as i32 / self.chars.len().max(1) as i32;
+        let mut x0 = 5;
+        if let Some(d) = difficulty {
+            // Keep the squeezed characters centered
+            let squeezed = (x as f32 * (1.0 - 0.6 * (d - 0.5).max(0.0))) as i32;
+            x0 += (x - squeezed) * self.chars.len() as i32 / 2;
+            x = squeezed;
+        }
+        (x0, x)
+    }
+
+    // Returns the layout of the characters. Texts of narrow characters are laid out in equal
+    // slots, see `char_slots`. Pictograms, spelled numbers and texts with wide East Asian
+    // characters are laid out in slots
+    // as wide as the advances of the glyphs measured with the fonts, and the glyphs are
+    // shrunk if they do not fit the width. So are the narrow characters with a letter spacing
+    // when the spaced glyphs do not fit the equal slots.
+    fn char_layout(
+        &self,
+        fonts: &[FontArc],
+        letter_spacing: Option<u32>,
+        large_glyphs: bool,
+        difficulty: Option<f32>,
+    ) -> CharLayout {
+        let si = if large_glyphs {
+            0
+        } else {
+            scale_index(self.chars.len())
+        };
+        let equal_slots = || {
+            let (x0, x) = self.char_slots(difficulty);
+            CharLayout {
+                slots: (0..self.chars.len() as i32)
+                    .map(|i| (x0 + i * x, x))
+                    .collect(),
+                offset: 0,
+                scale: SCALES[si],
+                scale_index: Some(si),
+                spacing: letter_spacing.map(|spacing| spacing.min(i32::MAX as u32) as i32),
+            }
+        };
+        let measured = self.answer.is_some() || self.chars.iter().copied().any(is_wide);
+        if fonts.is_empty() || (!measured && letter_spacing.is_none()) {
+            return equal_slots();
+        }
+
+        let spacing = letter_spacing.unwrap_or(0).min(self.image.width()) as f32;
+        let advances: Vec<f32> = self
+            .chars
+            .iter()
+            .map(|c| {
+                fonts
+                    .iter()
+                    .map(|font| {
+                        let id = font.glyph_id(*c);
+                        let advance = font.as_scaled(SCALES[si]).h_advance(id);
+                        // The spaced glyphs are pushed apart by their ink, which may be wider
+                        let ink = match letter_spacing {
+                            Some(_) => font
+                                .outline_glyph(id.with_scale(SCALES[si]))
+                                .map_or(0.0, |glyph| glyph.px_bounds().width()),
+                            None => 0.0,
+                        };
+                        advance.max(ink)
+                    })
+                    .fold(0.0, f32::max)
+                    + spacing
+            })
+            .collect();
+        if !measured && advances.iter().sum::<f32>() <= self.image.width().saturating_sub(10) as f32
+        {
+            return equal_slots();
+        }
+        let (slots, offset, factor) = wide_slots(&advances, self.image.width(), difficulty);
+        CharLayout {
+            slots,
+            offset,
+            scale: PxScale {
+                x: SCALES[si].x * factor,
+                y: SCALES[si].y * factor,
+            },
+            scale_index: (factor == 1.0).then_some(si),
+            spacing: letter_spacing.map(|_| (spacing * factor) as i32),
         }
     }
 
-    // Create a new captcha instance with random text, width, height and dark mode
-    pub(crate) fn random<R>(get_rnd: &mut R, num: u8, width: u32, height: u32, mode: u8) -> Self
+    // Draw a tile under every character slot, tinted towards a random interference color
+    // and rotated by up to 15 degrees either way, with its size and offset from the slot
+    // drawn from the random stream.
+    pub(crate) fn draw_char_tiles<R>(
+        &mut self,
+        get_rnd: &mut R,
+        fonts: &[FontArc],
+        letter_spacing: Option<u32>,
+        large_glyphs: bool,
+        difficulty: Option<f32>,
+    ) where
+        R: FnMut(u32) -> u32,
+    {
+        let layout = self.char_layout(fonts, letter_spacing, large_glyphs, difficulty);
+        let h = self.image.height() as i32;
+        let background = self.background();
+        for (left, x) in layout.slots {
+            let tint = self.interference_color(get_rnd);
+            let color = Rgb(std::array::from_fn(|c| {
+                ((background[c] as u32 * (100 - TILE_TINT) + tint[c] as u32 * TILE_TINT) / 100)
+                    as u8
+            }));
+            let tw = rnd_between(get_rnd, x * 3 / 4, x + x / 4);
+            let th = rnd_between(get_rnd, h * 3 / 5, h * 9 / 10);
+            let cx = left + x / 2 + rnd_between(get_rnd, -x / 8, x / 8);
+            let cy = h / 2 + rnd_between(get_rnd, -h / 10, h / 10);
+            let angle = (rnd_between(get_rnd, -15, 15) as f32).to_radians();
+            draw_filled_rotated_rect_mut(
+                &mut self.image,
+                (cx as f32, cy as f32),
+                (tw as f32, th as f32),
+                angle,
+                color,
+            );
+        }
+    }
+
+    // Fill the image with dense short strokes of random interference colors, then carve
+    // the characters out of them by filling the pixels of their glyph masks with the
+    // background color, so that the characters are the absence of the pattern.
+    pub(crate) fn carve_negative_space<R>(&mut self, get_rnd: &mut R, masks: &[Vec<u32>])
     where
         R: FnMut(u32) -> u32,
     {
-        let mut chars: Vec<char> = Vec::with_capacity(num as usize);
-        for _ in 0..num {
-            chars.push(BASIC_CHAR[get_rnd(BASIC_CHAR.len() as u32) as usize])
+        let (width, height) = self.image.dimensions();
+        let background = self.background();
+        self.image
+            .pixels_mut()
+            .for_each(|pixel| pixel.0 = background);
+        for _ in 0..(width as u64 * height as u64 / NEGATIVE_STROKE_AREA) {
+            let color = self.interference_color(get_rnd);
+            let x = get_rnd(width) as f32;
+            let y = get_rnd(height) as f32;
+            let dx = rnd_between(get_rnd, -5, 5) as f32;
+            let dy = rnd_between(get_rnd, -5, 5) as f32;
+            draw_line_segment_mut(&mut self.image, (x, y), (x + dx, y + dy), color);
+        }
+
+        let pixels: &mut [u8] = &mut self.image;
+        for index in masks.iter().flatten() {
+            let offset = *index as usize * 3;
+            pixels[offset..offset + 3].copy_from_slice(&background);
+        }
+        self.colors.fill(Rgb(background));
+    }
+
+    // Draw characters with given fonts on the captcha image.
+    // With more than one font, the font of each character is picked from the random stream.
+    // With a variable font, it replaces the primary font and its axes are randomized per character.
+    // With a letter spacing, characters are moved right to keep the gap from the previous one.
+    // With large glyphs, the largest font scale is used regardless of the text length.
+    // Texts with wide East Asian characters are laid out by the measured glyph widths.
+    // With a difficulty above 0.5, characters are squeezed together to overlap, and the
+    // vertical jitter is scaled from 0.5 to 1.5 times of the default range.
+    // With masks, the glyph mask of each character is appended to them, see `Glyph::mask`.
+    // Glyphs found in the cache are not rasterized again.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn draw_characters<R>(
+        &mut self,
+        get_rnd: &mut R,
+        fonts: &[FontArc],
+        variable: Option<&VariableFace>,
+        letter_spacing: Option<u32>,
+        large_glyphs: bool,
+        difficulty: Option<f32>,
+        mut masks: Option<&mut Vec<Vec<u32>>>,
+        cache: Option<&GlyphCache>,
+    ) where
+        R: FnMut(u32) -> u32,
+    {
+        self.colors.clear();
+        if self.chars.is_empty() {
+            return;
         }
+        #[cfg(feature = "shaping")]
+        if crate::shaping::needs_shaping(&self.chars)
+            && self.draw_shaped(get_rnd, &fonts[0], large_glyphs, masks.as_deref_mut())
+        {
+            return;
+        }
+
+        let layout = self.char_layout(fonts, letter_spacing, large_glyphs, difficulty);
+        let (w, h) = (self.image.width() as i32, self.image.height() as i32);
+
+        let variable_font = variable.map(|face| (face, face.font()));
+        let mut prev_right: Option<i32> = None;
 
-        let text: String = chars.iter().collect();
-        Self::new(text, width, height, mode)
+        for (i, c) in self.chars.iter().enumerate() {
+            let fi = if fonts.len() > 1 {
+                get_rnd(fonts.len() as u32) as usize
+            } else {
+                0
+            };
+            let rasterized;
+            let glyph = match (
+                &variable_font,
+                cache
+                    .zip(layout.scale_index)
+                    .and_then(|(cache, si)| cache.get(fi, *c, si)),
+            ) {
+                (Some((face, font)), _) if fi == 0 => {
+                    let mut font = font.clone();
+                    face.randomize(&mut font, get_rnd);
+                    rasterized = Glyph::rasterize(&font, layout.scale, *c);
+                    &rasterized
+                }
+                (_, Some(glyph)) => glyph,
+                _ => {
+                    rasterized = Glyph::rasterize(&fonts[fi], layout.scale, *c);
+                    &rasterized
+                }
+            };
+            let ch = glyph.text_height;
+            let color = self.text_color(get_rnd);
+            self.colors.push(color);
+            let mut gx = layout.slots[i].0 + layout.offset;
+            if let (Some(spacing), Some(right)) = (layout.spacing, prev_right) {
+                gx = gx.max(right.saturating_add(spacing) - glyph.min_x());
+                // The layout leaves room for the spaced glyphs, the glyph is kept on the canvas
+                // anyway if its ink is wider than its advance
+                gx = gx.min(w - glyph.right()).max(-glyph.min_x());
+            }
+            prev_right = Some(gx + glyph.right());
+            let (mut min_y, mut max_y) = (0 - (ch / 8), h + (ch / 8) - ch);
+            if let Some(d) = difficulty {
+                let center = (min_y + max_y) / 2;
+                let half = ((max_y - min_y) as f32 * (0.5 + d) / 2.0) as i32;
+                (min_y, max_y) = (center - half, center + half);
+            }
+            let gy = rnd_between(get_rnd, min_y, max_y);
+            glyph.draw(&mut self.image, color, gx, gy);
+            #[cfg(feature = "svg")]
+            self.vectors.push(VectorShape::Glyph {
+                path: glyph.path.clone(),
+                x: gx,
+                y: gy,
+                color,
+            });
+            if let Some(masks) = masks.as_deref_mut() {
+                let mut mask = Vec::new();
+                let (width, height) = self.image.dimensions();
+                glyph.mask(width, height, gx, gy, &mut mask);
+                masks.push(mask);
+            }
+        }
     }
 
-    // Draw characters with given font on the captcha image.
-    pub(crate) fn draw_characters<R>(&mut self, get_rnd: &mut R, font: &Font)
+    // Draw the characters as one run shaped with the primary font, so that the letters of
+    // joining scripts such as Arabic keep their contextual forms and right-to-left texts are
+    // laid out in visual order. Every glyph is drawn with the color of its first character,
+    // and the run with one vertical jitter at the largest scale fitting the width, centered.
+    // Returns false if the font can not be shaped, the characters are then drawn one by one.
+    #[cfg(feature = "shaping")]
+    fn draw_shaped<R>(
+        &mut self,
+        get_rnd: &mut R,
+        font: &FontArc,
+        large_glyphs: bool,
+        mut masks: Option<&mut Vec<Vec<u32>>>,
+    ) -> bool
     where
         R: FnMut(u32) -> u32,
     {
-        let x = (self.image.width() - 10) as i32 / self.chars.len() as i32;
+        let si = if large_glyphs {
+            0
+        } else {
+            scale_index(self.chars.len())
+        };
+        let text: String = self.chars.iter().collect();
+        let Some((glyphs, advance)) = crate::shaping::shape(font, SCALES[si], &text) else {
+            return false;
+        };
+
+        let available = self.image.width().saturating_sub(10) as f32;
+        let factor = if advance > available && advance > 0.0 {
+            available / advance
+        } else {
+            1.0
+        };
+        let scale = PxScale {
+            x: SCALES[si].x * factor,
+            y: SCALES[si].y * factor,
+        };
+        let glyphs: Vec<(Glyph, usize, i32, i32)> = glyphs
+            .iter()
+            .map(|shaped| {
+                let glyph = Glyph::rasterize_id(font, scale, shaped.id);
+                // The index of the first character of the glyph
+                let index = text
+                    .char_indices()
+                    .take_while(|(offset, _)| *offset < shaped.cluster as usize)
+                    .count();
+                let (x, y) = ((shaped.x * factor) as i32, (shaped.y * factor) as i32);
+                (glyph, index.min(self.chars.len() - 1), x, y)
+            })
+            .collect();
+
+        for _ in 0..self.chars.len() {
+            let color = self.text_color(get_rnd);
+            self.colors.push(color);
+        }
         let h = self.image.height() as i32;
+        let ch = glyphs
+            .iter()
+            .map(|(glyph, _, _, _)| glyph.text_height)
+            .max()
+            .unwrap_or(0);
+        let x0 = 5 + ((available - advance * factor) / 2.0) as i32;
+        let y0 = rnd_between(get_rnd, -(ch / 8), h + (ch / 8) - ch);
+        for (glyph, index, x, y) in &glyphs {
+            let (gx, gy, color) = (x0 + x, y0 - y, self.colors[*index]);
+            glyph.draw(&mut self.image, color, gx, gy);
+            #[cfg(feature = "svg")]
+            self.vectors.push(VectorShape::Glyph {
+                path: glyph.path.clone(),
+                x: gx,
+                y: gy,
+                color,
+            });
+            if let Some(masks) = masks.as_deref_mut() {
+                let mut mask = Vec::new();
+                let (width, height) = self.image.dimensions();
+                glyph.mask(width, height, gx, gy, &mut mask);
+                masks.push(mask);
+            }
+        }
+        true
+    }
 
-        let scale = match self.chars.len() {
-            1..=4 => SCALE_LG,
-            5..=6 => SCALE_MD,
-            _ => SCALE_SM,
+    // Returns true if every character keeps at least `min_visible` of its glyph mask visible.
+    // A pixel is visible if it is still closer to the color drawn by the characters stage
+    // than to the background, and its luma contrasts with the background.
+    pub(crate) fn is_readable(
+        &self,
+        drawn: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+        masks: &[Vec<u32>],
+        min_visible: f32,
+    ) -> bool {
+        let background = self.background();
+        let distance = |a: &[u8], b: &[u8]| -> i32 {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| (*a as i32 - *b as i32).abs())
+                .sum()
         };
+        let luma = |p: &[u8]| (p[0] as i32 * 299 + p[1] as i32 * 587 + p[2] as i32 * 114) / 1000;
+        let (pixels, drawn): (&[u8], &[u8]) = (&self.image, drawn);
+        masks.iter().all(|mask| {
+            let visible = mask
+                .iter()
+                .filter(|i| {
+                    let at = **i as usize * 3;
+                    let (current, expected) = (&pixels[at..at + 3], &drawn[at..at + 3]);
+                    distance(current, expected) < distance(current, &background)
+                        && (luma(current) - luma(&background)).abs() >= MIN_CONTRAST
+                })
+                .count();
+            visible as f32 >= min_visible * mask.len() as f32
+        })
+    }
 
-        for (i, cs) in self.chars.iter().enumerate() {
-            let c = cs.to_string();
-            let (_, ch) = text_size(scale, font, c.as_str());
-            draw_text_mut(
-                &mut self.image,
-                get_color(get_rnd, self.mode),
-                5 + (i as i32 * x),
-                rnd_between(get_rnd, 0 - (ch / 8), h + (ch / 8) - ch),
-                scale,
-                font,
-                c.as_str(),
-            );
+    // Return the background color of the theme or the mode, its gray level in grayscale mode
+    fn background(&self) -> [u8; 3] {
+        let background = match &self.theme {
+            Some(theme) => theme.background.0,
+            None if self.mode > 1 => DARK,
+            None => LIGHT,
+        };
+        match self.grayscale {
+            true => gray(Rgb(background)).0,
+            false => background,
+        }
+    }
+
+    // Return the color as is, or in grayscale mode a gray level contrasting with the background:
+    // the luma of the color is scaled into the dark half of the gray scale on a light
+    // background and into the light half on a dark one, so that palettes of similar lumas
+    // stay distinguishable from each other and from the background
+    fn foreground(&self, color: Rgb<u8>) -> Rgb<u8> {
+        if !self.grayscale {
+            return color;
+        }
+
+        let level = color.to_luma()[0] as u32 * GRAY_RANGE / 255;
+        match self.background()[0] >= 128 {
+            true => Rgb([level as u8; 3]),
+            false => Rgb([(255 - GRAY_RANGE + level) as u8; 3]),
+        }
+    }
+
+    // Return a random text color of the theme or the mode
+    fn text_color<R>(&self, get_rnd: &mut R) -> Rgb<u8>
+    where
+        R: FnMut(u32) -> u32,
+    {
+        self.foreground(match &self.theme {
+            Some(theme) if !theme.text.is_empty() => {
+                theme.text[get_rnd(theme.text.len() as u32) as usize]
+            }
+            _ => get_color(get_rnd, self.mode),
+        })
+    }
+
+    // Return a random interference color of the theme or the mode
+    fn interference_color<R>(&self, get_rnd: &mut R) -> Rgb<u8>
+    where
+        R: FnMut(u32) -> u32,
+    {
+        self.foreground(match &self.theme {
+            Some(theme) if !theme.interference.is_empty() => {
+                theme.interference[get_rnd(theme.interference.len() as u32) as usize]
+            }
+            _ => get_color(get_rnd, self.mode),
+        })
+    }
+
+    // Re-encode the image through a JPEG pass with the given quality and decode it back,
+    // introducing the block artifacts of the quality around the glyph edges
+    #[cfg(feature = "jpeg")]
+    pub(crate) fn jitter_quality(&mut self, quality: u8) -> Result<(), CaptchaError> {
+        let mut buf = Vec::new();
+        JpegEncoder::new_with_quality(&mut buf, quality)
+            .encode_image(&self.image)
+            .map_err(|err| CaptchaError::Encode(err.to_string()))?;
+        JpegDecoder::new(buf.as_slice())
+            .and_then(|decoder| decoder.read_image(&mut self.image))
+            .map_err(|err| CaptchaError::Encode(err.to_string()))
+    }
+
+    // Restore the image drawn before the interference stages
+    pub(crate) fn restore_image(&mut self, drawn: &ImageBuffer<Rgb<u8>, Vec<u8>>) {
+        self.image.copy_from_slice(drawn);
+        // Only the glyphs of the characters stage are kept
+        #[cfg(feature = "svg")]
+        self.vectors.truncate(self.colors.len());
+    }
+
+    // Returns the vector layout of the glyphs and the interference
+    #[cfg(feature = "svg")]
+    pub(crate) fn vectors(&self) -> &[VectorShape] {
+        &self.rendered().vectors
+    }
+
+    // Returns the background color of the theme or the mode
+    pub(crate) fn background_color(&self) -> [u8; 3] {
+        self.rendered().background()
+    }
+
+    // Draw a group of 3-6 bars crossing the image, all vertical or all slanted the same way,
+    // with random widths, gaps and opacities. A bar is skipped if it would cover more than
+    // `BAR_OCCLUSION_CAP` of any glyph mask together with the pixels already `covered`.
+    pub(crate) fn draw_interference_bars<R>(
+        &mut self,
+        get_rnd: &mut R,
+        masks: &[Vec<u32>],
+        covered: &mut Vec<bool>,
+    ) where
+        R: FnMut(u32) -> u32,
+    {
+        let (width, height) = self.image.dimensions();
+        covered.resize(width as usize * height as usize, false);
+        let (w, h) = (width as i32, height as i32);
+        let slant = if get_rnd(2) == 0 {
+            0
+        } else {
+            rnd_between(get_rnd, -h / 2, h / 2)
+        };
+        let mut x = rnd_between(get_rnd, -slant.max(0), w);
+        let mut pixels = Vec::new();
+        for _ in 0..rnd_between(get_rnd, 3, 7) {
+            let bar_width = rnd_between(get_rnd, 1, 4);
+            let alpha = rnd_between(get_rnd, 30, 80) as u32;
+            let color = self.interference_color(get_rnd);
+            pixels.clear();
+            for y in 0..h {
+                let left = x + slant * y / h;
+                for px in left.max(0)..(left + bar_width).min(w) {
+                    pixels.push((y * w + px) as u32);
+                }
+            }
+
+            let within_cap = masks.iter().all(|mask| {
+                let occluded = mask
+                    .iter()
+                    .filter(|i| covered[**i as usize] || pixels.binary_search(i).is_ok())
+                    .count();
+                occluded as f32 <= mask.len() as f32 * BAR_OCCLUSION_CAP
+            });
+            if within_cap {
+                for i in &pixels {
+                    covered[*i as usize] = true;
+                    let pixel = self.image.get_pixel_mut(*i % width, *i / width);
+                    for c in 0..3 {
+                        pixel[c] = ((pixel[c] as u32 * (100 - alpha) + color[c] as u32 * alpha)
+                            / 100) as u8;
+                    }
+                }
+            }
+            x += bar_width + rnd_between(get_rnd, 3, 12);
         }
     }
 
-    // Draw interference lines on the captcha image
-    pub(crate) fn draw_interference_line<R>(&mut self, get_rnd: &mut R)
+    // Draw interference lines on the captcha image, a single curve instead of a doubled one
+    // in the fast mode
+    pub(crate) fn draw_interference_line<R>(&mut self, get_rnd: &mut R, fast: bool)
     where
         R: FnMut(u32) -> u32,
     {
@@ -145,60 +943,146 @@ impl Captcha {
 
         let ctrl_x2 = rnd_between(get_rnd, width as i32 / 2 + span, width as i32 - span);
         let ctrl_y2 = rnd_between(get_rnd, 0, height as i32);
-        // Randomly draw bezier curves
-        let color = get_color(get_rnd, self.mode);
-        draw_cubic_bezier_curve_mut(
-            &mut self.image,
-            (x1 as f32, y1 as f32),
-            (x2 as f32, y2 as f32),
-            (ctrl_x as f32, ctrl_y as f32),
-            (ctrl_x2 as f32, ctrl_y2 as f32),
-            color,
-        );
-        draw_cubic_bezier_curve_mut(
-            &mut self.image,
-            (x1 as f32, y1 as f32 + 2.0),
-            (x2 as f32, y2 as f32 + 2.0),
-            (ctrl_x as f32, ctrl_y as f32 + 2.0),
-            (ctrl_x2 as f32, ctrl_y2 as f32 + 2.0),
-            color,
-        );
-    }
-
-    // Draw interference circle on the captcha image
-    pub(crate) fn draw_interference_ellipse<R>(&mut self, get_rnd: &mut R)
+        // Randomly draw bezier curves, the second one 2 pixels below
+        let color = self.interference_color(get_rnd);
+        for dy in if fast { [0].iter() } else { [0, 2].iter() } {
+            draw_cubic_bezier_curve_mut(
+                &mut self.image,
+                (x1 as f32, (y1 + dy) as f32),
+                (x2 as f32, (y2 + dy) as f32),
+                (ctrl_x as f32, (ctrl_y + dy) as f32),
+                (ctrl_x2 as f32, (ctrl_y2 + dy) as f32),
+                color,
+            );
+            #[cfg(feature = "svg")]
+            self.vectors.push(VectorShape::Curve {
+                start: (x1, y1 + dy),
+                end: (x2, y2 + dy),
+                control_a: (ctrl_x, ctrl_y + dy),
+                control_b: (ctrl_x2, ctrl_y2 + dy),
+                color,
+            });
+        }
+    }
+
+    // Draw interference circle on the captcha image, a single outline in the fast mode
+    pub(crate) fn draw_interference_ellipse<R>(&mut self, get_rnd: &mut R, fast: bool)
     where
         R: FnMut(u32) -> u32,
     {
         let w = rnd_between(get_rnd, 5, self.image.height() as i32 / 3);
         let x = rnd_between(get_rnd, 5, self.image.width() as i32 - 5);
         let y = rnd_between(get_rnd, 5, self.image.height() as i32 - 5);
-        let color = get_color(get_rnd, self.mode);
-        draw_hollow_ellipse_mut(&mut self.image, (x, y), w * 2, w, color);
-        draw_hollow_ellipse_mut(&mut self.image, (x, y), w * 2 + 2, w + 2, color);
+        let color = self.interference_color(get_rnd);
+        for grow in if fast { [0].iter() } else { [0, 2].iter() } {
+            let radius = (w * 2 + grow, w + grow);
+            draw_hollow_ellipse_mut(&mut self.image, (x, y), radius.0, radius.1, color);
+            #[cfg(feature = "svg")]
+            self.vectors.push(VectorShape::Ellipse {
+                center: (x, y),
+                radius,
+                color,
+            });
+        }
     }
 
-    // Draw interference noise on the captcha image
-    pub(crate) fn draw_interference_noise<R>(&mut self, get_rnd: &mut R, complexity: u32)
-    where
+    // Draw interference noise on the captcha image, with the cheap approximations in the fast mode
+    #[cfg(feature = "noise")]
+    pub(crate) fn draw_interference_noise<R>(
+        &mut self,
+        get_rnd: &mut R,
+        complexity: u32,
+        fast: bool,
+    ) where
         R: FnMut(u32) -> u32,
     {
         if complexity > 1 {
-            gaussian_noise_mut(
-                &mut self.image,
-                (complexity - 1) as f64,
-                (4 * complexity) as f64,
-                get_rnd(u32::MAX) as u64,
-            );
-            salt_and_pepper_noise_mut(
-                &mut self.image,
-                (0.002 * complexity as f64) - 0.002,
-                get_rnd(u32::MAX) as u64,
-            );
+            let (mean, stddev) = ((complexity - 1) as f64, (4 * complexity) as f64);
+            let rate = (0.002 * complexity as f64) - 0.002;
+            if fast {
+                tiled_gaussian_noise_mut(&mut self.image, get_rnd, mean, stddev);
+                sparse_salt_and_pepper_noise_mut(&mut self.image, get_rnd, rate);
+            } else {
+                gaussian_noise_mut(&mut self.image, get_rnd, mean, stddev);
+                salt_and_pepper_noise_mut(&mut self.image, get_rnd, rate);
+            }
         }
     }
 }
 
+// The horizontal layout of the characters
+struct CharLayout {
+    // The left and the width of every character slot
+    slots: Vec<(i32, i32)>,
+    // The offset of the glyph origins from the left of the slots
+    offset: i32,
+    scale: PxScale,
+    // The index of the scale in SCALES, None if the glyphs are shrunk to fit the width
+    scale_index: Option<usize>,
+    // The minimum gap between the glyph boxes, shrunk with the glyphs
+    spacing: Option<i32>,
+}
+
+// Returns true if the character is a wide East Asian character, such as the CJK ideographs,
+// kana and hangul, drawn about twice as wide as the Latin characters
+pub(crate) fn is_wide(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1100..=0x115F
+            | 0x2E80..=0x303E
+            | 0x3041..=0x33FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xA960..=0xA97F
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x20000..=0x3FFFD
+    )
+}
+
+// Lay out glyphs of the given advances over the width, returns the slots, the offset of the
+// glyph origins in them and the factor the glyphs are shrunk with to fit. The space left is
+// shared evenly between the slots, and with a difficulty above 0.5 the slots are squeezed
+// together and kept centered, as with the narrow characters.
+pub(crate) fn wide_slots(
+    advances: &[f32],
+    width: u32,
+    difficulty: Option<f32>,
+) -> (Vec<(i32, i32)>, i32, f32) {
+    let available = width.saturating_sub(10) as f32;
+    let total: f32 = advances.iter().sum();
+    let factor = if total > available && total > 0.0 {
+        available / total
+    } else {
+        1.0
+    };
+    let gap = (available - total * factor) / advances.len().max(1) as f32;
+    let squeeze = difficulty.map_or(1.0, |d| 1.0 - 0.6 * (d - 0.5).max(0.0));
+
+    let mut left = 5.0 + available * (1.0 - squeeze) / 2.0;
+    let slots = advances
+        .iter()
+        .map(|advance| {
+            let w = (advance * factor + gap) * squeeze;
+            let slot = (left as i32, w as i32);
+            left += w;
+            slot
+        })
+        .collect();
+    (slots, (gap * squeeze / 2.0) as i32, factor)
+}
+
+// Return a random character of the verification code from the charset
+pub(crate) fn random_char<R>(get_rnd: &mut R, charset: &[char]) -> char
+where
+    R: FnMut(u32) -> u32,
+{
+    charset[get_rnd(charset.len() as u32) as usize]
+}
+
 // Return a random color with given mode
 fn get_color<R>(get_rnd: &mut R, mode: u8) -> Rgb<u8>
 where
@@ -211,6 +1095,11 @@ where
     }
 }
 
+// Return the gray level of a color
+fn gray(color: Rgb<u8>) -> Rgb<u8> {
+    Rgb([color.to_luma()[0]; 3])
+}
+
 // Return a random number between two numbers
 fn rnd_between<R>(get_rnd: &mut R, min: i32, max: i32) -> i32
 where
@@ -220,5 +1109,5 @@ where
         return min;
     }
 
-    min + get_rnd((max - min) as u32) as i32
+    (min as i64 + get_rnd((max as i64 - min as i64) as u32) as i64) as i32
 }
@@ -0,0 +1,419 @@
+use sha3::{Digest, Sha3_256};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use crate::stream::derive_seed;
+#[cfg(feature = "metrics")]
+use crate::CaptchaMetrics;
+use crate::{Captcha, CaptchaError, CaptchaGenerator, ChallengeId, ChallengeToken, SpentTokens};
+
+// Domain separation for the values derived from the master seed
+const SEED_DOMAIN: &[u8] = b"ic-captcha:challenge-seed";
+
+/// A captcha with its challenge token, ready to be served to a client.
+pub struct Challenge {
+    pub captcha: Captcha,
+    pub token: ChallengeToken,
+}
+
+/// The non-secret metadata of a challenge passed to the hooks of a [`ChallengePool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChallengeEvent {
+    /// The id of the challenge, the nonce of its token.
+    pub id: ChallengeId,
+    /// The expiration time of the challenge token in unix milliseconds.
+    pub expire_at: u64,
+    /// The time of the event in unix milliseconds.
+    pub now_ms: u64,
+}
+
+type Hook = Box<dyn Fn(&ChallengeEvent) + Send + Sync>;
+type FailureHook = Box<dyn Fn(&ChallengeEvent, &CaptchaError) + Send + Sync>;
+
+/// A pool of pre-generated captchas derived from a master seed.
+/// Issuing a challenge only pops a ready captcha and signs its token, so it is O(1)
+/// even when rendering is expensive. Call [`ChallengePool::refill`] off the hot path
+/// (e.g. in a timer of a canister) to keep the pool full; if the pool is empty,
+/// [`ChallengePool::issue`] renders a captcha on demand.
+pub struct ChallengePool {
+    generator: CaptchaGenerator,
+    key: Vec<u8>,
+    master_seed: [u8; 32],
+    capacity: usize,
+    ttl_ms: u64,
+    counter: AtomicU64,
+    ready: Mutex<VecDeque<Captcha>>,
+    spent: SpentTokens,
+    on_issue: Option<Hook>,
+    on_verify_success: Option<Hook>,
+    on_verify_failure: Option<FailureHook>,
+    stamp: bool,
+    #[cfg(feature = "metrics")]
+    metrics: Option<CaptchaMetrics>,
+}
+
+impl ChallengePool {
+    /// Returns an empty pool keeping up to `capacity` captchas.
+    /// `key` is the secret key of the challenge tokens, `master_seed` should come from
+    /// a secure random source (e.g. `raw_rand` in a canister).
+    /// Tokens of issued challenges expire after `ttl_ms` milliseconds.
+    pub fn new(
+        generator: CaptchaGenerator,
+        key: &[u8],
+        master_seed: &[u8],
+        capacity: usize,
+        ttl_ms: u64,
+    ) -> Self {
+        ChallengePool {
+            generator,
+            key: key.to_vec(),
+            master_seed: Sha3_256::digest(master_seed).into(),
+            capacity,
+            ttl_ms,
+            counter: AtomicU64::new(0),
+            ready: Mutex::new(VecDeque::with_capacity(capacity)),
+            spent: SpentTokens::new(),
+            on_issue: None,
+            on_verify_success: None,
+            on_verify_failure: None,
+            stamp: false,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Stamps the short id of the challenge on every issued captcha, see [`ChallengeId::short`]
+    /// and [`Captcha::stamp`]. Default is false.
+    pub fn stamp_ids(mut self, stamp: bool) -> Self {
+        self.stamp = stamp;
+        self
+    }
+
+    /// Sets a hook called after a challenge is issued, e.g. for auditing or metrics.
+    pub fn on_issue(mut self, hook: impl Fn(&ChallengeEvent) + Send + Sync + 'static) -> Self {
+        self.on_issue = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets a hook called after an answer is verified successfully.
+    pub fn on_verify_success(
+        mut self,
+        hook: impl Fn(&ChallengeEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_verify_success = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets a hook called after an answer fails the verification, with the error,
+    /// e.g. for abuse scoring.
+    pub fn on_verify_failure(
+        mut self,
+        hook: impl Fn(&ChallengeEvent, &CaptchaError) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_verify_failure = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets the metrics recording the issued challenges, the verification outcomes
+    /// and the rendering time of the pool.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: CaptchaMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Records the size of an encoded captcha image in the metrics of the pool, if any.
+    /// The web framework integrations call it after encoding the issued challenges.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub fn observe_encoded(&self, bytes: usize) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.encode_bytes.observe(bytes as f64);
+        }
+    }
+
+    /// Returns the number of ready captchas.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns true if there is no ready captcha.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Renders captchas until the pool is full, returns the number of new captchas.
+    pub fn refill(&self) -> usize {
+        let mut n = 0;
+        while self.len() < self.capacity {
+            let item = self.render_next();
+            self.lock().push_back(item);
+            n += 1;
+        }
+        n
+    }
+
+    /// Issues a challenge at the given time in unix milliseconds.
+    pub fn issue(&self, now_ms: u64) -> Challenge {
+        let ready = self.lock().pop_front();
+        let captcha = ready.unwrap_or_else(|| self.render_next());
+        self.issue_with(captcha, now_ms)
+    }
+
+    /// Issues a challenge like [`ChallengePool::issue`] with a lazy captcha, see
+    /// [`crate::CaptchaBuilder::generate_lazy`]: the answer and the token are derived now,
+    /// the image is rendered when it is first accessed. The ready captchas are not used.
+    /// Lazy captchas are rendered without the glyph cache of the generator.
+    pub fn issue_lazy(&self, now_ms: u64) -> Challenge {
+        let seed = self.next_seed();
+        let captcha = self.generator.builder().generate_lazy(&seed, None);
+        self.issue_with(captcha, now_ms)
+    }
+
+    fn issue_with(&self, mut captcha: Captcha, now_ms: u64) -> Challenge {
+        if self.stamp {
+            captcha.stamp(&captcha.id().short());
+        }
+        let token = ChallengeToken::new(
+            &self.key,
+            captcha.id().0,
+            now_ms.saturating_add(self.ttl_ms),
+            &captcha.answer(),
+        );
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.issued.inc();
+        }
+        if let Some(hook) = &self.on_issue {
+            hook(&event(&token, now_ms));
+        }
+        Challenge { captcha, token }
+    }
+
+    /// Issues a challenge like [`ChallengePool::issue`] with the accessibility-grade variant
+    /// of its captcha, see [`crate::CaptchaBuilder::generate_pair`].
+    /// The token of the challenge verifies the answer of both.
+    pub fn issue_pair(&self, now_ms: u64) -> (Challenge, Captcha) {
+        let challenge = self.issue(now_ms);
+        let text = challenge.captcha.text();
+        let accessible = self
+            .generator
+            .builder()
+            .accessible_variant(text.chars().count())
+            .generate(&challenge.token.nonce, Some(text));
+        (challenge, accessible)
+    }
+
+    /// Verifies the answer of a challenge token at the given time in unix milliseconds.
+    ///
+    /// The token is not spent: it verifies again and again with its answer until it expires,
+    /// so callers must enforce single use themselves. Use [`ChallengePool::verify_once`] to
+    /// let the pool do it.
+    pub fn verify(
+        &self,
+        token: &ChallengeToken,
+        answer: &str,
+        now_ms: u64,
+    ) -> Result<(), CaptchaError> {
+        let res = token.verify(&self.key, answer, now_ms);
+        self.observe_verify(token, now_ms, res)
+    }
+
+    /// Verifies the answer of a challenge token like [`ChallengePool::verify`] and spends
+    /// the token, so a solved challenge can not be replayed. Returns
+    /// [`CaptchaError::TokenSpent`] if the token was verified before. The spent tokens are
+    /// kept in memory until they expire.
+    pub fn verify_once(
+        &self,
+        token: &ChallengeToken,
+        answer: &str,
+        now_ms: u64,
+    ) -> Result<(), CaptchaError> {
+        let res = token.verify_once(&self.key, answer, now_ms, &self.spent);
+        self.observe_verify(token, now_ms, res)
+    }
+
+    // Record the outcome of a verification in the metrics and the hooks
+    fn observe_verify(
+        &self,
+        token: &ChallengeToken,
+        now_ms: u64,
+        res: Result<(), CaptchaError>,
+    ) -> Result<(), CaptchaError> {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_verify(&res);
+        }
+        match (&res, &self.on_verify_success, &self.on_verify_failure) {
+            (Ok(()), Some(hook), _) => hook(&event(token, now_ms)),
+            (Err(err), _, Some(hook)) => hook(&event(token, now_ms), err),
+            _ => {}
+        }
+        res
+    }
+
+    // Render the captcha with the next seed derived from the master seed
+    fn render_next(&self) -> Captcha {
+        let seed = self.next_seed();
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let captcha = self.generator.generate(&seed, None);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .generation_ms
+                .observe(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        captcha
+    }
+
+    // Return the next captcha seed derived from the master seed
+    fn next_seed(&self) -> [u8; 32] {
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed);
+        derive_seed(SEED_DOMAIN, &self.master_seed, counter)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Captcha>> {
+        match self.ready.lock() {
+            Ok(ready) => ready,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+fn event(token: &ChallengeToken, now_ms: u64) -> ChallengeEvent {
+    ChallengeEvent {
+        id: token.id(),
+        expire_at: token.expire_at,
+        now_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CaptchaBuilder;
+    use std::sync::Arc;
+
+    #[test]
+    fn it_issues_challenges_from_pool() {
+        let generator = CaptchaBuilder::new().complexity(1).build();
+        let pool = ChallengePool::new(generator, b"secret", b"master seed", 2, 60_000);
+        assert_eq!(pool.refill(), 2);
+        assert_eq!(pool.refill(), 0);
+
+        let c1 = pool.issue(1000);
+        let c2 = pool.issue(1000);
+        assert!(pool.is_empty());
+        assert_ne!(c1.token.nonce, c2.token.nonce);
+        assert_eq!(c1.token.expire_at, 61_000);
+        assert!(pool.verify(&c1.token, &c1.captcha.text(), 2000).is_ok());
+        assert_eq!(
+            pool.verify(&c1.token, &c1.captcha.text(), 62_000),
+            Err(CaptchaError::TokenExpired)
+        );
+
+        // renders on demand when empty
+        let c3 = pool.issue(1000);
+        assert!(pool.verify(&c3.token, &c3.captcha.text(), 2000).is_ok());
+
+        // lazy challenges are verified before rendering
+        let c4 = pool.issue_lazy(1000);
+        assert!(!c4.captcha.is_rendered());
+        assert!(pool.verify(&c4.token, &c4.captcha.text(), 2000).is_ok());
+        assert!(!c4.captcha.is_rendered());
+    }
+
+    #[test]
+    fn it_verifies_challenges_once() {
+        let generator = CaptchaBuilder::new().complexity(1).build();
+        let pool = ChallengePool::new(generator, b"secret", b"master seed", 0, 60_000);
+        let c = pool.issue(1000);
+        let answer = c.captcha.answer();
+        assert_eq!(
+            pool.verify_once(&c.token, "wrong", 2000),
+            Err(CaptchaError::AnswerMismatch)
+        );
+        assert!(pool.verify_once(&c.token, &answer, 2000).is_ok());
+        assert_eq!(
+            pool.verify_once(&c.token, &answer, 3000),
+            Err(CaptchaError::TokenSpent)
+        );
+    }
+
+    #[test]
+    fn it_issues_challenge_pairs() {
+        let generator = CaptchaBuilder::new().length(6).build();
+        let pool = ChallengePool::new(generator, b"secret", b"master seed", 0, 60_000);
+        let (challenge, accessible) = pool.issue_pair(1000);
+        assert_eq!(accessible.text(), challenge.captcha.text());
+        assert_eq!(accessible.image().dimensions(), (298, 60));
+        assert!(pool
+            .verify(&challenge.token, &accessible.text(), 2000)
+            .is_ok());
+    }
+
+    #[test]
+    fn it_stamps_challenge_ids() {
+        let new_pool = || {
+            ChallengePool::new(
+                CaptchaBuilder::new().complexity(1).build(),
+                b"secret",
+                b"master seed",
+                0,
+                60_000,
+            )
+        };
+        let plain = new_pool().issue(1000);
+        let stamped = new_pool().stamp_ids(true).issue(1000);
+        assert_eq!(stamped.captcha.text(), plain.captcha.text());
+        assert_eq!(stamped.token, plain.token);
+        assert_ne!(stamped.captcha.image(), plain.captcha.image());
+        assert_eq!(stamped.token.id(), stamped.captcha.id());
+
+        // only the bottom-right corner is changed
+        let (width, height) = plain.captcha.image().dimensions();
+        for (x, y, pixel) in plain.captcha.image().enumerate_pixels() {
+            if x < width - 40 || y < height - 8 {
+                assert_eq!(stamped.captcha.image().get_pixel(x, y), pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn it_calls_hooks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (e1, e2, e3) = (events.clone(), events.clone(), events.clone());
+        let generator = CaptchaBuilder::new().complexity(1).build();
+        let pool = ChallengePool::new(generator, b"secret", b"master seed", 0, 60_000)
+            .on_issue(move |event| e1.lock().unwrap().push(("issue", *event, None)))
+            .on_verify_success(move |event| e2.lock().unwrap().push(("success", *event, None)))
+            .on_verify_failure(move |event, err| {
+                e3.lock()
+                    .unwrap()
+                    .push(("failure", *event, Some(err.clone())))
+            });
+
+        let c = pool.issue(1000);
+        let _ = pool.verify(&c.token, &c.captcha.text(), 2000);
+        let _ = pool.verify(&c.token, "wrong", 3000);
+        let event = |now_ms| ChallengeEvent {
+            id: c.captcha.id(),
+            expire_at: 61_000,
+            now_ms,
+        };
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ("issue", event(1000), None),
+                ("success", event(2000), None),
+                ("failure", event(3000), Some(CaptchaError::AnswerMismatch)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,565 @@
+use std::borrow::Cow;
+
+use crate::captcha::random_char;
+use crate::{CaptchaBuilder, CaptchaError};
+
+// The minimum number of characters left in the charset by `CaptchaBuilder::exclude_chars`
+const MIN_CHARSET_LEN: usize = 4;
+
+// The maximum number of times a character is re-sampled by a constraint of the codes
+const MAX_RESAMPLES: usize = 8;
+
+// Pairs of characters easily confused with each other, besides the case pairs
+const SIMILAR_CHARS: [(char, char); 8] = [
+    ('2', 'Z'),
+    ('5', 'S'),
+    ('6', 'b'),
+    ('8', 'B'),
+    ('9', 'g'),
+    ('9', 'q'),
+    ('g', 'q'),
+    ('u', 'v'),
+];
+
+// Groups of characters confused with each other, the digits 0 and 1 with the letters of
+// the BASIC_CHARS exclusions, and the Latin letters with their Cyrillic and Greek homoglyphs
+const CONFUSABLES: [&[char]; 19] = [
+    &['0', 'O', 'o', 'О', 'о', 'Ο', 'ο'],
+    &['1', 'I', 'i', 'l', '|', 'І', 'і', 'Ι', 'ι'],
+    &['A', 'А', 'Α'],
+    &['a', 'а', 'α'],
+    &['B', 'В', 'Β'],
+    &['C', 'c', 'С', 'с'],
+    &['E', 'Е', 'Ε'],
+    &['e', 'е'],
+    &['H', 'Н', 'Η'],
+    &['K', 'k', 'К', 'к', 'Κ', 'κ'],
+    &['M', 'М', 'Μ'],
+    &['N', 'Ν'],
+    &['P', 'p', 'Р', 'р', 'Ρ', 'ρ'],
+    &['S', 's', 'Ѕ', 'ѕ'],
+    &['T', 'Т', 'Τ'],
+    &['X', 'x', 'Х', 'х', 'Χ', 'χ'],
+    &['Y', 'Υ'],
+    &['y', 'у', 'γ'],
+    &['Z', 'Ζ'],
+];
+
+// The vowels of the pronounceable codes, the other letters are consonants
+const VOWELS: [char; 6] = ['a', 'e', 'i', 'o', 'u', 'y'];
+
+// Return true if the characters are the same or easily confused with each other
+fn similar(a: char, b: char) -> bool {
+    let (a, b) = (a.to_ascii_lowercase(), b.to_ascii_lowercase());
+    a == b
+        || SIMILAR_CHARS.iter().any(|(x, y)| {
+            let (x, y) = (x.to_ascii_lowercase(), y.to_ascii_lowercase());
+            (a, b) == (x, y) || (a, b) == (y, x)
+        })
+}
+
+/// The letter case of the random verification codes, see [`CaptchaBuilder::case_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CasePolicy {
+    /// Both uppercase and lowercase letters of the charset.
+    #[default]
+    Mixed,
+    /// Uppercase letters only, the lowercase letters of the charset are uppercased.
+    Upper,
+    /// Lowercase letters only, the uppercase letters of the charset are lowercased.
+    Lower,
+}
+
+impl CasePolicy {
+    // Return the character in the case of the policy
+    fn apply(self, c: char) -> char {
+        match self {
+            CasePolicy::Mixed => c,
+            CasePolicy::Upper => c.to_uppercase().next().unwrap_or(c),
+            CasePolicy::Lower => c.to_lowercase().next().unwrap_or(c),
+        }
+    }
+}
+
+/// The handling of the charset characters confused with each other, such as `0` and `O`
+/// or the Latin `A` and the Cyrillic `А`, see [`CaptchaBuilder::confusables`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ConfusablePolicy {
+    /// Keep the charset as it is.
+    #[default]
+    Allow,
+    /// Strip the confusable characters from the random verification codes.
+    Strip,
+    /// Reject charsets with confusable characters with an error naming them.
+    Reject,
+}
+
+/// The composition of the random verification codes, see [`CaptchaBuilder::char_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CharPolicy {
+    /// Any characters of the charset.
+    #[default]
+    Any,
+    /// At least one digit and one letter, for downstream validation rules.
+    DigitAndLetter,
+}
+
+impl CharPolicy {
+    // Return the character classes every code must contain
+    fn required(self) -> &'static [fn(&char) -> bool] {
+        match self {
+            CharPolicy::Any => &[],
+            CharPolicy::DigitAndLetter => &[char::is_ascii_digit, |c| c.is_alphabetic()],
+        }
+    }
+}
+
+impl CaptchaBuilder {
+    /// Exclude characters from the random verification codes, e.g. `S` and `5` or `Z` and `2`
+    /// that are easily confused in some fonts. The exclusion is case-sensitive and applies to
+    /// the charset in the case of the case policy, so set it after them. Calls accumulate.
+    /// Returns an error if fewer than 4 characters are left, see [`CaptchaBuilder::charset`].
+    /// The charset set after it is checked as well.
+    pub fn exclude_chars(mut self, chars: &[char]) -> Result<Self, CaptchaError> {
+        let mut excluded = self.excluded.to_vec();
+        excluded.extend(chars.iter().filter(|c| !self.excluded.contains(c)));
+        self.excluded = excluded.into();
+        self.check_charset()?;
+        Ok(self)
+    }
+
+    /// Set the handling of the charset characters confused with each other, default is
+    /// [`ConfusablePolicy::Allow`]. A character is confusable if the charset has another one
+    /// of its group in a built-in table that differs other than in case, e.g. `0` and `O`, `1`
+    /// and `l` or the Latin `P` and the Cyrillic `Р`, which [`crate::BASIC_CHARS`] avoids.
+    /// Returns an error if the charset is rejected or fewer than 4 characters are left.
+    /// The charset set after it is checked as well.
+    pub fn confusables(mut self, policy: ConfusablePolicy) -> Result<Self, CaptchaError> {
+        self.confusables = policy;
+        self.check_charset()?;
+        Ok(self)
+    }
+
+    // Check the characters the random codes are sampled from: the charset must not be
+    // rejected by the confusable policy, and at least 4 characters must be left after the
+    // case policy, the exclusions and the confusable policy, or all of them if the charset
+    // has fewer. Every setter changing them calls it, whatever the order of the calls.
+    pub(crate) fn check_charset(&self) -> Result<(), CaptchaError> {
+        let charset = self.active_charset();
+        if self.confusables == ConfusablePolicy::Reject {
+            let confusable = confusable_chars(&charset);
+            if !confusable.is_empty() {
+                return Err(CaptchaError::InvalidConfig(format!(
+                    "confusable characters {:?} in charset",
+                    confusable
+                )));
+            }
+        }
+
+        let required = MIN_CHARSET_LEN.min(self.charset.len());
+        if charset.len() < required {
+            return Err(CaptchaError::InvalidConfig(format!(
+                "only {} characters left in the charset, at least {} required",
+                charset.len(),
+                required
+            )));
+        }
+        Ok(())
+    }
+
+    // Fill the code with a random verification code drawn from the random stream, its length
+    // is only drawn with a length range so that the codes of a fixed length stay the same
+    pub(crate) fn random_code<R>(&self, get_rnd: &mut R, code: &mut Vec<char>)
+    where
+        R: FnMut(u32) -> u32,
+    {
+        if self.random_pictograms(get_rnd, code)
+            || self.random_spelled(get_rnd, code)
+            || self.random_words(get_rnd, code)
+        {
+            return;
+        }
+
+        let mut length = self.length;
+        if self.max_length > self.length {
+            length += get_rnd((self.max_length - self.length) as u32 + 1) as u8;
+        }
+        let charset = self.active_charset();
+        let syllables = match self.pronounceable {
+            true => syllables(&charset),
+            false => None,
+        };
+        code.clear();
+        for i in 0..length {
+            let pool: &[char] = match &syllables {
+                Some((consonants, _)) if i % 2 == 0 => consonants,
+                Some((_, vowels)) => vowels,
+                None => &charset,
+            };
+            let mut c = random_char(get_rnd, pool);
+            if self.no_adjacent_repeats {
+                if let Some(prev) = code.last() {
+                    for _ in 0..MAX_RESAMPLES {
+                        if !similar(*prev, c) {
+                            break;
+                        }
+                        c = random_char(get_rnd, pool);
+                    }
+                }
+            }
+            code.push(c);
+        }
+
+        // Replace a random character with one of every missing class, without replacing
+        // the only character of another class
+        let required = self.char_policy.required();
+        for class in required {
+            if code.is_empty() || code.iter().any(class) {
+                continue;
+            }
+            let candidates: Vec<char> = charset.iter().copied().filter(class).collect();
+            if candidates.is_empty() {
+                continue;
+            }
+
+            let sole = |code: &[char], i: usize| {
+                required
+                    .iter()
+                    .any(|other| other(&code[i]) && code.iter().filter(|c| other(c)).count() == 1)
+            };
+            let mut i = get_rnd(code.len() as u32) as usize;
+            for _ in 0..code.len() {
+                if !sole(code, i) {
+                    break;
+                }
+                i = (i + 1) % code.len();
+            }
+            let mut c = random_char(get_rnd, &candidates);
+            if self.no_adjacent_repeats {
+                for _ in 0..MAX_RESAMPLES {
+                    let prev = i.checked_sub(1).map(|j| code[j]);
+                    let next = code.get(i + 1).copied();
+                    if [prev, next].iter().flatten().all(|n| !similar(*n, c)) {
+                        break;
+                    }
+                    c = random_char(get_rnd, &candidates);
+                }
+            }
+            code[i] = c;
+        }
+    }
+
+    // Return the characters the random verification codes are sampled from: the charset in
+    // the case of the case policy, without the duplicates of the case conversion, the excluded
+    // characters and the confusable characters if they are stripped
+    pub(crate) fn active_charset(&self) -> Cow<'_, [char]> {
+        if self.case_policy == CasePolicy::Mixed
+            && self.excluded.is_empty()
+            && self.confusables != ConfusablePolicy::Strip
+        {
+            return Cow::Borrowed(&self.charset);
+        }
+
+        let mut charset: Vec<char> = Vec::with_capacity(self.charset.len());
+        for c in self.charset.iter().map(|c| self.case_policy.apply(*c)) {
+            if !charset.contains(&c) && !self.excluded.contains(&c) {
+                charset.push(c);
+            }
+        }
+        if self.confusables == ConfusablePolicy::Strip {
+            let confusable = confusable_chars(&charset);
+            charset.retain(|c| !confusable.contains(c));
+        }
+        Cow::Owned(charset)
+    }
+}
+
+// Return the characters of the charset confusable with another one of it, in the same
+// group of the table but not the same letter in the other case
+fn confusable_chars(charset: &[char]) -> Vec<char> {
+    let lower = |c: &char| c.to_lowercase().next().unwrap_or(*c);
+    charset
+        .iter()
+        .copied()
+        .filter(|c| {
+            CONFUSABLES.iter().any(|group| {
+                group.contains(c)
+                    && charset
+                        .iter()
+                        .any(|other| group.contains(other) && lower(other) != lower(c))
+            })
+        })
+        .collect()
+}
+
+// Split the letters of the charset into consonants and vowels, returns None if either is
+// missing so that the codes fall back to the whole charset
+fn syllables(charset: &[char]) -> Option<(Vec<char>, Vec<char>)> {
+    let (vowels, consonants): (Vec<char>, Vec<char>) = charset
+        .iter()
+        .filter(|c| c.is_ascii_alphabetic())
+        .partition(|c| VOWELS.contains(&c.to_ascii_lowercase()));
+    match consonants.is_empty() || vowels.is_empty() {
+        true => None,
+        false => Some((consonants, vowels)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BASIC_CHARS;
+
+    #[test]
+    fn it_applies_case_policy() {
+        let builder = CaptchaBuilder::new();
+        assert_eq!(&builder.active_charset()[..], BASIC_CHARS);
+
+        let upper = builder.clone().case_policy(CasePolicy::Upper).unwrap();
+        let charset = upper.active_charset();
+        assert_eq!(charset.len(), 8 + 23);
+        assert!(charset.iter().all(|c| !c.is_lowercase()));
+        for seed in [b"seed 0", b"seed 1", b"seed 2"] {
+            let captcha = upper.generate(seed, None);
+            assert!(captcha.text().chars().all(|c| charset.contains(&c)));
+            assert_eq!(captcha.text(), upper.generate_lazy(seed, None).text());
+        }
+
+        let lower = builder.case_policy(CasePolicy::Lower).unwrap();
+        let charset = lower.active_charset();
+        assert_eq!(charset.len(), 8 + 23);
+        assert!(charset.iter().all(|c| !c.is_uppercase()));
+        assert!(lower
+            .generate(b"seed", None)
+            .text()
+            .chars()
+            .all(|c| charset.contains(&c)));
+
+        let custom = CaptchaBuilder::new()
+            .charset(&['a', 'b', 'B', '2', '3'])
+            .unwrap()
+            .case_policy(CasePolicy::Upper)
+            .unwrap();
+        assert_eq!(&custom.active_charset()[..], &['A', 'B', '2', '3']);
+
+        // the conversion can not shrink the charset below 4 characters
+        let pairs = CaptchaBuilder::new()
+            .charset(&['A', 'a', 'B', 'b'])
+            .unwrap();
+        assert!(matches!(
+            pairs.clone().case_policy(CasePolicy::Upper),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            pairs
+                .case_policy(CasePolicy::Mixed)
+                .unwrap()
+                .exclude_chars(&['a']),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn it_draws_code_lengths_from_seed() {
+        let builder = CaptchaBuilder::new().length_range(4, 7);
+        let lengths: std::collections::HashSet<usize> = (0..32u8)
+            .map(|i| {
+                let captcha = builder.generate(&[i; 32], None);
+                let text = captcha.text();
+                assert_eq!(text, builder.generate_lazy(&[i; 32], None).text());
+                assert_eq!(captcha.image(), builder.generate(&[i; 32], None).image());
+                text.chars().count()
+            })
+            .collect();
+        assert!(lengths.iter().all(|len| (4..=7).contains(len)));
+        assert!(lengths.len() > 2);
+
+        // A fixed length is still drawn the same
+        let fixed = CaptchaBuilder::new().length_range(5, 5);
+        assert_eq!(
+            fixed.generate(b"seed", None).text(),
+            CaptchaBuilder::new()
+                .length(5)
+                .generate(b"seed", None)
+                .text()
+        );
+        assert_eq!(builder.length(6).generate(b"seed", None).text().len(), 6);
+    }
+
+    #[test]
+    fn it_avoids_adjacent_repeats() {
+        assert!(similar('s', 'S'));
+        assert!(similar('5', 's'));
+        assert!(similar('q', '9'));
+        assert!(!similar('a', 'b'));
+
+        let builder = CaptchaBuilder::new().length(8).no_adjacent_repeats(true);
+        let plain = CaptchaBuilder::new().length(8);
+        let mut resampled = false;
+        // The lazy captchas only draw the codes
+        for i in 0..64u8 {
+            let text: Vec<char> = builder
+                .generate_lazy(&[i; 32], None)
+                .text()
+                .chars()
+                .collect();
+            assert!(text.windows(2).all(|w| !similar(w[0], w[1])), "{text:?}");
+            resampled |= plain.generate_lazy(&[i; 32], None).text().chars().ne(text);
+        }
+        assert!(resampled);
+        assert_eq!(
+            builder.generate(b"seed", None).text(),
+            builder.generate_lazy(b"seed", None).text()
+        );
+    }
+
+    #[test]
+    fn it_enforces_char_policy() {
+        let builder = CaptchaBuilder::new()
+            .length_range(2, 6)
+            .char_policy(CharPolicy::DigitAndLetter);
+        let plain = CaptchaBuilder::new().length_range(2, 6);
+        let mut replaced = false;
+        for i in 0..64u8 {
+            let text = builder.generate_lazy(&[i; 32], None).text();
+            assert!(text.chars().any(|c| c.is_ascii_digit()), "{text}");
+            assert!(text.chars().any(|c| c.is_alphabetic()), "{text}");
+            assert!(text.chars().all(|c| BASIC_CHARS.contains(&c)));
+            replaced |= plain.generate_lazy(&[i; 32], None).text() != text;
+        }
+        assert!(replaced);
+        assert_eq!(
+            builder.generate(b"seed", None).text(),
+            builder.generate_lazy(b"seed", None).text()
+        );
+
+        // A charset without digits can not meet the policy
+        let letters = CaptchaBuilder::new()
+            .charset(&['A', 'B', 'C', 'D'])
+            .unwrap()
+            .char_policy(CharPolicy::DigitAndLetter);
+        assert_eq!(letters.generate_lazy(b"seed", None).text().len(), 4);
+    }
+
+    #[test]
+    fn it_excludes_chars() {
+        let builder = CaptchaBuilder::new()
+            .exclude_chars(&['S', '5'])
+            .unwrap()
+            .exclude_chars(&['Z', '2', 'S'])
+            .unwrap();
+        let charset = builder.active_charset();
+        assert_eq!(charset.len(), BASIC_CHARS.len() - 4);
+        assert!(!charset.iter().any(|c| ['S', '5', 'Z', '2'].contains(c)));
+        assert!(charset.contains(&'s'));
+        for seed in [b"seed 0", b"seed 1", b"seed 2"] {
+            let captcha = builder.generate(seed, None);
+            assert!(captcha.text().chars().all(|c| charset.contains(&c)));
+            assert_eq!(captcha.text(), builder.generate_lazy(seed, None).text());
+        }
+
+        let upper = CaptchaBuilder::new()
+            .case_policy(CasePolicy::Upper)
+            .unwrap()
+            .exclude_chars(&['S'])
+            .unwrap();
+        assert!(!upper.active_charset().contains(&'S'));
+
+        let digits = CaptchaBuilder::numeric()
+            .exclude_chars(&['2', '5'])
+            .unwrap();
+        assert_eq!(digits.active_charset().len(), 6);
+        assert!(matches!(
+            digits.exclude_chars(&['3', '4', '6']),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+
+        // the charset set after the exclusions is checked as well
+        let excluded = CaptchaBuilder::new().exclude_chars(&['2']).unwrap();
+        assert!(matches!(
+            excluded.clone().charset(&['2']),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            excluded.charset(&['2', '3', '4', '5']),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn it_generates_pronounceable_codes() {
+        let builder = CaptchaBuilder::new()
+            .length(6)
+            .case_policy(CasePolicy::Lower)
+            .unwrap()
+            .pronounceable(true);
+        for i in 0..32u8 {
+            let text: Vec<char> = builder
+                .generate_lazy(&[i; 32], None)
+                .text()
+                .chars()
+                .collect();
+            assert_eq!(text.len(), 6);
+            for (j, c) in text.iter().enumerate() {
+                assert!(c.is_ascii_lowercase(), "{text:?}");
+                assert_eq!(VOWELS.contains(c), j % 2 == 1, "{text:?}");
+            }
+        }
+        assert_eq!(
+            builder.generate(b"seed", None).text(),
+            builder.generate_lazy(b"seed", None).text()
+        );
+
+        // Falls back to the whole charset without vowels
+        let digits = CaptchaBuilder::numeric().pronounceable(true);
+        assert_eq!(
+            digits.generate_lazy(b"seed", None).text(),
+            CaptchaBuilder::numeric()
+                .generate_lazy(b"seed", None)
+                .text()
+        );
+    }
+
+    #[test]
+    fn it_handles_confusable_chars() {
+        assert!(confusable_chars(BASIC_CHARS).is_empty());
+        assert!(confusable_chars(crate::CYRILLIC_CHARS).is_empty());
+        assert!(confusable_chars(&['o', 'O', 'A']).is_empty());
+        assert_eq!(confusable_chars(&['0', 'O', 'A', 'l']), ['0', 'O']);
+        assert_eq!(confusable_chars(&['P', '\u{420}', 'x']), ['P', '\u{420}']);
+
+        // The default font subset has no glyphs of the confusable characters
+        #[cfg(feature = "full-font")]
+        {
+            let charset = ['0', 'O', '1', 'I', 'A', 'B', 'C', 'D', 'E'];
+            let builder = CaptchaBuilder::new()
+                .confusables(ConfusablePolicy::Strip)
+                .unwrap()
+                .charset(&charset)
+                .unwrap();
+            assert_eq!(&builder.active_charset()[..], &['A', 'B', 'C', 'D', 'E']);
+            for seed in [b"seed 0", b"seed 1", b"seed 2"] {
+                let text = builder.generate_lazy(seed, None).text();
+                assert!(text.chars().all(|c| "ABCDE".contains(c)), "{text}");
+            }
+            assert!(matches!(
+                builder.charset(&['0', 'O', 'A', 'B', 'C']),
+                Err(CaptchaError::InvalidConfig(_))
+            ));
+
+            let builder = CaptchaBuilder::new().charset(&charset).unwrap();
+            assert!(matches!(
+                builder.clone().confusables(ConfusablePolicy::Reject),
+                Err(CaptchaError::InvalidConfig(msg)) if msg.contains("'0', 'O', '1', 'I'")
+            ));
+            assert!(builder
+                .exclude_chars(&['0', '1'])
+                .unwrap()
+                .confusables(ConfusablePolicy::Reject)
+                .is_ok());
+        }
+        assert!(CaptchaBuilder::new()
+            .confusables(ConfusablePolicy::Reject)
+            .is_ok());
+    }
+}
@@ -0,0 +1,112 @@
+use image::{ImageBuffer, Rgb};
+
+use crate::Captcha;
+
+// SSIM constants for 8-bit images: (0.01 * 255)^2 and (0.03 * 255)^2
+const SSIM_C1: f64 = 6.5025;
+const SSIM_C2: f64 = 58.5225;
+
+// Side length of the square windows used for the structural similarity
+const SSIM_WINDOW: u32 = 8;
+
+/// Returns a difference score between two images, range 0.0-1.0.
+/// 0.0 means the images are identical, 1.0 means they are completely different.
+/// The score is the average of the normalized pixel difference and the structural dissimilarity.
+/// Images with different dimensions always score 1.0.
+pub fn diff_score(a: &ImageBuffer<Rgb<u8>, Vec<u8>>, b: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> f64 {
+    if a.dimensions() != b.dimensions() {
+        return 1.0;
+    }
+    if a.width() == 0 || a.height() == 0 {
+        return 0.0;
+    }
+
+    let pixel = pixel_diff(a, b);
+    let structural = (1.0 - ssim(a, b)) / 2.0;
+    ((pixel + structural) / 2.0).clamp(0.0, 1.0)
+}
+
+impl Captcha {
+    /// Returns the difference score between this captcha image and another one, range 0.0-1.0.
+    /// It can be used to check that a refresh actually produced a visually different image.
+    pub fn diff_score(&self, other: &Captcha) -> f64 {
+        diff_score(self.image(), other.image())
+    }
+}
+
+// Mean absolute difference over all channels, normalized to 0.0-1.0
+fn pixel_diff(a: &ImageBuffer<Rgb<u8>, Vec<u8>>, b: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> f64 {
+    let sum: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw().iter())
+        .map(|(x, y)| x.abs_diff(*y) as u64)
+        .sum();
+    sum as f64 / (a.as_raw().len() as f64 * 255.0)
+}
+
+// Mean structural similarity of the luma channel over non-overlapping windows, range -1.0-1.0
+fn ssim(a: &ImageBuffer<Rgb<u8>, Vec<u8>>, b: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> f64 {
+    let (width, height) = a.dimensions();
+    let mut total = 0.0;
+    let mut windows = 0u32;
+
+    let mut y0 = 0;
+    while y0 < height {
+        let y1 = (y0 + SSIM_WINDOW).min(height);
+        let mut x0 = 0;
+        while x0 < width {
+            let x1 = (x0 + SSIM_WINDOW).min(width);
+            let n = ((x1 - x0) * (y1 - y0)) as f64;
+
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let la = luma(a.get_pixel(x, y));
+                    let lb = luma(b.get_pixel(x, y));
+                    sa += la;
+                    sb += lb;
+                    saa += la * la;
+                    sbb += lb * lb;
+                    sab += la * lb;
+                }
+            }
+
+            let (ma, mb) = (sa / n, sb / n);
+            let va = saa / n - ma * ma;
+            let vb = sbb / n - mb * mb;
+            let cov = sab / n - ma * mb;
+            total += ((2.0 * ma * mb + SSIM_C1) * (2.0 * cov + SSIM_C2))
+                / ((ma * ma + mb * mb + SSIM_C1) * (va + vb + SSIM_C2));
+            windows += 1;
+            x0 = x1;
+        }
+        y0 = y1;
+    }
+
+    total / windows as f64
+}
+
+fn luma(p: &Rgb<u8>) -> f64 {
+    0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CaptchaBuilder;
+
+    #[test]
+    fn it_scores_image_differences() {
+        let builder = CaptchaBuilder::new();
+        let captcha = builder.generate(&[0u8, 32], None);
+        let same = builder.generate(&[0u8, 32], None);
+        let other = builder.generate(&[1u8, 32], None);
+
+        assert_eq!(captcha.diff_score(&same), 0.0);
+        let score = captcha.diff_score(&other);
+        assert!(score > 0.05 && score <= 1.0, "score: {}", score);
+
+        let small = CaptchaBuilder::new().width(120).generate(&[0u8, 32], None);
+        assert_eq!(captcha.diff_score(&small), 1.0);
+    }
+}
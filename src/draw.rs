@@ -0,0 +1,262 @@
+use image::{ImageBuffer, Rgb};
+use std::mem::swap;
+
+// Drawing primitives for the interference stages, they draw as much of the shape
+// as lies inside the image bounds.
+
+// Draw a cubic bezier curve by connecting points sampled along the curve with line segments
+pub(crate) fn draw_cubic_bezier_curve_mut(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    start: (f32, f32),
+    end: (f32, f32),
+    control_a: (f32, f32),
+    control_b: (f32, f32),
+    color: Rgb<u8>,
+) {
+    let cubic_bezier_curve = |t: f32| {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let mt = 1.0 - t;
+        let mt2 = mt * mt;
+        let mt3 = mt2 * mt;
+        let x = (start.0 * mt3)
+            + (3.0 * control_a.0 * mt2 * t)
+            + (3.0 * control_b.0 * mt * t2)
+            + (end.0 * t3);
+        let y = (start.1 * mt3)
+            + (3.0 * control_a.1 * mt2 * t)
+            + (3.0 * control_b.1 * mt * t2)
+            + (end.1 * t3);
+        // round to nearest pixel to avoid line artifacts
+        (x.round(), y.round())
+    };
+
+    let distance =
+        |a: (f32, f32), b: (f32, f32)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+
+    // Approximate the curve length by adding the distances between control points,
+    // shorter curves get relatively more segments.
+    let length =
+        distance(start, control_a) + distance(control_a, control_b) + distance(control_b, end);
+    let segments = ((length.powi(2) + 800.0).sqrt() / 8.0) as i32;
+
+    let t_interval = 1.0 / segments as f32;
+    let mut t1 = 0.0;
+    for i in 0..segments {
+        let t2 = (i as f32 + 1.0) * t_interval;
+        draw_line_segment_mut(image, cubic_bezier_curve(t1), cubic_bezier_curve(t2), color);
+        t1 = t2;
+    }
+}
+
+// Draw a line segment with Bresenham's algorithm
+pub(crate) fn draw_line_segment_mut(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    start: (f32, f32),
+    end: (f32, f32),
+    color: Rgb<u8>,
+) {
+    let (mut x0, mut y0) = start;
+    let (mut x1, mut y1) = end;
+
+    let is_steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if is_steep {
+        swap(&mut x0, &mut y0);
+        swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        swap(&mut x0, &mut x1);
+        swap(&mut y0, &mut y1);
+    }
+
+    let dx = x1 - x0;
+    let dy = (y1 - y0).abs();
+    let y_step = if y0 < y1 { 1 } else { -1 };
+    let mut error = dx / 2.0;
+    let (mut x, mut y) = (x0 as i32, y0 as i32);
+    while x <= x1 as i32 {
+        if is_steep {
+            draw_if_in_bounds(image, y, x, color);
+        } else {
+            draw_if_in_bounds(image, x, y, color);
+        }
+
+        x += 1;
+        error -= dy;
+        if error < 0.0 {
+            y += y_step;
+            error += dx;
+        }
+    }
+}
+
+// Draw the outline of an ellipse with the midpoint ellipse algorithm
+pub(crate) fn draw_hollow_ellipse_mut(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    center: (i32, i32),
+    width_radius: i32,
+    height_radius: i32,
+    color: Rgb<u8>,
+) {
+    let (x0, y0) = center;
+    let mut draw_quad_pixels = |x: i32, y: i32| {
+        draw_if_in_bounds(image, x0 + x, y0 + y, color);
+        draw_if_in_bounds(image, x0 - x, y0 + y, color);
+        draw_if_in_bounds(image, x0 + x, y0 - y, color);
+        draw_if_in_bounds(image, x0 - x, y0 - y, color);
+    };
+
+    let w2 = width_radius as i64 * width_radius as i64;
+    let h2 = height_radius as i64 * height_radius as i64;
+    let mut x = 0;
+    let mut y = height_radius;
+    let mut px = 0i64;
+    let mut py = 2 * w2 * y as i64;
+
+    draw_quad_pixels(x, y);
+
+    // Top and bottom regions
+    let mut p = (h2 - (w2 * height_radius as i64)) as f32 + (0.25 * w2 as f32);
+    while px < py {
+        x += 1;
+        px += 2 * h2;
+        if p < 0.0 {
+            p += (h2 + px) as f32;
+        } else {
+            y -= 1;
+            py -= 2 * w2;
+            p += (h2 + px - py) as f32;
+        }
+        draw_quad_pixels(x, y);
+    }
+
+    // Left and right regions
+    p = (h2 as f32) * (x as f32 + 0.5).powi(2) + (w2 * (y as i64 - 1).pow(2)) as f32
+        - (w2 * h2) as f32;
+    while y > 0 {
+        y -= 1;
+        py -= 2 * w2;
+        if p > 0.0 {
+            p += (w2 - py) as f32;
+        } else {
+            x += 1;
+            px += 2 * h2;
+            p += (w2 - py + px) as f32;
+        }
+        draw_quad_pixels(x, y);
+    }
+}
+
+// A 3x5 bitmap font of the stamp characters, one row per byte with the 3 low bits as pixels
+const STAMP_GLYPHS: [(char, [u8; 5]); 35] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b011, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b111, 0b101, 0b101, 0b101, 0b101]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+];
+
+// The width of a stamp character including the gap
+pub(crate) const STAMP_ADVANCE: i32 = 4;
+// The height of a stamp character
+pub(crate) const STAMP_HEIGHT: i32 = 5;
+
+// Draw a label with the 3x5 stamp font at the given position, shifting the pixels
+// towards the opposite of their brightness so it stays low-contrast on any background.
+// Letters are case-insensitive, unsupported characters are drawn as spaces.
+pub(crate) fn draw_stamp_mut(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    label: &str,
+    x: i32,
+    y: i32,
+) {
+    for (i, c) in label.chars().enumerate() {
+        let c = c.to_ascii_uppercase();
+        let Some((_, rows)) = STAMP_GLYPHS.iter().find(|(g, _)| *g == c) else {
+            continue;
+        };
+        let gx = x + i as i32 * STAMP_ADVANCE;
+        for (dy, row) in rows.iter().enumerate() {
+            for dx in 0..3 {
+                if row & (0b100 >> dx) == 0 {
+                    continue;
+                }
+                let (px, py) = (gx + dx, y + dy as i32);
+                if px < 0 || py < 0 || px >= image.width() as i32 || py >= image.height() as i32 {
+                    continue;
+                }
+                let pixel = image.get_pixel_mut(px as u32, py as u32);
+                let luma = (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3;
+                for channel in pixel.0.iter_mut() {
+                    *channel = if luma > 127 {
+                        channel.saturating_sub(64)
+                    } else {
+                        channel.saturating_add(64)
+                    };
+                }
+            }
+        }
+    }
+}
+
+// Fill a rectangle rotated by the given angle in radians around its center
+pub(crate) fn draw_filled_rotated_rect_mut(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    center: (f32, f32),
+    size: (f32, f32),
+    angle: f32,
+    color: Rgb<u8>,
+) {
+    let (sin, cos) = angle.sin_cos();
+    let (half_w, half_h) = (size.0 / 2.0, size.1 / 2.0);
+    // The half extents of the bounding box of the rotated rectangle
+    let extent_x = half_w * cos.abs() + half_h * sin.abs();
+    let extent_y = half_w * sin.abs() + half_h * cos.abs();
+    let x_range = (center.0 - extent_x).floor() as i32..=(center.0 + extent_x).ceil() as i32;
+    for y in (center.1 - extent_y).floor() as i32..=(center.1 + extent_y).ceil() as i32 {
+        for x in x_range.clone() {
+            let (dx, dy) = (x as f32 + 0.5 - center.0, y as f32 + 0.5 - center.1);
+            // Rotate the pixel back into the frame of the rectangle
+            let (rx, ry) = (dx * cos + dy * sin, dy * cos - dx * sin);
+            if rx.abs() <= half_w && ry.abs() <= half_h {
+                draw_if_in_bounds(image, x, y, color);
+            }
+        }
+    }
+}
+
+fn draw_if_in_bounds(image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, x: i32, y: i32, color: Rgb<u8>) {
+    if x >= 0 && x < image.width() as i32 && y >= 0 && y < image.height() as i32 {
+        image.put_pixel(x as u32, y as u32, color);
+    }
+}
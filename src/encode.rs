@@ -0,0 +1,786 @@
+use base64::{
+    engine::{general_purpose, GeneralPurpose},
+    write::EncoderStringWriter,
+};
+#[cfg(feature = "bmp")]
+use image::codecs::bmp::BmpEncoder;
+#[cfg(feature = "png")]
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+#[cfg(feature = "webp")]
+use image::codecs::webp::WebPEncoder;
+#[cfg(any(feature = "bmp", feature = "png", feature = "webp"))]
+use image::ImageEncoder;
+#[cfg(feature = "jpeg")]
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType as ResizeFilter, DynamicImage};
+#[cfg(any(feature = "png", feature = "webp"))]
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{Captcha, CaptchaError};
+
+/// The image format of an encoded captcha.
+/// Every format is enabled by the crate feature of the same name in lowercase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// Lossy JPEG, the quality is in range 10-80, default is 30.
+    #[cfg(feature = "jpeg")]
+    Jpeg,
+    /// Lossless PNG for crisp small text, the quality is ignored.
+    /// See [`Captcha::to_png`] for the compression options.
+    #[cfg(feature = "png")]
+    Png,
+    /// WebP, lossless with quality 0 or 100. Quality 1-99 is near-lossless: up to the 3 low bits
+    /// of every channel are rounded away before the lossless encoding, smaller files with a
+    /// lower quality. The lossy VP8 encoder of libwebp is not used, to stay pure Rust.
+    #[cfg(feature = "webp")]
+    WebP,
+    /// Lossy AVIF, the quality is in range 1-100, default is 30.
+    #[cfg(feature = "avif")]
+    Avif,
+    /// Uncompressed 24-bit BMP, the quality is ignored. The pixel rows are stored bottom-up
+    /// in BGR order, every row padded to a multiple of 4 bytes.
+    #[cfg(feature = "bmp")]
+    Bmp,
+}
+
+/// The alphabet of a bare base64 encoded image, see [`Captcha::to_base64_raw`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Base64Alphabet {
+    /// The standard alphabet with padding, as in data URLs.
+    #[default]
+    Standard,
+    /// The URL and filename safe alphabet without padding, for query strings, cookies and
+    /// other transports that reserve `+`, `/` and `=`.
+    UrlSafeNoPad,
+}
+
+impl Base64Alphabet {
+    fn engine(self) -> &'static GeneralPurpose {
+        match self {
+            Base64Alphabet::Standard => &general_purpose::STANDARD,
+            Base64Alphabet::UrlSafeNoPad => &general_purpose::URL_SAFE_NO_PAD,
+        }
+    }
+}
+
+/// The compression level of PNG output, see [`PngOptions`].
+#[cfg(feature = "png")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PngCompression {
+    /// Fast, minimal compression, the fewest cycles.
+    #[default]
+    Fast,
+    /// The balanced compression level of zlib.
+    Default,
+    /// The highest compression level, the smallest payload.
+    Best,
+}
+
+/// The filter applied to the pixel rows of PNG output before compression, see [`PngOptions`].
+#[cfg(feature = "png")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PngFilter {
+    /// No filter, the cheapest.
+    None,
+    /// The difference with the previous pixel in the row.
+    Sub,
+    /// The difference with the pixel above.
+    Up,
+    /// The difference with the average of the left and the above pixels.
+    Avg,
+    /// The difference with the Paeth predictor of the left, above and upper left pixels.
+    Paeth,
+    /// A heuristic choice of the filter for every row.
+    #[default]
+    Adaptive,
+}
+
+/// The options of PNG output, see [`Captcha::to_png`]. Canisters can trade cycles for
+/// payload size, the default is the fast compression with the adaptive filter,
+/// the same as [`OutputFormat::Png`].
+#[cfg(feature = "png")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+}
+
+#[cfg(feature = "png")]
+impl PngOptions {
+    /// Returns the options with the fewest cycles: fast compression without filter.
+    pub fn fast() -> Self {
+        PngOptions {
+            compression: PngCompression::Fast,
+            filter: PngFilter::None,
+        }
+    }
+
+    /// Returns the options with the smallest payload: best compression with the adaptive filter.
+    pub fn best() -> Self {
+        PngOptions {
+            compression: PngCompression::Best,
+            filter: PngFilter::Adaptive,
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Returns the MIME type of the format.
+    pub fn mime_type(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "jpeg")]
+            OutputFormat::Jpeg => "image/jpeg",
+            #[cfg(feature = "png")]
+            OutputFormat::Png => "image/png",
+            #[cfg(feature = "webp")]
+            OutputFormat::WebP => "image/webp",
+            #[cfg(feature = "avif")]
+            OutputFormat::Avif => "image/avif",
+            #[cfg(feature = "bmp")]
+            OutputFormat::Bmp => "image/bmp",
+        }
+    }
+
+    /// Returns the common file extension of the format, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "jpeg")]
+            OutputFormat::Jpeg => "jpeg",
+            #[cfg(feature = "png")]
+            OutputFormat::Png => "png",
+            #[cfg(feature = "webp")]
+            OutputFormat::WebP => "webp",
+            #[cfg(feature = "avif")]
+            OutputFormat::Avif => "avif",
+            #[cfg(feature = "bmp")]
+            OutputFormat::Bmp => "bmp",
+        }
+    }
+
+    /// Returns the format of a file extension, case-insensitive and without the dot,
+    /// or `None` if the extension is unknown or its format is not enabled.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "jpeg")]
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            #[cfg(feature = "png")]
+            "png" => Some(OutputFormat::Png),
+            #[cfg(feature = "webp")]
+            "webp" => Some(OutputFormat::WebP),
+            #[cfg(feature = "avif")]
+            "avif" => Some(OutputFormat::Avif),
+            #[cfg(feature = "bmp")]
+            "bmp" => Some(OutputFormat::Bmp),
+            _ => None,
+        }
+    }
+}
+
+impl Captcha {
+    /// Appends the verification code image encoded in the given format to the given buffer,
+    /// without an intermediate buffer.
+    /// params `quality` - specify image quality, see [`OutputFormat`] for the range of each format
+    pub fn encode_into(
+        &self,
+        buf: &mut Vec<u8>,
+        format: OutputFormat,
+        quality: u8,
+    ) -> Result<(), CaptchaError> {
+        self.write_to(buf, format, quality)
+    }
+
+    /// Returns the verification code image encoded in the given format as raw bytes,
+    /// e.g. to write it to a socket or a blob store without decoding a data URL.
+    /// params `quality` - specify image quality, see [`OutputFormat`] for the range of each format
+    pub fn to_bytes(&self, format: OutputFormat, quality: u8) -> Result<Vec<u8>, CaptchaError> {
+        let mut buf = Vec::new();
+        self.write_to(&mut buf, format, quality)?;
+        Ok(buf)
+    }
+
+    /// Saves the verification code image to a file, the format is inferred from the extension
+    /// of the path, see [`OutputFormat::from_extension`], with the default quality of the format.
+    /// Returns an error if the extension is unknown or the file can not be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CaptchaError> {
+        let path = path.as_ref();
+        let format = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(OutputFormat::from_extension)
+            .ok_or_else(|| {
+                CaptchaError::InvalidConfig(format!(
+                    "unsupported image file extension: {}",
+                    path.display()
+                ))
+            })?;
+        self.save_with(path, format, 0)
+    }
+
+    /// Saves the verification code image encoded in the given format to a file,
+    /// regardless of the extension of the path.
+    /// params `quality` - specify image quality, see [`OutputFormat`] for the range of each format
+    pub fn save_with(
+        &self,
+        path: impl AsRef<Path>,
+        format: OutputFormat,
+        quality: u8,
+    ) -> Result<(), CaptchaError> {
+        let path = path.as_ref();
+        let write_err = |err: std::io::Error| {
+            CaptchaError::Encode(format!("failed to write {}: {}", path.display(), err))
+        };
+        let mut w = BufWriter::new(File::create(path).map_err(write_err)?);
+        self.write_to(&mut w, format, quality)?;
+        w.flush().map_err(write_err)
+    }
+
+    /// Returns the verification code image encoded in the given format as a data URL,
+    /// e.g. `data:image/png;base64,...`.
+    /// params `quality` - specify image quality, see [`OutputFormat`] for the range of each format
+    pub fn to_data_url(&self, format: OutputFormat, quality: u8) -> Result<String, CaptchaError> {
+        let mut buf = String::new();
+        self.to_data_url_into(format, quality, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Returns the verification code image in WebP format as a data URL, the WebP counterpart
+    /// of [`Captcha::to_base64`].
+    /// params `quality` - 0 or 100 for lossless, 1-99 for near-lossless, see [`OutputFormat::WebP`]
+    #[cfg(feature = "webp")]
+    pub fn to_base64_webp(&self, quality: u8) -> Result<String, CaptchaError> {
+        self.to_data_url(OutputFormat::WebP, quality)
+    }
+
+    /// Appends the verification code image encoded in the given format as a data URL to the
+    /// given buffer, the image is encoded and base64 encoded in one pass.
+    pub fn to_data_url_into(
+        &self,
+        format: OutputFormat,
+        quality: u8,
+        buf: &mut String,
+    ) -> Result<(), CaptchaError> {
+        push_data_url_prefix(buf, format);
+        self.to_base64_raw_into(format, quality, Base64Alphabet::Standard, buf)
+    }
+
+    /// Returns the verification code image in base64 format like [`Captcha::to_base64`] with
+    /// the highest JPEG quality that keeps the data URL within `limit` bytes, e.g. for an
+    /// ingress message limit. If even the lowest quality exceeds the limit and `downscale` is
+    /// true, the image is downscaled to 3/4 and then 1/2 of its size before giving up.
+    /// Returns [`CaptchaError::PayloadTooLarge`] with the smallest size if nothing fits.
+    #[cfg(feature = "jpeg")]
+    pub fn to_base64_max_bytes(
+        &self,
+        limit: usize,
+        downscale: bool,
+    ) -> Result<String, CaptchaError> {
+        let (width, height) = self.image().dimensions();
+        let mut buf = String::new();
+        let mut best = String::new();
+        let mut smallest = usize::MAX;
+        // The scales of the image in quarters
+        let scales: &[u32] = if downscale { &[4, 3, 2] } else { &[4] };
+        for &scale in scales {
+            let scaled = (scale < 4).then(|| {
+                let image = match self.is_grayscale() {
+                    true => DynamicImage::ImageLuma8(self.luma_image()),
+                    false => DynamicImage::ImageRgb8(self.image().clone()),
+                };
+                image.resize_exact(
+                    (width * scale / 4).max(1),
+                    (height * scale / 4).max(1),
+                    ResizeFilter::Triangle,
+                )
+            });
+
+            // The encoded size decreases with the quality, search the highest quality that fits
+            let (mut lo, mut hi) = (10u8, 80u8);
+            while lo <= hi {
+                let quality = (lo + hi) / 2;
+                buf.clear();
+                match &scaled {
+                    Some(image) => {
+                        push_data_url_prefix(&mut buf, OutputFormat::Jpeg);
+                        let mut writer = EncoderStringWriter::from_consumer(
+                            &mut buf,
+                            &general_purpose::STANDARD,
+                        );
+                        JpegEncoder::new_with_quality(&mut writer, quality)
+                            .encode_image(image)
+                            .map_err(|err| CaptchaError::Encode(err.to_string()))?;
+                        writer.into_inner();
+                    }
+                    None => self.to_base64_into(quality, &mut buf)?,
+                }
+                smallest = smallest.min(buf.len());
+                if buf.len() <= limit {
+                    std::mem::swap(&mut best, &mut buf);
+                    lo = quality + 1;
+                } else {
+                    hi = quality - 1;
+                }
+            }
+            if !best.is_empty() {
+                return Ok(best);
+            }
+        }
+        Err(CaptchaError::PayloadTooLarge {
+            limit,
+            size: smallest,
+        })
+    }
+
+    /// Returns the verification code image encoded in the given format as bare base64,
+    /// without the `data:...;base64,` prefix, e.g. for JSON APIs.
+    /// params `quality` - specify image quality, see [`OutputFormat`] for the range of each format
+    pub fn to_base64_raw(
+        &self,
+        format: OutputFormat,
+        quality: u8,
+        alphabet: Base64Alphabet,
+    ) -> Result<String, CaptchaError> {
+        let mut buf = String::new();
+        self.to_base64_raw_into(format, quality, alphabet, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Appends the verification code image encoded in the given format as bare base64 to the
+    /// given buffer, the image is encoded and base64 encoded in one pass.
+    pub fn to_base64_raw_into(
+        &self,
+        format: OutputFormat,
+        quality: u8,
+        alphabet: Base64Alphabet,
+        buf: &mut String,
+    ) -> Result<(), CaptchaError> {
+        let mut writer = EncoderStringWriter::from_consumer(buf, alphabet.engine());
+        self.write_to(&mut writer, format, quality)?;
+        writer.into_inner();
+        Ok(())
+    }
+
+    /// Writes the verification code image encoded in the given format into the writer as it is
+    /// encoded, e.g. straight into an HTTP response body, without buffering the whole image.
+    /// The encoders issue many small writes, wrap unbuffered writers in a `BufWriter`.
+    /// params `quality` - specify image quality, see [`OutputFormat`] for the range of each format
+    #[cfg_attr(not(feature = "jpeg"), allow(unused_variables, unused_mut))]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "encode",
+            level = "debug",
+            skip_all,
+            fields(format = ?format, quality = quality),
+            err(level = "warn", Display)
+        )
+    )]
+    pub fn write_to<W: Write>(
+        &self,
+        w: W,
+        format: OutputFormat,
+        quality: u8,
+    ) -> Result<(), CaptchaError> {
+        match format {
+            #[cfg(feature = "jpeg")]
+            OutputFormat::Jpeg => {
+                let mut encoder = JpegEncoder::new_with_quality(w, jpeg_quality(quality));
+                match self.is_grayscale() {
+                    true => encoder.encode_image(&self.luma_image()),
+                    false => encoder.encode_image(self.image()),
+                }
+                .map_err(|err| CaptchaError::Encode(err.to_string()))
+            }
+            #[cfg(feature = "png")]
+            OutputFormat::Png => self.write_png_to(w, PngOptions::default()),
+            #[cfg(feature = "webp")]
+            OutputFormat::WebP => {
+                let (width, height) = self.image().dimensions();
+                let (mut data, color_type) = self.channels();
+                let bits = webp_rounded_bits(quality);
+                if bits > 0 {
+                    let half = 1u16 << (bits - 1);
+                    for c in data.to_mut().iter_mut() {
+                        *c = ((*c as u16 + half) >> bits << bits).min(255) as u8;
+                    }
+                }
+                WebPEncoder::new_lossless(w)
+                    .write_image(&data, width, height, color_type)
+                    .map_err(|err| CaptchaError::Encode(err.to_string()))
+            }
+            #[cfg(feature = "avif")]
+            OutputFormat::Avif => {
+                let image = self.image();
+                let pixels: Vec<ravif::RGB8> = image
+                    .pixels()
+                    .map(|p| ravif::RGB8::new(p[0], p[1], p[2]))
+                    .collect();
+                let encoded = ravif::Encoder::new()
+                    .with_quality(avif_quality(quality) as f32)
+                    .with_speed(AVIF_SPEED)
+                    .encode_rgb(ravif::Img::new(
+                        &pixels[..],
+                        image.width() as usize,
+                        image.height() as usize,
+                    ))
+                    .map_err(|err| CaptchaError::Encode(err.to_string()))?;
+                let mut w = w;
+                w.write_all(&encoded.avif_file)
+                    .map_err(|err| CaptchaError::Encode(err.to_string()))
+            }
+            #[cfg(feature = "bmp")]
+            OutputFormat::Bmp => {
+                let (image, mut w) = (self.image(), w);
+                BmpEncoder::new(&mut w)
+                    .write_image(
+                        image.as_raw(),
+                        image.width(),
+                        image.height(),
+                        image::ColorType::Rgb8,
+                    )
+                    .map_err(|err| CaptchaError::Encode(err.to_string()))
+            }
+        }
+    }
+}
+
+impl Captcha {
+    /// Returns the verification code image encoded as PNG with the given options.
+    #[cfg(feature = "png")]
+    pub fn to_png(&self, options: PngOptions) -> Result<Vec<u8>, CaptchaError> {
+        let mut buf = Vec::new();
+        self.write_png_to(&mut buf, options)?;
+        Ok(buf)
+    }
+
+    /// Writes the verification code image encoded as PNG with the given options into the
+    /// writer, see [`Captcha::write_to`].
+    #[cfg(feature = "png")]
+    pub fn write_png_to<W: Write>(&self, w: W, options: PngOptions) -> Result<(), CaptchaError> {
+        let compression = match options.compression {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        };
+        let filter = match options.filter {
+            PngFilter::None => FilterType::NoFilter,
+            PngFilter::Sub => FilterType::Sub,
+            PngFilter::Up => FilterType::Up,
+            PngFilter::Avg => FilterType::Avg,
+            PngFilter::Paeth => FilterType::Paeth,
+            PngFilter::Adaptive => FilterType::Adaptive,
+        };
+        let (width, height) = self.image().dimensions();
+        let (data, color_type) = self.channels();
+        PngEncoder::new_with_quality(w, compression, filter)
+            .write_image(&data, width, height, color_type)
+            .map_err(|err| CaptchaError::Encode(err.to_string()))
+    }
+
+    // Returns the pixels of the image for the lossless encoders: RGBA for a transparent
+    // captcha, a single channel for a grayscale captcha, RGB otherwise
+    #[cfg(any(feature = "png", feature = "webp"))]
+    fn channels(&self) -> (Cow<'_, [u8]>, image::ColorType) {
+        if self.is_transparent() {
+            (
+                Cow::Owned(self.rgba_image().into_raw()),
+                image::ColorType::Rgba8,
+            )
+        } else if self.is_grayscale() {
+            (
+                Cow::Owned(self.luma_image().into_raw()),
+                image::ColorType::L8,
+            )
+        } else {
+            (Cow::Borrowed(self.image().as_raw()), image::ColorType::Rgb8)
+        }
+    }
+}
+
+// Append the `data:<mime type>;base64,` prefix of a data URL of the format
+fn push_data_url_prefix(buf: &mut String, format: OutputFormat) {
+    buf.push_str("data:");
+    buf.push_str(format.mime_type());
+    buf.push_str(";base64,");
+}
+
+// The rav1e speed preset, 1-10, captchas are small so a fast preset is good enough
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 8;
+
+// Return the AVIF quality in range 1-100, default is 30
+#[cfg(feature = "avif")]
+pub(crate) fn avif_quality(quality: u8) -> u8 {
+    match quality {
+        0 => 30,
+        _ => quality.min(100),
+    }
+}
+
+// Return the low bits of every channel rounded away for the WebP quality,
+// 0 for lossless and 1-3 from high to low near-lossless quality
+#[cfg(feature = "webp")]
+pub(crate) fn webp_rounded_bits(quality: u8) -> u8 {
+    match quality {
+        0 | 100.. => 0,
+        67..=99 => 1,
+        34..=66 => 2,
+        _ => 3,
+    }
+}
+
+// Return the JPEG quality in range 10-80, default is 30
+#[cfg(feature = "jpeg")]
+pub(crate) fn jpeg_quality(quality: u8) -> u8 {
+    match quality {
+        0..=9 => 30,
+        10..=80 => quality,
+        _ => 80,
+    }
+}
+
+#[cfg(all(test, feature = "jpeg"))]
+mod tests {
+    use super::*;
+    use crate::CaptchaBuilder;
+
+    #[test]
+    fn it_encodes_into_vec() {
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let mut buf = vec![1u8, 2, 3];
+        captcha
+            .encode_into(&mut buf, OutputFormat::Jpeg, 30)
+            .unwrap();
+        assert_eq!(&buf[..3], &[1, 2, 3]);
+        assert_eq!(&buf[3..5], &[0xff, 0xd8]);
+        assert_eq!(OutputFormat::Jpeg.mime_type(), "image/jpeg");
+
+        let bytes = captcha.to_bytes(OutputFormat::Jpeg, 30).unwrap();
+        assert_eq!(bytes, &buf[3..]);
+    }
+
+    #[test]
+    fn it_encodes_bare_base64() {
+        use base64::Engine;
+
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let bytes = captcha.to_bytes(OutputFormat::Jpeg, 30).unwrap();
+        let raw = captcha
+            .to_base64_raw(OutputFormat::Jpeg, 30, Base64Alphabet::Standard)
+            .unwrap();
+        assert_eq!(raw, general_purpose::STANDARD.encode(&bytes));
+        assert_eq!(
+            captcha.to_base64(30),
+            format!("data:image/jpeg;base64,{}", raw)
+        );
+
+        let url_safe = captcha
+            .to_base64_raw(OutputFormat::Jpeg, 30, Base64Alphabet::UrlSafeNoPad)
+            .unwrap();
+        assert!(!url_safe.contains(['+', '/', '=']));
+        assert_eq!(
+            general_purpose::URL_SAFE_NO_PAD.decode(&url_safe).unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn it_fits_base64_within_max_bytes() {
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let best = captcha.to_base64(80);
+        assert_eq!(
+            captcha.to_base64_max_bytes(best.len(), false).unwrap(),
+            best
+        );
+
+        let limit = captcha.to_base64(40).len();
+        let fitted = captcha.to_base64_max_bytes(limit, false).unwrap();
+        assert!(fitted.len() <= limit);
+        assert!(fitted.len() > captcha.to_base64(30).len());
+
+        let lowest = captcha.to_base64(10).len();
+        let err = captcha.to_base64_max_bytes(lowest - 1, false).unwrap_err();
+        assert_eq!(
+            err,
+            CaptchaError::PayloadTooLarge {
+                limit: lowest - 1,
+                size: lowest
+            }
+        );
+        let scaled = captcha.to_base64_max_bytes(lowest - 1, true).unwrap();
+        assert!(scaled.starts_with("data:image/jpeg;base64,"));
+        assert!(scaled.len() < lowest);
+        assert!(matches!(
+            captcha.to_base64_max_bytes(100, true),
+            Err(CaptchaError::PayloadTooLarge { limit: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn it_writes_to_writer() {
+        struct Chunks(Vec<usize>);
+        impl Write for Chunks {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(buf.len());
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let mut chunks = Chunks(Vec::new());
+        captcha
+            .write_to(&mut chunks, OutputFormat::Jpeg, 30)
+            .unwrap();
+        assert!(chunks.0.len() > 1);
+        assert_eq!(
+            chunks.0.iter().sum::<usize>(),
+            captcha.to_bytes(OutputFormat::Jpeg, 30).unwrap().len()
+        );
+
+        let mut full = [0u8; 16];
+        assert!(matches!(
+            captcha.write_to(&mut full[..], OutputFormat::Jpeg, 30),
+            Err(CaptchaError::Encode(_))
+        ));
+    }
+
+    #[test]
+    fn it_saves_to_file() {
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let dir = std::env::temp_dir().join(format!("ic-captcha-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("captcha.JPG");
+        captcha.save(&path).unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            captcha.to_bytes(OutputFormat::Jpeg, 0).unwrap()
+        );
+
+        let path = dir.join("captcha.bin");
+        assert!(matches!(
+            captcha.save(&path),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+        captcha.save_with(&path, OutputFormat::Jpeg, 60).unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            captcha.to_bytes(OutputFormat::Jpeg, 60).unwrap()
+        );
+        assert!(matches!(
+            captcha.save_with(dir.join("missing/captcha.jpeg"), OutputFormat::Jpeg, 0),
+            Err(CaptchaError::Encode(_))
+        ));
+
+        assert_eq!(
+            OutputFormat::from_extension("jpeg"),
+            Some(OutputFormat::Jpeg)
+        );
+        assert_eq!(OutputFormat::from_extension("gif"), None);
+        assert_eq!(
+            OutputFormat::from_extension(OutputFormat::Jpeg.extension()),
+            Some(OutputFormat::Jpeg)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn it_encodes_png() {
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let mut buf = Vec::new();
+        captcha.encode_into(&mut buf, OutputFormat::Png, 0).unwrap();
+        assert_eq!(&buf[..8], b"\x89PNG\r\n\x1a\n");
+        let decoded = image::load_from_memory_with_format(&buf, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(&decoded, captcha.image());
+
+        let url = captcha.to_data_url(OutputFormat::Png, 0).unwrap();
+        assert!(url.starts_with("data:image/png;base64,iVBORw0KGgo"));
+        assert_eq!(
+            captcha.to_data_url(OutputFormat::Jpeg, 30).unwrap(),
+            captcha.to_base64(30)
+        );
+
+        assert_eq!(captcha.to_png(PngOptions::default()).unwrap(), buf);
+        let fast = captcha.to_png(PngOptions::fast()).unwrap();
+        let best = captcha.to_png(PngOptions::best()).unwrap();
+        assert!(best.len() < fast.len());
+        for png in [fast, best] {
+            let decoded = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+                .unwrap()
+                .to_rgb8();
+            assert_eq!(&decoded, captcha.image());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "bmp")]
+    fn it_encodes_bmp() {
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let bmp = captcha.to_bytes(OutputFormat::Bmp, 0).unwrap();
+        assert_eq!(&bmp[..2], b"BM");
+        // A 54 bytes header and the pixel rows padded to a multiple of 4 bytes
+        let (width, height) = captcha.image().dimensions();
+        let stride = (width as usize * 3).div_ceil(4) * 4;
+        assert_eq!(bmp.len(), 54 + stride * height as usize);
+        assert_eq!(u32::from_le_bytes(bmp[10..14].try_into().unwrap()), 54);
+
+        // The last pixel row of the image is the first row of the bitmap, in BGR order
+        let pixel = captcha.image().get_pixel(0, height - 1);
+        assert_eq!(&bmp[54..57], &[pixel[2], pixel[1], pixel[0]]);
+        assert_eq!(OutputFormat::Bmp.mime_type(), "image/bmp");
+    }
+
+    #[test]
+    #[cfg(feature = "avif")]
+    fn it_encodes_avif() {
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let avif = captcha.to_bytes(OutputFormat::Avif, 30).unwrap();
+        assert_eq!(&avif[4..12], b"ftypavif");
+        assert_eq!(avif, captcha.to_bytes(OutputFormat::Avif, 0).unwrap());
+        assert_eq!(OutputFormat::Avif.mime_type(), "image/avif");
+        assert!(captcha
+            .to_data_url(OutputFormat::Avif, 30)
+            .unwrap()
+            .starts_with("data:image/avif;base64,"));
+    }
+
+    #[test]
+    #[cfg(feature = "webp")]
+    fn it_encodes_webp() {
+        let captcha = CaptchaBuilder::new().generate(&[11u8, 32], None);
+        let mut lossless = Vec::new();
+        captcha
+            .encode_into(&mut lossless, OutputFormat::WebP, 0)
+            .unwrap();
+        assert_eq!(&lossless[..4], b"RIFF");
+        assert_eq!(&lossless[8..12], b"WEBP");
+        let decoded = image::load_from_memory_with_format(&lossless, image::ImageFormat::WebP)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(&decoded, captcha.image());
+
+        let mut near = Vec::new();
+        captcha
+            .encode_into(&mut near, OutputFormat::WebP, 20)
+            .unwrap();
+        assert!(near.len() < lossless.len());
+        let decoded = image::load_from_memory_with_format(&near, image::ImageFormat::WebP)
+            .unwrap()
+            .to_rgb8();
+        for (a, b) in decoded.as_raw().iter().zip(captcha.image().as_raw()) {
+            assert!(a.abs_diff(*b) <= 4);
+        }
+        assert!(captcha
+            .to_base64_webp(20)
+            .unwrap()
+            .starts_with("data:image/webp;base64,"));
+    }
+}
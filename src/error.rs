@@ -0,0 +1,90 @@
+use std::fmt;
+
+/// Errors returned by the fallible captcha APIs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error), uniffi(flat_error))]
+pub enum CaptchaError {
+    /// The image dimensions exceed the configured limits.
+    TooLarge {
+        width: u32,
+        height: u32,
+        max_width: u32,
+        max_height: u32,
+        max_pixels: u64,
+    },
+    /// The estimated generation work exceeds the configured budget.
+    BudgetExceeded { budget: u64, required: u64 },
+    /// The smallest encoded payload exceeds the size limit in bytes.
+    PayloadTooLarge { limit: usize, size: usize },
+    /// The font is unknown or invalid.
+    Font(String),
+    /// The configuration is invalid.
+    InvalidConfig(String),
+    /// Failed to encode the image.
+    Encode(String),
+    /// Failed to decode a serialized value.
+    Decode(String),
+    /// The challenge token is malformed.
+    InvalidToken(String),
+    /// The challenge token has expired.
+    TokenExpired,
+    /// The challenge token has been used before.
+    TokenSpent,
+    /// The answer does not match the challenge.
+    AnswerMismatch,
+    /// The generation task failed unexpectedly.
+    Internal(String),
+}
+
+impl CaptchaError {
+    /// Returns the HTTP status code used by the web framework integrations:
+    /// 403 for expired or used tokens and wrong answers, 500 for internal errors and 400 otherwise.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            CaptchaError::TokenExpired
+            | CaptchaError::TokenSpent
+            | CaptchaError::AnswerMismatch => 403,
+            CaptchaError::Internal(_) => 500,
+            _ => 400,
+        }
+    }
+}
+
+impl fmt::Display for CaptchaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptchaError::TooLarge {
+                width,
+                height,
+                max_width,
+                max_height,
+                max_pixels,
+            } => write!(
+                f,
+                "image size {}x{} exceeds the limits {}x{} or {} pixels",
+                width, height, max_width, max_height, max_pixels
+            ),
+            CaptchaError::BudgetExceeded { budget, required } => write!(
+                f,
+                "generation requires at least {} steps, exceeds the budget {}",
+                required, budget
+            ),
+            CaptchaError::PayloadTooLarge { limit, size } => write!(
+                f,
+                "encoded payload of {} bytes exceeds the limit {}",
+                size, limit
+            ),
+            CaptchaError::Font(reason) => write!(f, "font error: {}", reason),
+            CaptchaError::InvalidConfig(reason) => write!(f, "invalid config: {}", reason),
+            CaptchaError::Encode(reason) => write!(f, "failed to encode image: {}", reason),
+            CaptchaError::Decode(reason) => write!(f, "failed to decode: {}", reason),
+            CaptchaError::InvalidToken(reason) => write!(f, "invalid token: {}", reason),
+            CaptchaError::TokenExpired => write!(f, "token expired"),
+            CaptchaError::TokenSpent => write!(f, "token already used"),
+            CaptchaError::AnswerMismatch => write!(f, "answer mismatch"),
+            CaptchaError::Internal(reason) => write!(f, "internal error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CaptchaError {}
@@ -0,0 +1,208 @@
+//! A C ABI of the generator for non-Rust backends, the header `include/ic_captcha.h` is
+//! generated by cbindgen into the build output directory of the crate, and copied into
+//! `include/` when the ABI changes. Build the shared or static library with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`).
+//!
+//! ```c
+//! IccCaptcha *captcha = icc_generate(seed, seed_len, NULL, NULL);
+//! printf("%s\n", icc_text(captcha));
+//! size_t len = 0;
+//! uint8_t *jpeg = icc_to_jpeg(captcha, 30, &len);
+//! icc_free_bytes(jpeg, len);
+//! icc_free(captcha);
+//! ```
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    ptr, slice,
+};
+
+use crate::{Captcha, CaptchaBuilder, OutputFormat};
+
+/// The options of `icc_generate`, the defaults of `CaptchaBuilder::new` are used if NULL.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IccOptions {
+    pub length: u8,
+    pub width: u32,
+    pub height: u32,
+    pub mode: u8,
+    pub complexity: u32,
+}
+
+/// An opaque generated captcha, freed by `icc_free`.
+pub struct IccCaptcha {
+    captcha: Captcha,
+    text: CString,
+}
+
+/// Generates a captcha with the given random seed, options and text, returns NULL on failure.
+///
+/// # Safety
+///
+/// `seed` must point to `seed_len` readable bytes, `options` must be NULL or a valid
+/// `IccOptions`, and `text` must be NULL or a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn icc_generate(
+    seed: *const u8,
+    seed_len: usize,
+    options: *const IccOptions,
+    text: *const c_char,
+) -> *mut IccCaptcha {
+    let seed = if seed_len == 0 {
+        &[][..]
+    } else if seed.is_null() {
+        return ptr::null_mut();
+    } else {
+        slice::from_raw_parts(seed, seed_len)
+    };
+    let text = if text.is_null() {
+        None
+    } else {
+        match CStr::from_ptr(text).to_str() {
+            Ok(text) => Some(text.to_string()),
+            Err(_) => return ptr::null_mut(),
+        }
+    };
+
+    let mut builder = CaptchaBuilder::new();
+    if let Some(options) = options.as_ref() {
+        builder = builder
+            .length(options.length)
+            .width(options.width)
+            .height(options.height)
+            .mode(options.mode)
+            .complexity(options.complexity);
+    }
+    let captcha = match builder.try_generate(seed, text) {
+        Ok(captcha) => captcha,
+        Err(_) => return ptr::null_mut(),
+    };
+    let text = match CString::new(captcha.text()) {
+        Ok(text) => text,
+        Err(_) => return ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(IccCaptcha { captcha, text }))
+}
+
+/// Returns the verification code string, valid until the captcha is freed.
+///
+/// # Safety
+///
+/// `captcha` must be a pointer returned by `icc_generate` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn icc_text(captcha: *const IccCaptcha) -> *const c_char {
+    match captcha.as_ref() {
+        Some(captcha) => captcha.text.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Encodes the image in JPEG with the given quality (10-80, values out of
+/// the range are clamped), writes the length to `out_len`
+/// and returns the bytes, freed by `icc_free_bytes`. Returns NULL on failure.
+///
+/// # Safety
+///
+/// `captcha` must be a pointer returned by `icc_generate` and not freed yet,
+/// `out_len` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn icc_to_jpeg(
+    captcha: *const IccCaptcha,
+    quality: u8,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let Some(captcha) = captcha.as_ref() else {
+        return ptr::null_mut();
+    };
+    if out_len.is_null() {
+        return ptr::null_mut();
+    }
+    let mut buf = Vec::new();
+    if captcha
+        .captcha
+        .encode_into(&mut buf, OutputFormat::Jpeg, quality)
+        .is_err()
+    {
+        return ptr::null_mut();
+    }
+    let buf = buf.into_boxed_slice();
+    *out_len = buf.len();
+    Box::into_raw(buf) as *mut u8
+}
+
+/// Frees the bytes returned by `icc_to_jpeg`.
+///
+/// # Safety
+///
+/// `data` and `len` must be returned by `icc_to_jpeg` and not freed yet, or `data` is NULL.
+#[no_mangle]
+pub unsafe extern "C" fn icc_free_bytes(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Frees a captcha returned by `icc_generate`.
+///
+/// # Safety
+///
+/// `captcha` must be returned by `icc_generate` and not freed yet, or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn icc_free(captcha: *mut IccCaptcha) {
+    if !captcha.is_null() {
+        drop(Box::from_raw(captcha));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_the_header_up_to_date() {
+        assert_eq!(
+            include_str!(concat!(env!("OUT_DIR"), "/ic_captcha.h")),
+            include_str!("../include/ic_captcha.h"),
+            "copy the generated header into include/"
+        );
+    }
+
+    #[test]
+    fn it_generates_through_c_abi() {
+        let seed = b"random seed 0";
+        unsafe {
+            let captcha = icc_generate(seed.as_ptr(), seed.len(), ptr::null(), ptr::null());
+            assert!(!captcha.is_null());
+            let expected = CaptchaBuilder::new().generate(seed, None);
+            let text = CStr::from_ptr(icc_text(captcha)).to_str().unwrap();
+            assert_eq!(text, expected.text());
+
+            let mut len = 0;
+            let jpeg = icc_to_jpeg(captcha, 30, &mut len);
+            assert!(!jpeg.is_null());
+            let mut buf = Vec::new();
+            expected
+                .encode_into(&mut buf, OutputFormat::Jpeg, 30)
+                .unwrap();
+            assert_eq!(slice::from_raw_parts(jpeg, len), &buf[..]);
+            icc_free_bytes(jpeg, len);
+            icc_free(captcha);
+
+            let options = IccOptions {
+                length: 6,
+                width: 100_000,
+                height: 100_000,
+                mode: 0,
+                complexity: 1,
+            };
+            let captcha = icc_generate(seed.as_ptr(), seed.len(), &options, ptr::null());
+            assert!(captcha.is_null());
+
+            let captcha = icc_generate(seed.as_ptr(), seed.len(), ptr::null(), c"abcd".as_ptr());
+            assert!(!captcha.is_null());
+            assert_eq!(CStr::from_ptr(icc_text(captcha)).to_str().unwrap(), "abcd");
+            icc_free(captcha);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use base64::{engine::general_purpose, Engine};
+use sha3::{Digest, Sha3_256};
+use std::{fmt, str::FromStr};
+
+use crate::{Captcha, CaptchaBuilder, CaptchaError, CaptchaGenerator, ChallengeToken, SpentTokens};
+
+// Domain separation for the values derived from the secret key
+const ROUND_KEY_DOMAIN: &[u8] = b"ic-captcha:flow-round-key";
+const NONCE_DOMAIN: &[u8] = b"ic-captcha:flow-nonce";
+const SEED_DOMAIN: &[u8] = b"ic-captcha:flow-seed";
+const POW_DOMAIN: &[u8] = b"ic-captcha:flow-pow";
+
+// A round of a flow: the captcha generator and the proof-of-work difficulty in bits
+struct FlowRound {
+    generator: CaptchaGenerator,
+    pow_bits: u8,
+}
+
+/// A multi-round challenge flow with escalating friction, e.g. an easy image, then a hard
+/// image, then a hard image with a proof of work. A wrong answer escalates to the next round
+/// (the last round repeats), a right answer passes the flow.
+///
+/// The state of a round is a [`FlowState`] carrying the round number and a [`ChallengeToken`]
+/// signed with a key derived from the secret key and the round, so the client can not tamper
+/// with the state. The flow spends every state it verifies, right or wrong, and keeps it until
+/// it expires: a state is answered only once, so the client can not keep guessing an easier
+/// round or replay a round to get the same next round again. Verify all the answers of a flow
+/// with the same [`ChallengeFlow`].
+pub struct ChallengeFlow {
+    key: Vec<u8>,
+    ttl_ms: u64,
+    rounds: Vec<FlowRound>,
+    spent: SpentTokens,
+}
+
+/// The signed state of a round of a [`ChallengeFlow`], given to the client with the captcha
+/// and sent back with the answer. It is formatted in URL-safe base64 without padding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowState {
+    /// The round number, starting from 0.
+    pub round: u8,
+    /// The challenge token of the round.
+    pub token: ChallengeToken,
+}
+
+/// A round of a [`ChallengeFlow`] to be served to the client.
+pub struct FlowChallenge {
+    pub state: FlowState,
+    pub captcha: Captcha,
+    /// The proof-of-work difficulty in bits, 0 if the round requires no proof of work,
+    /// see [`solve_proof_of_work`].
+    pub pow_bits: u8,
+}
+
+/// The outcome of an answer of a [`ChallengeFlow`].
+// The outcome is moved once per answer, boxing the next round is not worth it
+#[allow(clippy::large_enum_variant)]
+pub enum FlowOutcome {
+    /// The answer is right, the flow is passed after the given number of rounds.
+    Passed { rounds: u8 },
+    /// The answer or the proof of work is wrong, the client gets the next round.
+    Next(FlowChallenge),
+}
+
+impl ChallengeFlow {
+    /// Returns a flow with the given first round, without proof of work.
+    /// `key` is the secret key of the round tokens, tokens expire after `ttl_ms` milliseconds.
+    pub fn new(key: &[u8], ttl_ms: u64, first: CaptchaBuilder) -> Self {
+        ChallengeFlow {
+            key: key.to_vec(),
+            ttl_ms,
+            rounds: vec![FlowRound {
+                generator: first.build(),
+                pow_bits: 0,
+            }],
+            spent: SpentTokens::new(),
+        }
+    }
+
+    /// Returns a flow with three escalating rounds: an easy image (difficulty 0.3),
+    /// a hard image (difficulty 0.9), and a hard image with a 16-bit proof of work.
+    pub fn escalating(key: &[u8], ttl_ms: u64) -> Self {
+        let hard = CaptchaBuilder::new().difficulty(0.9);
+        Self::new(key, ttl_ms, CaptchaBuilder::new().difficulty(0.3))
+            .then(hard.clone(), 0)
+            .then(hard, 16)
+    }
+
+    /// Appends a round escalated to after a wrong answer of the previous round.
+    /// `pow_bits` is the proof-of-work difficulty of the round, 0-32, 0 for none.
+    pub fn then(mut self, builder: CaptchaBuilder, pow_bits: u8) -> Self {
+        if self.rounds.len() < u8::MAX as usize {
+            self.rounds.push(FlowRound {
+                generator: builder.build(),
+                pow_bits: pow_bits.min(32),
+            });
+        }
+        self
+    }
+
+    /// Returns the number of rounds.
+    pub fn rounds(&self) -> usize {
+        self.rounds.len()
+    }
+
+    /// Starts a flow at the given time in unix milliseconds with the first round.
+    /// `seed` should come from a secure random source (e.g. `raw_rand` in a canister).
+    pub fn start(&self, seed: &[u8], now_ms: u64) -> FlowChallenge {
+        let nonce = self.derive(NONCE_DOMAIN, &[seed]);
+        self.issue(0, nonce, now_ms)
+    }
+
+    /// Verifies the answer of a round at the given time in unix milliseconds. `pow` is the
+    /// proof of work of the round, ignored by rounds without proof of work.
+    /// Returns an error if the state is invalid, expired or answered before, the flow should be
+    /// restarted.
+    pub fn verify(
+        &self,
+        state: &FlowState,
+        answer: &str,
+        pow: u64,
+        now_ms: u64,
+    ) -> Result<FlowOutcome, CaptchaError> {
+        let round = self
+            .rounds
+            .get(state.round as usize)
+            .ok_or_else(|| CaptchaError::InvalidToken(format!("unknown round {}", state.round)))?;
+        // A genuine state expires within the TTL, so a forged state can not stay spent longer
+        if state.token.expire_at > now_ms.saturating_add(self.ttl_ms) {
+            return Err(CaptchaError::InvalidToken(
+                "expiration beyond the TTL".to_string(),
+            ));
+        }
+        self.spent.spend(&state.token, now_ms)?;
+        let res = state
+            .token
+            .verify(&self.round_key(state.round), answer, now_ms)
+            .and_then(|_| {
+                if check_proof_of_work(&state.token.nonce, round.pow_bits, pow) {
+                    Ok(())
+                } else {
+                    Err(CaptchaError::AnswerMismatch)
+                }
+            });
+
+        match res {
+            Ok(()) => Ok(FlowOutcome::Passed {
+                rounds: state.round + 1,
+            }),
+            Err(CaptchaError::AnswerMismatch) => {
+                let next = (state.round as usize + 1).min(self.rounds.len() - 1) as u8;
+                let nonce = self.derive(
+                    NONCE_DOMAIN,
+                    &[&state.token.nonce, &[next], &now_ms.to_be_bytes()],
+                );
+                Ok(FlowOutcome::Next(self.issue(next, nonce, now_ms)))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn issue(&self, round: u8, nonce: [u8; 32], now_ms: u64) -> FlowChallenge {
+        let mut id = [0u8; 16];
+        id.copy_from_slice(&nonce[..16]);
+        let seed = self.derive(SEED_DOMAIN, &[&id]);
+        let flow_round = &self.rounds[round as usize];
+        let captcha = flow_round.generator.generate(&seed, None);
+        let token = ChallengeToken::new(
+            &self.round_key(round),
+            id,
+            now_ms.saturating_add(self.ttl_ms),
+            &captcha.answer(),
+        );
+        FlowChallenge {
+            state: FlowState { round, token },
+            captcha,
+            pow_bits: flow_round.pow_bits,
+        }
+    }
+
+    fn round_key(&self, round: u8) -> [u8; 32] {
+        self.derive(ROUND_KEY_DOMAIN, &[&[round]])
+    }
+
+    fn derive(&self, domain: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(domain);
+        hasher.update((self.key.len() as u64).to_be_bytes());
+        hasher.update(&self.key);
+        for part in parts {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// Returns the proof of work of a round with the given difficulty: the smallest number
+/// whose SHA3-256 hash with the token nonce has at least `bits` leading zero bits.
+/// Clients run it before sending the answer; it takes about 2^bits hashes.
+pub fn solve_proof_of_work(nonce: &[u8; 16], bits: u8) -> u64 {
+    (0..=u64::MAX)
+        .find(|pow| check_proof_of_work(nonce, bits, *pow))
+        .expect("a proof of work exists")
+}
+
+fn check_proof_of_work(nonce: &[u8; 16], bits: u8, pow: u64) -> bool {
+    if bits == 0 {
+        return true;
+    }
+    let mut hasher = Sha3_256::new();
+    hasher.update(POW_DOMAIN);
+    hasher.update(nonce);
+    hasher.update(pow.to_be_bytes());
+    let hash: [u8; 32] = hasher.finalize().into();
+    let mut prefix = [0u8; 4];
+    prefix.copy_from_slice(&hash[..4]);
+    u32::from_be_bytes(prefix).leading_zeros() >= bits as u32
+}
+
+/// Formats the state in URL-safe base64 without padding.
+impl fmt::Display for FlowState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut data = Vec::with_capacity(1 + ChallengeToken::LEN);
+        data.push(self.round);
+        data.extend_from_slice(&self.token.to_bytes());
+        f.write_str(&general_purpose::URL_SAFE_NO_PAD.encode(data))
+    }
+}
+
+/// Parses the state from URL-safe base64 without padding.
+impl FromStr for FlowState {
+    type Err = CaptchaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = general_purpose::URL_SAFE_NO_PAD
+            .decode(s.trim())
+            .map_err(|err| CaptchaError::InvalidToken(err.to_string()))?;
+        match data.split_first() {
+            Some((round, token)) => Ok(FlowState {
+                round: *round,
+                token: ChallengeToken::from_bytes(token)?,
+            }),
+            None => Err(CaptchaError::InvalidToken("empty flow state".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_escalates_rounds() {
+        let flow = ChallengeFlow::new(b"secret", 60_000, CaptchaBuilder::new().complexity(1))
+            .then(CaptchaBuilder::new().complexity(2), 0)
+            .then(CaptchaBuilder::new().complexity(3), 8);
+        assert_eq!(flow.rounds(), 3);
+
+        let first = flow.start(b"seed", 1000);
+        assert_eq!((first.state.round, first.pow_bits), (0, 0));
+        let Ok(FlowOutcome::Next(second)) = flow.verify(&first.state, "wrong", 0, 2000) else {
+            panic!("expected the next round");
+        };
+        assert_eq!(second.state.round, 1);
+        assert_ne!(second.state.token.nonce, first.state.token.nonce);
+
+        // a state is answered only once
+        assert!(matches!(
+            flow.verify(&first.state, &first.captcha.answer(), 0, 2000),
+            Err(CaptchaError::TokenSpent)
+        ));
+
+        // the round can not be skipped
+        let other = flow.start(b"other seed", 1000);
+        let forged = FlowState {
+            round: 2,
+            ..other.state.clone()
+        };
+        assert!(matches!(
+            flow.verify(&forged, &other.captcha.answer(), 0, 2000),
+            Ok(FlowOutcome::Next(_))
+        ));
+        let forged = FlowState {
+            token: ChallengeToken {
+                expire_at: 1_000_000,
+                ..other.state.token.clone()
+            },
+            ..other.state.clone()
+        };
+        assert!(matches!(
+            flow.verify(&forged, &other.captcha.answer(), 0, 2000),
+            Err(CaptchaError::InvalidToken(_))
+        ));
+
+        let Ok(FlowOutcome::Next(third)) = flow.verify(&second.state, "wrong", 0, 2000) else {
+            panic!("expected the next round");
+        };
+        assert_eq!((third.state.round, third.pow_bits), (2, 8));
+        let state: FlowState = third.state.to_string().parse().unwrap();
+        assert_eq!(state, third.state);
+
+        // the last round requires the proof of work and repeats
+        let answer = third.captcha.answer();
+        let pow = solve_proof_of_work(&state.token.nonce, 8);
+        let Ok(FlowOutcome::Next(again)) = flow.verify(&state, &answer, pow + 1, 2000) else {
+            panic!("expected the last round again");
+        };
+        assert_eq!(again.state.round, 2);
+        let answer = again.captcha.answer();
+        let pow = solve_proof_of_work(&again.state.token.nonce, 8);
+        assert!(matches!(
+            flow.verify(&again.state, &answer, pow, 2000),
+            Ok(FlowOutcome::Passed { rounds: 3 })
+        ));
+        assert!(matches!(
+            flow.verify(&again.state, &answer, pow, 2000),
+            Err(CaptchaError::TokenSpent)
+        ));
+        assert!(matches!(
+            flow.verify(&again.state, &answer, pow, 62_001),
+            Err(CaptchaError::TokenExpired)
+        ));
+    }
+}
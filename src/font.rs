@@ -0,0 +1,429 @@
+use ab_glyph::{Font, FontArc, FontRef, VariableFont};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use crate::{CaptchaError, FONTS};
+
+/// DejaVu Serif Bold, only contains the glyphs of [`crate::BASIC_CHARS`].
+#[cfg(feature = "font-serif")]
+static SERIF_FONT: &[u8] = include_bytes!("../fonts/dejavu-serif-bold-subset.ttf");
+
+/// DejaVu Sans Mono Bold, only contains the glyphs of [`crate::BASIC_CHARS`].
+#[cfg(feature = "font-mono")]
+static MONO_FONT: &[u8] = include_bytes!("../fonts/dejavu-sans-mono-bold-subset.ttf");
+
+/// DejaVu Sans Bold, only contains the glyphs of [`crate::BASIC_CHARS`].
+#[cfg(feature = "font-accessible")]
+static ACCESSIBLE_FONT: &[u8] = include_bytes!("../fonts/dejavu-sans-bold-subset.ttf");
+
+/// The fonts bundled with the crate, selectable with [`crate::CaptchaBuilder::bundled_font`].
+/// The alternatives are enabled by the `font-serif`, `font-mono` and `font-accessible` features,
+/// they only contain the glyphs of [`crate::BASIC_CHARS`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BundledFont {
+    /// Arial Rounded Bold, the default font.
+    #[default]
+    Default,
+    /// DejaVu Serif Bold.
+    #[cfg(feature = "font-serif")]
+    Serif,
+    /// DejaVu Sans Mono Bold.
+    #[cfg(feature = "font-mono")]
+    Mono,
+    /// DejaVu Sans Bold, a plain sans-serif used by [`crate::CaptchaBuilder::accessible`].
+    /// It is not designed for dyslexic readers, load such a font with
+    /// [`crate::CaptchaBuilder::fonts_from_bytes`] if needed.
+    #[cfg(feature = "font-accessible")]
+    Accessible,
+}
+
+impl BundledFont {
+    /// All fonts enabled in this build.
+    pub const ALL: &'static [BundledFont] = &[
+        BundledFont::Default,
+        #[cfg(feature = "font-serif")]
+        BundledFont::Serif,
+        #[cfg(feature = "font-mono")]
+        BundledFont::Mono,
+        #[cfg(feature = "font-accessible")]
+        BundledFont::Accessible,
+    ];
+
+    /// Returns the name of the font, used by [`FromStr`].
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BundledFont::Default => "default",
+            #[cfg(feature = "font-serif")]
+            BundledFont::Serif => "serif",
+            #[cfg(feature = "font-mono")]
+            BundledFont::Mono => "mono",
+            #[cfg(feature = "font-accessible")]
+            BundledFont::Accessible => "accessible",
+        }
+    }
+
+    /// Returns the font data.
+    pub fn data(&self) -> &'static [u8] {
+        match *self {
+            BundledFont::Default => FONTS,
+            #[cfg(feature = "font-serif")]
+            BundledFont::Serif => SERIF_FONT,
+            #[cfg(feature = "font-mono")]
+            BundledFont::Mono => MONO_FONT,
+            #[cfg(feature = "font-accessible")]
+            BundledFont::Accessible => ACCESSIBLE_FONT,
+        }
+    }
+
+    /// Returns the parsed font, it is parsed once and shared by all builders.
+    pub fn font(&self) -> FontArc {
+        static PARSED: [OnceLock<FontArc>; 4] = [const { OnceLock::new() }; 4];
+        PARSED[*self as usize]
+            .get_or_init(|| {
+                FontArc::try_from_slice(self.data()).expect("Invalid font for CaptchaBuilder")
+            })
+            .clone()
+    }
+}
+
+impl fmt::Display for BundledFont {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses a font by its name, case-insensitive.
+impl FromStr for BundledFont {
+    type Err = CaptchaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim();
+        BundledFont::ALL
+            .iter()
+            .find(|font| font.name().eq_ignore_ascii_case(name))
+            .copied()
+            .ok_or_else(|| CaptchaError::Font(format!("unknown bundled font {:?}", name)))
+    }
+}
+
+/// A registry of named fonts loaded at runtime, shared by the builders of a long-running service.
+/// Fonts can be replaced at any time, e.g. to rotate fonts or to serve per-tenant fonts;
+/// a builder resolves the font when [`crate::CaptchaBuilder::registry_font`] is called,
+/// so rebuild the builder to pick up a replaced font.
+/// The registry starts with the fonts of [`BundledFont::ALL`] under their names.
+pub struct FontRegistry {
+    fonts: RwLock<BTreeMap<String, FontArc>>,
+}
+
+impl Default for FontRegistry {
+    fn default() -> Self {
+        FontRegistry {
+            fonts: RwLock::new(
+                BundledFont::ALL
+                    .iter()
+                    .map(|font| (font.name().to_string(), font.font()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl FontRegistry {
+    /// Returns a registry with the bundled fonts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts or replaces the font with the given name.
+    pub fn insert(&self, name: impl Into<String>, font: FontArc) {
+        self.fonts
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(name.into(), font);
+    }
+
+    /// Inserts or replaces the font with the given name from TrueType or OpenType font data.
+    /// Returns an error if the data is not a valid font, the registered font is kept.
+    pub fn load_bytes(
+        &self,
+        name: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) -> Result<(), CaptchaError> {
+        self.insert(name, parse_font(data.into())?);
+        Ok(())
+    }
+
+    /// Inserts or replaces the font with the given name from a TrueType or OpenType font file.
+    /// Returns an error if the file can not be read or is not a valid font,
+    /// the registered font is kept.
+    pub fn load_path(
+        &self,
+        name: impl Into<String>,
+        path: impl AsRef<Path>,
+    ) -> Result<(), CaptchaError> {
+        self.insert(name, read_font(path.as_ref())?);
+        Ok(())
+    }
+
+    /// Removes the font with the given name, returns it if it was registered.
+    pub fn remove(&self, name: &str) -> Option<FontArc> {
+        self.fonts
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(name)
+    }
+
+    /// Returns the font with the given name.
+    pub fn get(&self, name: &str) -> Option<FontArc> {
+        self.fonts
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// Returns the names of the registered fonts in order.
+    pub fn names(&self) -> Vec<String> {
+        self.fonts
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .keys()
+            .cloned()
+            .collect()
+    }
+}
+
+// Parse TrueType or OpenType font data
+pub(crate) fn parse_font(data: Vec<u8>) -> Result<FontArc, CaptchaError> {
+    FontArc::try_from_vec(data)
+        .map_err(|err| CaptchaError::Font(format!("invalid font data: {}", err)))
+}
+
+// The tables copied from a borrowed rusttype font: the tables of the outlines, the metrics,
+// the layout and the variations
+#[cfg(feature = "rusttype")]
+const SFNT_TABLES: [&[u8; 4]; 33] = [
+    b"avar", b"CBDT", b"CBLC", b"CFF ", b"CFF2", b"cmap", b"COLR", b"CPAL", b"cvar", b"fvar",
+    b"GDEF", b"glyf", b"GPOS", b"GSUB", b"gvar", b"head", b"hhea", b"hmtx", b"HVAR", b"kern",
+    b"loca", b"maxp", b"MVAR", b"name", b"OS/2", b"post", b"sbix", b"STAT", b"SVG ", b"vhea",
+    b"vmtx", b"VORG", b"VVAR",
+];
+
+// Convert a rusttype font. Fonts owning their data are parsed again, the data of borrowed fonts
+// (e.g. from `rusttype::Font::try_from_bytes`) is not reachable, so a font is rebuilt from
+// their tables.
+#[cfg(feature = "rusttype")]
+pub(crate) fn from_rusttype(font: &rusttype::Font<'_>) -> Result<FontArc, CaptchaError> {
+    match font {
+        rusttype::Font::Owned(face) => parse_font(face.as_slice().to_vec()),
+        rusttype::Font::Ref(face) => {
+            let mut tables: Vec<(&[u8; 4], &[u8])> = SFNT_TABLES
+                .iter()
+                .filter_map(|tag| {
+                    let data = face.table_data(ttf_parser::Tag::from_bytes(tag))?;
+                    Some((*tag, data))
+                })
+                .collect();
+            // The table records are sorted by tag
+            tables.sort_by_key(|(tag, _)| **tag);
+            parse_font(build_sfnt(&tables))
+        }
+    }
+}
+
+// Build an OpenType font of the tables sorted by tag, the checksums are not computed
+#[cfg(feature = "rusttype")]
+fn build_sfnt(tables: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let cff = tables.iter().any(|(tag, _)| *tag == b"CFF ");
+    let num_tables = tables.len() as u16;
+    let entry_selector = num_tables.max(1).ilog2() as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+
+    let mut data = Vec::new();
+    data.extend_from_slice(if cff { b"OTTO" } else { &[0, 1, 0, 0] });
+    data.extend_from_slice(&num_tables.to_be_bytes());
+    data.extend_from_slice(&search_range.to_be_bytes());
+    data.extend_from_slice(&entry_selector.to_be_bytes());
+    data.extend_from_slice(&(num_tables * 16).saturating_sub(search_range).to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, table) in tables {
+        data.extend_from_slice(*tag);
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&(offset as u32).to_be_bytes());
+        data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += table.len().next_multiple_of(4);
+    }
+    for (_, table) in tables {
+        data.extend_from_slice(table);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+    data
+}
+
+// Returns true if the font has an outline of the character, subset fonts may map characters
+// to glyphs whose outlines were stripped
+pub(crate) fn has_glyph(font: &FontArc, c: char) -> bool {
+    let id = font.glyph_id(c);
+    id.0 != 0 && font.outline(id).is_some()
+}
+
+// Read and parse a TrueType or OpenType font file
+pub(crate) fn read_font(path: &Path) -> Result<FontArc, CaptchaError> {
+    let data = std::fs::read(path)
+        .map_err(|err| CaptchaError::Font(format!("failed to read {}: {}", path.display(), err)))?;
+    parse_font(data).map_err(|err| match err {
+        CaptchaError::Font(reason) => CaptchaError::Font(format!("{}: {}", path.display(), reason)),
+        err => err,
+    })
+}
+
+/// A variation axis of a variable font randomized per character between the bounds,
+/// see [`crate::CaptchaBuilder::variable_font`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontAxis {
+    /// The tag of the axis, e.g. `wght` or `wdth`.
+    pub tag: [u8; 4],
+    /// The minimum value of the axis.
+    pub min: f32,
+    /// The maximum value of the axis.
+    pub max: f32,
+}
+
+impl FontAxis {
+    /// Returns an axis with the given tag and bounds.
+    pub fn new(tag: [u8; 4], min: f32, max: f32) -> Self {
+        FontAxis { tag, min, max }
+    }
+
+    /// Returns the weight axis `wght` with the given bounds.
+    pub fn weight(min: f32, max: f32) -> Self {
+        Self::new(*b"wght", min, max)
+    }
+
+    /// Returns the width axis `wdth` with the given bounds.
+    pub fn width(min: f32, max: f32) -> Self {
+        Self::new(*b"wdth", min, max)
+    }
+}
+
+// A variable font with the axes to randomize, the bounds are clamped to the axis ranges
+#[derive(Clone)]
+pub(crate) struct VariableFace {
+    data: Arc<[u8]>,
+    axes: Vec<FontAxis>,
+}
+
+impl VariableFace {
+    pub(crate) fn new(data: Vec<u8>, axes: &[FontAxis]) -> Result<Self, CaptchaError> {
+        let font = FontRef::try_from_slice(&data)
+            .map_err(|err| CaptchaError::Font(format!("invalid font data: {}", err)))?;
+        let variations = font.variations();
+        let axes = axes
+            .iter()
+            .map(|axis| {
+                let v = variations
+                    .iter()
+                    .find(|v| v.tag == axis.tag)
+                    .ok_or_else(|| {
+                        CaptchaError::Font(format!(
+                            "no variation axis {:?}",
+                            String::from_utf8_lossy(&axis.tag)
+                        ))
+                    })?;
+                let min = axis.min.min(axis.max).clamp(v.min_value, v.max_value);
+                let max = axis.max.max(axis.min).clamp(v.min_value, v.max_value);
+                Ok(FontAxis::new(axis.tag, min, max))
+            })
+            .collect::<Result<Vec<_>, CaptchaError>>()?;
+        Ok(VariableFace {
+            data: data.into(),
+            axes,
+        })
+    }
+
+    // Returns the font at its default instance
+    pub(crate) fn font(&self) -> FontRef<'_> {
+        FontRef::try_from_slice(&self.data).expect("font data is validated")
+    }
+
+    // Set every axis of the font to a random value between its bounds
+    pub(crate) fn randomize<R>(&self, font: &mut FontRef<'_>, get_rnd: &mut R)
+    where
+        R: FnMut(u32) -> u32,
+    {
+        for axis in &self.axes {
+            let value = axis.min + (axis.max - axis.min) * get_rnd(1001) as f32 / 1000.0;
+            font.set_variation(&axis.tag, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_bundled_fonts() {
+        for font in BundledFont::ALL {
+            assert_eq!(font.name().parse::<BundledFont>().unwrap(), *font);
+            assert_eq!(
+                font.to_string()
+                    .to_uppercase()
+                    .parse::<BundledFont>()
+                    .unwrap(),
+                *font
+            );
+            // the default charset is covered
+            let parsed = font.font();
+            assert!(crate::BASIC_CHARS
+                .iter()
+                .all(|c| ab_glyph::Font::glyph_id(&parsed, *c).0 != 0));
+        }
+        assert!(matches!(
+            "fantasy".parse::<BundledFont>(),
+            Err(CaptchaError::Font(_))
+        ));
+    }
+
+    #[test]
+    fn it_registers_and_replaces_fonts() {
+        let registry = FontRegistry::new();
+        assert!(registry.names().contains(&"default".to_string()));
+        assert!(registry
+            .load_bytes("brand", b"not a font".as_slice())
+            .is_err());
+        assert!(registry.get("brand").is_none());
+
+        registry
+            .load_path("brand", "fonts/arial-rounded-bold-subset.ttf")
+            .unwrap();
+        let captcha = crate::CaptchaBuilder::new()
+            .registry_font(&registry, "brand")
+            .unwrap()
+            .generate(b"seed", None);
+        let expected = crate::CaptchaBuilder::new().generate(b"seed", None);
+        assert_eq!(captcha.image(), expected.image());
+
+        // replaced fonts are picked up by new builders
+        registry
+            .load_path("brand", "fonts/test/Cantarell-VF.otf")
+            .unwrap();
+        let captcha = crate::CaptchaBuilder::new()
+            .registry_font(&registry, "brand")
+            .unwrap()
+            .generate(b"seed", None);
+        assert_ne!(captcha.image(), expected.image());
+        assert!(registry.remove("brand").is_some());
+        assert!(matches!(
+            crate::CaptchaBuilder::new().registry_font(&registry, "brand"),
+            Err(CaptchaError::Font(_))
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{glyph::GlyphCache, Captcha, CaptchaBuilder, CaptchaError, ImagePool};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// The default number of pooled image buffers
+const POOL_SIZE: usize = 16;
+
+/// A prebuilt captcha generator created by [`CaptchaBuilder::build`].
+/// The charset glyphs are rasterized once, so generating is cheaper than with the builder,
+/// while the captchas are the same for the same seed.
+/// Image buffers of recycled captchas are pooled and reused by the next generations.
+pub struct CaptchaGenerator {
+    builder: CaptchaBuilder,
+    glyphs: GlyphCache,
+    pool: ImagePool,
+}
+
+impl CaptchaGenerator {
+    pub(crate) fn new(builder: CaptchaBuilder, glyphs: GlyphCache) -> Self {
+        CaptchaGenerator {
+            builder,
+            glyphs,
+            pool: ImagePool::new(POOL_SIZE),
+        }
+    }
+
+    /// Set the maximum number of pooled image buffers, default is 16.
+    pub fn pool_size(mut self, size: usize) -> Self {
+        self.pool = ImagePool::new(size);
+        self
+    }
+
+    /// Returns the pool of image buffers.
+    pub fn pool(&self) -> &ImagePool {
+        &self.pool
+    }
+
+    /// Returns the image buffer of a captcha that is no longer used to the pool.
+    /// Lazy captchas never rendered have no buffer to return.
+    pub fn recycle(&self, captcha: Captcha) {
+        if !captcha.is_rendered() {
+            return;
+        }
+        let (width, height) = captcha.image().dimensions();
+        self.pool.put(width, height, captcha.into_buffer());
+    }
+
+    /// Returns the builder this generator was built from.
+    pub fn builder(&self) -> &CaptchaBuilder {
+        &self.builder
+    }
+
+    /// Generate a [`Captcha`] with the given random seed and a optional text.
+    /// See [`CaptchaBuilder::generate`].
+    pub fn generate(&self, seed: &[u8], text: Option<String>) -> Captcha {
+        let (width, height) = self.builder.dimensions();
+        let mut captcha = Captcha::with_buffer(self.pool.take(width, height));
+        self.generate_into(&mut captcha, seed, text);
+        captcha
+    }
+
+    /// Generate a [`Captcha`] or returns an error if the limits or the work budget are exceeded.
+    /// See [`CaptchaBuilder::try_generate`].
+    pub fn try_generate(&self, seed: &[u8], text: Option<String>) -> Result<Captcha, CaptchaError> {
+        let (width, height) = self.builder.dimensions();
+        let mut captcha = Captcha::with_buffer(self.pool.take(width, height));
+        self.try_generate_into(&mut captcha, seed, text)?;
+        Ok(captcha)
+    }
+
+    /// Generate a batch of [`Captcha`]s with the given random seeds, one captcha per seed.
+    /// With the `parallel` feature, the batch is generated across threads.
+    pub fn generate_batch(&self, seeds: &[&[u8]]) -> Vec<Captcha> {
+        #[cfg(not(feature = "parallel"))]
+        let seeds = seeds.iter();
+        #[cfg(feature = "parallel")]
+        let seeds = seeds.par_iter();
+        seeds.map(|seed| self.generate(seed, None)).collect()
+    }
+
+    /// Generate a batch of [`Captcha`]s with the given random seeds and texts, one captcha per item.
+    pub fn generate_batch_with_texts(&self, items: &[(&[u8], &str)]) -> Vec<Captcha> {
+        #[cfg(not(feature = "parallel"))]
+        let items = items.iter();
+        #[cfg(feature = "parallel")]
+        let items = items.par_iter();
+        items
+            .map(|(seed, text)| self.generate(seed, Some(text.to_string())))
+            .collect()
+    }
+
+    /// Generate a captcha into an existing [`Captcha`], reusing its image buffer.
+    /// See [`CaptchaBuilder::generate_into`].
+    pub fn generate_into(&self, captcha: &mut Captcha, seed: &[u8], text: Option<String>) {
+        self.builder
+            .generate_into_with(captcha, seed, text, Some(&self.glyphs))
+    }
+
+    /// Generate a captcha into an existing [`Captcha`], reusing its image buffer.
+    /// See [`CaptchaBuilder::try_generate_into`].
+    pub fn try_generate_into(
+        &self,
+        captcha: &mut Captcha,
+        seed: &[u8],
+        text: Option<String>,
+    ) -> Result<(), CaptchaError> {
+        self.builder
+            .try_generate_into_with(captcha, seed, text, Some(&self.glyphs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CaptchaBuilder;
+    use ab_glyph::FontArc;
+
+    #[test]
+    fn it_generates_same_captchas_as_builder() {
+        let builder = CaptchaBuilder::new().length(6).complexity(3);
+        let generator = builder.clone().build();
+
+        let captcha = generator.generate(&[5u8, 32], None);
+        assert_eq!(captcha.text(), builder.generate(&[5u8, 32], None).text());
+        assert_eq!(captcha.image(), builder.generate(&[5u8, 32], None).image());
+
+        // characters out of the charset fall back to rasterizing, the subset font lacks them
+        let text = Some("IO0l".to_string());
+        #[cfg(not(feature = "full-font"))]
+        assert!(generator.try_generate(&[5u8, 32], text.clone()).is_err());
+        let full =
+            FontArc::try_from_slice(include_bytes!("../fonts/arial-rounded-bold.ttf")).unwrap();
+        let builder = builder.font(full);
+        let generator = builder.clone().build();
+        let captcha = generator.try_generate(&[5u8, 32], text.clone()).unwrap();
+        assert_eq!(captcha.image(), builder.generate(&[5u8, 32], text).image());
+    }
+
+    #[test]
+    fn it_reuses_pooled_buffers() {
+        let generator = CaptchaBuilder::new().build().pool_size(2);
+        let captcha = generator.generate(&[9u8, 32], None);
+        let expected = captcha.image().clone();
+        let ptr = captcha.image().as_ptr();
+
+        generator.recycle(captcha);
+        assert_eq!(generator.pool().len(140, 40), 1);
+        let captcha = generator.generate(&[9u8, 32], None);
+        assert!(generator.pool().is_empty(140, 40));
+        assert_eq!(captcha.image().as_ptr(), ptr);
+        assert_eq!(captcha.image(), &expected);
+    }
+}
@@ -0,0 +1,190 @@
+use ab_glyph::{point, Font, FontArc, GlyphId, PxScale, ScaleFont};
+use image::{ImageBuffer, Rgb};
+use std::collections::HashMap;
+
+// Define font size
+pub(crate) const SCALES: [PxScale; 3] = [
+    PxScale { x: 53.0, y: 50.0 },
+    PxScale { x: 45.0, y: 42.0 },
+    PxScale { x: 38.0, y: 35.0 },
+];
+
+// Return the index of the font scale for a text with the given length
+pub(crate) fn scale_index(len: usize) -> usize {
+    match len {
+        0..=4 => 0,
+        5..=6 => 1,
+        _ => 2,
+    }
+}
+
+// A rasterized glyph: coverage bitmap plus its offset from the drawing origin
+pub(crate) struct Glyph {
+    coverage: Vec<f32>,
+    width: u32,
+    min_x: i32,
+    min_y: i32,
+    // The height of the glyph box from the drawing origin, same as imageproc's text_size
+    pub(crate) text_height: i32,
+    // The SVG path data of the glyph outline from the drawing origin
+    #[cfg(feature = "svg")]
+    pub(crate) path: String,
+}
+
+impl Glyph {
+    pub(crate) fn rasterize<F: Font>(font: &F, scale: PxScale, c: char) -> Self {
+        Glyph::rasterize_id(font, scale, font.glyph_id(c))
+    }
+
+    // Rasterize the glyph of the given id, e.g. of a shaped run
+    pub(crate) fn rasterize_id<F: Font>(font: &F, scale: PxScale, id: GlyphId) -> Self {
+        let ascent = font.as_scaled(scale).ascent();
+        let mut glyph = Glyph {
+            coverage: Vec::new(),
+            width: 0,
+            min_x: 0,
+            min_y: 0,
+            text_height: 0,
+            #[cfg(feature = "svg")]
+            path: crate::svg::outline_path(font, scale, id),
+        };
+
+        let g = id.with_scale_and_position(scale, point(0.0, ascent));
+        if let Some(outlined) = font.outline_glyph(g) {
+            let bb = outlined.px_bounds();
+            glyph.width = bb.width() as u32;
+            glyph.min_x = bb.min.x as i32;
+            glyph.min_y = bb.min.y as i32;
+            glyph.text_height = (bb.max.y as i32).max(0);
+            glyph.coverage = vec![0.0; (bb.width() * bb.height()) as usize];
+            outlined.draw(|gx, gy, gv| {
+                glyph.coverage[(gy * glyph.width + gx) as usize] = gv;
+            });
+        }
+        glyph
+    }
+
+    // The left edge of the glyph box from the drawing origin
+    pub(crate) fn min_x(&self) -> i32 {
+        self.min_x
+    }
+
+    // The right edge of the glyph box from the drawing origin
+    pub(crate) fn right(&self) -> i32 {
+        self.min_x + self.width as i32
+    }
+
+    // Append the pixel indices of the image mostly covered by the glyph drawn at the given position
+    pub(crate) fn mask(
+        &self,
+        image_width: u32,
+        image_height: u32,
+        x: i32,
+        y: i32,
+        out: &mut Vec<u32>,
+    ) {
+        if self.width == 0 {
+            return;
+        }
+
+        let (ox, oy) = (x as i64 + self.min_x as i64, y as i64 + self.min_y as i64);
+        for (i, coverage) in self.coverage.iter().enumerate() {
+            let px = ox + (i as u32 % self.width) as i64;
+            let py = oy + (i as u32 / self.width) as i64;
+            if *coverage >= 0.5
+                && (0..image_width as i64).contains(&px)
+                && (0..image_height as i64).contains(&py)
+            {
+                out.push((py * image_width as i64 + px) as u32);
+            }
+        }
+    }
+
+    // Blend the glyph into the image at the given position with the given color
+    pub(crate) fn draw(
+        &self,
+        image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+        color: Rgb<u8>,
+        x: i32,
+        y: i32,
+    ) {
+        if self.width == 0 {
+            return;
+        }
+
+        // Clip the glyph box to the image, then blend row by row over contiguous memory
+        let (image_width, image_height) = (image.width() as i64, image.height() as i64);
+        let (glyph_width, glyph_height) = (
+            self.width as i64,
+            (self.coverage.len() / self.width as usize) as i64,
+        );
+        let (ox, oy) = (x as i64 + self.min_x as i64, y as i64 + self.min_y as i64);
+        let (x0, x1) = ((-ox).max(0), glyph_width.min(image_width - ox));
+        let (y0, y1) = ((-oy).max(0), glyph_height.min(image_height - oy));
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        let pixels: &mut [u8] = image;
+        for gy in y0..y1 {
+            let row = (gy * glyph_width) as usize;
+            let start = (((oy + gy) * image_width + ox + x0) * 3) as usize;
+            let len = (x1 - x0) as usize;
+            blend_row(
+                &mut pixels[start..start + len * 3],
+                &self.coverage[row + x0 as usize..row + x1 as usize],
+                color,
+            );
+        }
+    }
+}
+
+// Blend a color into a row of RGB pixels with the coverage values
+fn blend_row(pixels: &mut [u8], coverage: &[f32], color: Rgb<u8>) {
+    let color = [color[0] as f32, color[1] as f32, color[2] as f32];
+    for (pixel, gv) in pixels.chunks_exact_mut(3).zip(coverage.iter()) {
+        for (p, c) in pixel.iter_mut().zip(color.iter()) {
+            *p = (*p as f32 * (1.0 - gv) + c * gv) as u8;
+        }
+    }
+}
+
+// Glyphs of a charset pre-rasterized with all fonts at all font scales
+pub(crate) struct GlyphCache {
+    glyphs: HashMap<(usize, char, usize), Glyph>,
+}
+
+impl GlyphCache {
+    pub(crate) fn new(fonts: &[FontArc], charset: &[char]) -> Self {
+        let mut glyphs = HashMap::with_capacity(fonts.len() * charset.len() * SCALES.len());
+        for (fi, font) in fonts.iter().enumerate() {
+            for &c in charset {
+                for (i, scale) in SCALES.iter().enumerate() {
+                    glyphs.insert((fi, c, i), Glyph::rasterize(font, *scale, c));
+                }
+            }
+        }
+        GlyphCache { glyphs }
+    }
+
+    pub(crate) fn get(&self, font_index: usize, c: char, scale_index: usize) -> Option<&Glyph> {
+        self.glyphs.get(&(font_index, c, scale_index))
+    }
+
+    // The approximate heap size of the cached glyphs in bytes
+    pub(crate) fn heap_bytes(&self) -> u64 {
+        let entry = std::mem::size_of::<((usize, char, usize), Glyph)>();
+        let coverage: usize = self
+            .glyphs
+            .values()
+            .map(|glyph| {
+                #[cfg(feature = "svg")]
+                let path = glyph.path.capacity();
+                #[cfg(not(feature = "svg"))]
+                let path = 0;
+                glyph.coverage.capacity() * std::mem::size_of::<f32>() + path
+            })
+            .sum();
+        (self.glyphs.capacity() * entry + coverage) as u64
+    }
+}
@@ -0,0 +1,164 @@
+//! A tonic gRPC service issuing challenges and verifying answers,
+//! generated from `proto/captcha.proto`.
+//!
+//! ```rust,ignore
+//! let service = ChallengeGrpc::new(Arc::new(pool)).into_server();
+//! tonic::transport::Server::builder()
+//!     .add_service(service)
+//!     .serve(addr)
+//!     .await?;
+//! ```
+
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+use crate::{token::now_ms, CaptchaError, ChallengePool, ChallengeToken, OutputFormat};
+
+/// The messages and the service trait generated from `proto/captcha.proto`.
+#[allow(clippy::all)]
+pub mod pb {
+    tonic::include_proto!("ic_captcha.v1");
+}
+
+use pb::{
+    challenge_service_server::{ChallengeService, ChallengeServiceServer},
+    IssueChallengeRequest, IssueChallengeResponse, VerifyAnswerRequest, VerifyAnswerResponse,
+};
+
+/// The `ChallengeService` implementation backed by a [`ChallengePool`].
+/// Captcha images are rendered and encoded in JPEG on tokio's blocking thread pool.
+/// A token verifies only once, it is spent by
+/// [`ChallengePool::verify_once`].
+#[derive(Clone)]
+pub struct ChallengeGrpc {
+    pool: Arc<ChallengePool>,
+}
+
+impl ChallengeGrpc {
+    /// Returns a service issuing challenges from the pool.
+    pub fn new(pool: Arc<ChallengePool>) -> Self {
+        ChallengeGrpc { pool }
+    }
+
+    /// Returns the tonic server of the service.
+    pub fn into_server(self) -> ChallengeServiceServer<Self> {
+        ChallengeServiceServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl ChallengeService for ChallengeGrpc {
+    async fn issue_challenge(
+        &self,
+        _request: Request<IssueChallengeRequest>,
+    ) -> Result<Response<IssueChallengeResponse>, Status> {
+        // render and encode on tokio's blocking thread pool, not on the executor
+        let pool = self.pool.clone();
+        let (challenge, image) = tokio::task::spawn_blocking(move || {
+            let challenge = pool.issue(now_ms());
+            let mut image = Vec::new();
+            challenge
+                .captcha
+                .encode_into(&mut image, OutputFormat::Jpeg, 30)?;
+            pool.observe_encoded(image.len());
+            Ok::<_, CaptchaError>((challenge, image))
+        })
+        .await
+        .map_err(|err| Status::internal(err.to_string()))?
+        .map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(IssueChallengeResponse {
+            image,
+            mime_type: OutputFormat::Jpeg.mime_type().to_string(),
+            token: challenge.token.to_string(),
+            expire_at: challenge.token.expire_at,
+        }))
+    }
+
+    async fn verify_answer(
+        &self,
+        request: Request<VerifyAnswerRequest>,
+    ) -> Result<Response<VerifyAnswerResponse>, Status> {
+        let req = request.into_inner();
+        let token: ChallengeToken = req
+            .token
+            .parse()
+            .map_err(|err: CaptchaError| Status::invalid_argument(err.to_string()))?;
+        let res = match self.pool.verify_once(&token, &req.answer, now_ms()) {
+            Ok(()) => VerifyAnswerResponse {
+                valid: true,
+                reason: String::new(),
+            },
+            Err(err) => VerifyAnswerResponse {
+                valid: false,
+                reason: err.to_string(),
+            },
+        };
+        Ok(Response::new(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CaptchaBuilder;
+
+    fn new_pool() -> ChallengePool {
+        ChallengePool::new(
+            CaptchaBuilder::new().build(),
+            b"secret",
+            &[9u8; 32],
+            1,
+            60_000,
+        )
+    }
+
+    #[tokio::test]
+    async fn it_issues_and_verifies_challenges() {
+        let service = ChallengeGrpc::new(Arc::new(new_pool()));
+        let res = service
+            .issue_challenge(Request::new(IssueChallengeRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.mime_type, "image/jpeg");
+        assert!(!res.image.is_empty());
+        let answer = new_pool().issue(0).captcha.text();
+
+        let verify = |answer: &str| VerifyAnswerRequest {
+            token: res.token.clone(),
+            answer: answer.to_string(),
+        };
+        let ok = service
+            .verify_answer(Request::new(verify(&answer)))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(ok.valid);
+
+        let wrong = service
+            .verify_answer(Request::new(verify("wrong")))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!wrong.valid);
+        assert_eq!(wrong.reason, "answer mismatch");
+
+        // the token verifies only once
+        let replay = service
+            .verify_answer(Request::new(verify(&answer)))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!replay.valid);
+        assert_eq!(replay.reason, "token already used");
+
+        let err = service
+            .verify_answer(Request::new(VerifyAnswerRequest {
+                token: "abc".to_string(),
+                answer,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
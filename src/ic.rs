@@ -0,0 +1,514 @@
+use base64::{engine::general_purpose, Engine};
+use ic_certification::{Hash, HashTree};
+use ic_http_certification::{
+    DefaultCelBuilder, DefaultResponseCertification, HttpCertification, HttpCertificationPath,
+    HttpCertificationTree, HttpCertificationTreeEntry,
+};
+use std::collections::BTreeMap;
+
+use crate::{
+    BundledFont, Captcha, CaptchaBuilder, CaptchaError, ChallengeToken, OutputFormat,
+    DEFAULT_MAX_HEIGHT, DEFAULT_MAX_PIXELS, DEFAULT_MAX_WIDTH,
+};
+
+// The CBOR tag of self-described data, the gateway expects it on the tree and the expression path
+const CBOR_SELF_DESCRIBED: u64 = 55799;
+
+/// The certificate expression of certified captcha responses for the IC HTTP gateway
+/// (response verification v2): the request is not certified, the response is certified
+/// with its `content-type` and `cache-control` headers. It is the expression
+/// [`CertifiedChallenges`] certifies the responses with.
+pub const CERTIFICATE_EXPRESSION: &str = "default_certification(ValidationArgs{certification:Certification{no_request_certification:Empty{},response_certification:ResponseCertification{certified_response_headers:ResponseHeaderList{headers:[\"content-type\",\"cache-control\"]}}}})";
+
+/// An HTTP response of the `http_request` method of the IC HTTP gateway interface.
+/// The fields match the `HttpResponse` record of the interface, so it maps one to one
+/// to the candid type declared by the canister.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub upgrade: Option<bool>,
+}
+
+/// The captcha configuration of a canister admin endpoint. The fields match the record
+/// below, so it maps one to one to the candid type declared by the canister:
+///
+/// ```candid
+/// type CaptchaConfig = record {
+///   length : nat8;
+///   width : nat32;
+///   height : nat32;
+///   mode : nat8;
+///   complexity : nat32;
+///   letter_spacing : opt nat32;
+///   font : opt text;
+///   work_budget : opt nat64;
+/// };
+/// ```
+///
+/// With the `candid` feature it derives `CandidType` and `Deserialize` of that record, so it
+/// can be the argument of the endpoint directly. Convert it with
+/// `CaptchaBuilder::try_from(config)`, which validates every field instead of falling back
+/// to the defaults like the builder setters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "candid", derive(candid::CandidType, serde::Deserialize))]
+pub struct CaptchaConfig {
+    /// The length of the text, 1-10.
+    pub length: u8,
+    /// The width of the image, 61-2048.
+    pub width: u32,
+    /// The height of the image, 21-1024, at most 1048576 pixels with the width.
+    pub height: u32,
+    /// The color mode, 0-2.
+    pub mode: u8,
+    /// The complexity, 1-10.
+    pub complexity: u32,
+    /// The minimum gap between characters, less than the width.
+    pub letter_spacing: Option<u32>,
+    /// The name of a [`BundledFont`].
+    pub font: Option<String>,
+    /// The work budget of the generation, greater than 0.
+    pub work_budget: Option<u64>,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        CaptchaConfig {
+            length: 4,
+            width: 140,
+            height: 40,
+            mode: 1,
+            complexity: 5,
+            letter_spacing: None,
+            font: None,
+            work_budget: None,
+        }
+    }
+}
+
+impl TryFrom<CaptchaConfig> for CaptchaBuilder {
+    type Error = CaptchaError;
+
+    fn try_from(config: CaptchaConfig) -> Result<Self, Self::Error> {
+        let check = |valid: bool, field: &str, value: &dyn std::fmt::Debug| {
+            if valid {
+                Ok(())
+            } else {
+                Err(CaptchaError::InvalidConfig(format!(
+                    "{} is out of range: {:?}",
+                    field, value
+                )))
+            }
+        };
+        check((1..=10).contains(&config.length), "length", &config.length)?;
+        check(
+            (61..=DEFAULT_MAX_WIDTH).contains(&config.width),
+            "width",
+            &config.width,
+        )?;
+        check(
+            (21..=DEFAULT_MAX_HEIGHT).contains(&config.height),
+            "height",
+            &config.height,
+        )?;
+        check(
+            config.width as u64 * config.height as u64 <= DEFAULT_MAX_PIXELS,
+            "pixels",
+            &(config.width as u64 * config.height as u64),
+        )?;
+        check(config.mode <= 2, "mode", &config.mode)?;
+        check(
+            (1..=10).contains(&config.complexity),
+            "complexity",
+            &config.complexity,
+        )?;
+        check(
+            config
+                .letter_spacing
+                .is_none_or(|spacing| spacing < config.width),
+            "letter_spacing",
+            &config.letter_spacing,
+        )?;
+        check(
+            config.work_budget != Some(0),
+            "work_budget",
+            &config.work_budget,
+        )?;
+        let font = match &config.font {
+            Some(name) => name
+                .parse::<BundledFont>()
+                .map_err(|err| CaptchaError::InvalidConfig(err.to_string()))?,
+            None => BundledFont::Default,
+        };
+
+        Ok(CaptchaBuilder::new()
+            .length(config.length)
+            .width(config.width)
+            .height(config.height)
+            .mode(config.mode)
+            .complexity(config.complexity)
+            .letter_spacing(config.letter_spacing)
+            .bundled_font(font)
+            .work_budget(config.work_budget))
+    }
+}
+
+impl Captcha {
+    /// Returns an [`HttpResponse`] with the image encoded in the given format,
+    /// the `content-type` of the format and `cache-control: no-store`.
+    pub fn to_http_response(
+        &self,
+        format: OutputFormat,
+        quality: u8,
+    ) -> Result<HttpResponse, CaptchaError> {
+        let mut body = Vec::new();
+        self.encode_into(&mut body, format, quality)?;
+        Ok(HttpResponse {
+            status_code: 200,
+            headers: vec![
+                ("content-type".to_string(), format.mime_type().to_string()),
+                ("cache-control".to_string(), "no-store".to_string()),
+                ("content-length".to_string(), body.len().to_string()),
+            ],
+            body,
+            upgrade: None,
+        })
+    }
+
+    /// Returns an [`HttpResponse`] like [`Captcha::to_http_response`] with the
+    /// `ic-certificateexpression` header set to [`CERTIFICATE_EXPRESSION`].
+    /// The gateway rejects it until the canister certifies it with
+    /// [`CertifiedChallenges::insert`] and adds the `ic-certificate` header with
+    /// [`CertifiedChallenges::certify`].
+    pub fn to_certified_http_response(
+        &self,
+        format: OutputFormat,
+        quality: u8,
+    ) -> Result<HttpResponse, CaptchaError> {
+        let mut res = self.to_http_response(format, quality)?;
+        res.headers.push((
+            "ic-certificateexpression".to_string(),
+            CERTIFICATE_EXPRESSION.to_string(),
+        ));
+        Ok(res)
+    }
+}
+
+/// The certified responses of the issued challenges for the response verification v2 of the
+/// IC HTTP gateway. Every challenge is served at its own URL path, e.g. `/captcha/<id>`, with
+/// a response of [`Captcha::to_certified_http_response`] certified in an `http_expr` tree.
+/// Set [`CertifiedChallenges::root_hash`] as the certified data of the canister after every
+/// change, and return the response through [`CertifiedChallenges::certify`] from the
+/// `http_request` query, so that the dynamically generated captcha images pass the response
+/// verification. The tree is the whole certified data, a canister certifying other data
+/// should build its own tree.
+#[derive(Default)]
+pub struct CertifiedChallenges {
+    tree: HttpCertificationTree,
+    entries: BTreeMap<[u8; 16], (String, HttpCertification)>,
+}
+
+impl CertifiedChallenges {
+    /// Returns an empty tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Certifies the response of a challenge served at the URL path, replacing the previous
+    /// one of the challenge. Returns an error if the response does not carry the
+    /// `ic-certificateexpression` header of [`CERTIFICATE_EXPRESSION`].
+    pub fn insert(
+        &mut self,
+        token: &ChallengeToken,
+        path: &str,
+        response: &HttpResponse,
+    ) -> Result<(), CaptchaError> {
+        let certification =
+            HttpCertification::response_only(&expression(), &response.to_certification(), None)
+                .map_err(|err| CaptchaError::InvalidConfig(err.to_string()))?;
+        self.remove(token);
+        self.tree.insert(&HttpCertificationTreeEntry::new(
+            HttpCertificationPath::exact(path),
+            certification,
+        ));
+        self.entries
+            .insert(token.nonce, (path.to_string(), certification));
+        Ok(())
+    }
+
+    /// Removes a challenge, e.g. after it is verified or expired.
+    pub fn remove(&mut self, token: &ChallengeToken) {
+        if let Some((path, certification)) = self.entries.remove(&token.nonce) {
+            self.tree.delete(&HttpCertificationTreeEntry::new(
+                HttpCertificationPath::exact(path),
+                certification,
+            ));
+        }
+    }
+
+    /// Returns true if the challenge is certified.
+    pub fn contains(&self, token: &ChallengeToken) -> bool {
+        self.entries.contains_key(&token.nonce)
+    }
+
+    /// Returns the root hash to set as the certified data of the canister.
+    pub fn root_hash(&self) -> Hash {
+        self.tree.root_hash()
+    }
+
+    /// Returns the witness of a challenge and its expression path, None if it is not certified.
+    pub fn witness(&self, token: &ChallengeToken) -> Option<(HashTree, Vec<String>)> {
+        let (path, certification) = self.entries.get(&token.nonce)?;
+        let entry =
+            HttpCertificationTreeEntry::new(HttpCertificationPath::exact(path), *certification);
+        let witness = self.tree.witness(&entry, path).ok()?;
+        Some((witness, entry.path.to_expr_path()))
+    }
+
+    /// Adds the `ic-certificate` header of the response verification v2 to the certified
+    /// response of a challenge, with the certificate of the canister (from `data_certificate`),
+    /// the witness and the expression path of the challenge. Returns an error if the challenge
+    /// is not certified.
+    pub fn certify(
+        &self,
+        token: &ChallengeToken,
+        mut response: HttpResponse,
+        certificate: &[u8],
+    ) -> Result<HttpResponse, CaptchaError> {
+        let (witness, expr_path) = self.witness(token).ok_or_else(|| {
+            CaptchaError::InvalidToken(format!("challenge {} is not certified", token.id()))
+        })?;
+        response.headers.push((
+            "ic-certificate".to_string(),
+            format!(
+                "certificate=:{}:, tree=:{}:, expr_path=:{}:, version=2",
+                general_purpose::STANDARD.encode(certificate),
+                general_purpose::STANDARD.encode(self_described(&witness)?),
+                general_purpose::STANDARD.encode(self_described(&expr_path)?)
+            ),
+        ));
+        Ok(response)
+    }
+}
+
+impl HttpResponse {
+    // Convert the response for the certification
+    fn to_certification(&self) -> ic_http_certification::HttpResponse {
+        ic_http_certification::HttpResponse {
+            status_code: self.status_code,
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            upgrade: self.upgrade,
+        }
+    }
+}
+
+// Return the expression of the certified captcha responses
+fn expression() -> ic_http_certification::DefaultResponseOnlyCelExpression<'static> {
+    DefaultCelBuilder::response_only_certification()
+        .with_response_certification(DefaultResponseCertification::certified_response_headers(
+            vec!["content-type", "cache-control"],
+        ))
+        .build()
+}
+
+// Encode the value in CBOR with the self-described tag
+fn self_described<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, CaptchaError> {
+    let mut buf = Vec::new();
+    ciborium::into_writer(
+        &ciborium::tag::Required::<_, CBOR_SELF_DESCRIBED>(value),
+        &mut buf,
+    )
+    .map_err(|err| CaptchaError::Encode(err.to_string()))?;
+    Ok(buf)
+}
+
+#[cfg(all(test, feature = "jpeg"))]
+mod tests {
+    use super::*;
+    use crate::CaptchaBuilder;
+
+    #[test]
+    fn it_certifies_challenges() {
+        let captcha = CaptchaBuilder::new().generate(b"seed", None);
+        let response = captcha
+            .to_certified_http_response(OutputFormat::Jpeg, 30)
+            .unwrap();
+        let token = ChallengeToken::new(b"secret", [4u8; 16], 1000, "abcd");
+        let other = ChallengeToken::new(b"secret", [5u8; 16], 1000, "efgh");
+        let mut certified = CertifiedChallenges::new();
+        let empty = certified.root_hash();
+        certified
+            .insert(&token, &format!("/captcha/{}", token.id()), &response)
+            .unwrap();
+        certified
+            .insert(&other, &format!("/captcha/{}", other.id()), &response)
+            .unwrap();
+        assert_ne!(certified.root_hash(), empty);
+        assert!(certified.contains(&token));
+
+        let (witness, expr_path) = certified.witness(&token).unwrap();
+        assert_eq!(witness.digest(), certified.root_hash());
+        assert_eq!(
+            expr_path,
+            ["http_expr", "captcha", &token.id().to_string(), "<$>"]
+        );
+
+        let res = certified
+            .certify(&token, response.clone(), b"cert")
+            .unwrap();
+        let header = &res.headers.last().unwrap();
+        assert_eq!(header.0, "ic-certificate");
+        let fields: Vec<&str> = header.1.split(", ").collect();
+        assert_eq!(fields[0], "certificate=:Y2VydA==:");
+        assert!(fields[1].starts_with("tree=:2dn3"));
+        assert!(fields[2].starts_with("expr_path=:2dn3"));
+        assert_eq!(fields[3], "version=2");
+        let expr_path_cbor = general_purpose::STANDARD
+            .decode(
+                fields[2]
+                    .trim_start_matches("expr_path=:")
+                    .trim_end_matches(':'),
+            )
+            .unwrap();
+        let decoded: ciborium::tag::Required<Vec<String>, CBOR_SELF_DESCRIBED> =
+            ciborium::from_reader(&expr_path_cbor[..]).unwrap();
+        assert_eq!(decoded.0, expr_path);
+
+        // responses without the certificate expression can not be certified
+        let plain = captcha.to_http_response(OutputFormat::Jpeg, 30).unwrap();
+        assert!(matches!(
+            certified.insert(&token, "/captcha", &plain),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+
+        certified.remove(&token);
+        assert!(!certified.contains(&token));
+        assert!(certified.certify(&token, response, b"cert").is_err());
+        certified.remove(&other);
+        assert_eq!(certified.root_hash(), empty);
+    }
+
+    #[test]
+    fn it_builds_from_validated_config() {
+        let config = CaptchaConfig {
+            length: 6,
+            mode: 2,
+            letter_spacing: Some(4),
+            font: Some("default".to_string()),
+            ..Default::default()
+        };
+        let captcha = CaptchaBuilder::try_from(config)
+            .unwrap()
+            .generate(b"seed", None);
+        let expected = CaptchaBuilder::new()
+            .length(6)
+            .mode(2)
+            .letter_spacing(Some(4))
+            .generate(b"seed", None);
+        assert_eq!(captcha.image(), expected.image());
+
+        for config in [
+            CaptchaConfig {
+                length: 0,
+                ..Default::default()
+            },
+            CaptchaConfig {
+                width: 4096,
+                ..Default::default()
+            },
+            CaptchaConfig {
+                width: 2048,
+                height: 1024,
+                ..Default::default()
+            },
+            CaptchaConfig {
+                mode: 3,
+                ..Default::default()
+            },
+            CaptchaConfig {
+                complexity: 11,
+                ..Default::default()
+            },
+            CaptchaConfig {
+                letter_spacing: Some(140),
+                ..Default::default()
+            },
+            CaptchaConfig {
+                font: Some("fantasy".to_string()),
+                ..Default::default()
+            },
+            CaptchaConfig {
+                work_budget: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                CaptchaBuilder::try_from(config),
+                Err(CaptchaError::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "candid")]
+    #[test]
+    fn it_decodes_candid_config() {
+        use candid::{CandidType, Decode, Encode};
+
+        let config = CaptchaConfig {
+            letter_spacing: Some(4),
+            font: Some("default".to_string()),
+            work_budget: Some(1000),
+            ..Default::default()
+        };
+        let data = Encode!(&config).unwrap();
+        assert_eq!(Decode!(&data, CaptchaConfig).unwrap(), config);
+
+        let ty = CaptchaConfig::ty().to_string();
+        for field in [
+            "length : nat8",
+            "width : nat32",
+            "mode : nat8",
+            "complexity : nat32",
+            "letter_spacing : opt nat32",
+            "font : opt text",
+            "work_budget : opt nat64",
+        ] {
+            assert!(ty.contains(field), "{} in {}", field, ty);
+        }
+    }
+
+    #[test]
+    fn it_builds_http_responses() {
+        let captcha = CaptchaBuilder::new().generate(&[16u8, 32], None);
+        let res = captcha.to_http_response(OutputFormat::Jpeg, 30).unwrap();
+        assert_eq!(res.status_code, 200);
+        assert_eq!(
+            res.headers[..2],
+            [
+                ("content-type".to_string(), "image/jpeg".to_string()),
+                ("cache-control".to_string(), "no-store".to_string())
+            ]
+        );
+        let mut body = Vec::new();
+        captcha
+            .encode_into(&mut body, OutputFormat::Jpeg, 30)
+            .unwrap();
+        assert_eq!(res.body, body);
+
+        // the header is the expression the challenges are certified with
+        assert_eq!(expression().to_string(), CERTIFICATE_EXPRESSION);
+        let certified = captcha
+            .to_certified_http_response(OutputFormat::Jpeg, 30)
+            .unwrap();
+        assert_eq!(certified.body, body);
+        assert_eq!(
+            certified.headers.last().unwrap(),
+            &(
+                "ic-certificateexpression".to_string(),
+                CERTIFICATE_EXPRESSION.to_string()
+            )
+        );
+    }
+}
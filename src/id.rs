@@ -0,0 +1,112 @@
+use sha3::{Digest, Sha3_256};
+use std::{fmt, str::FromStr};
+
+use crate::CaptchaError;
+
+// Domain separation for the ids derived from the captcha seeds
+const ID_DOMAIN: &[u8] = b"ic-captcha:challenge-id";
+
+// Crockford's base32 alphabet, the same as ULID
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The id of a challenge, correlating its captcha, token, store entries and hook events in logs.
+/// It is derived from the captcha seed in a separate domain, so it is deterministic for a seed
+/// but unpredictable without it, and it is the nonce of the tokens issued by
+/// [`crate::ChallengePool`]. Like a ULID, it has 128 bits and is formatted in 26 characters
+/// of Crockford's base32.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChallengeId(pub [u8; 16]);
+
+impl ChallengeId {
+    /// The length of the formatted id.
+    pub const LEN: usize = 26;
+
+    /// Returns the id of the captcha generated with the given seed.
+    pub fn from_seed(seed: &[u8]) -> Self {
+        let mut hasher = Sha3_256::new();
+        hasher.update(ID_DOMAIN);
+        hasher.update(seed);
+        let hash: [u8; 32] = hasher.finalize().into();
+        let mut id = [0u8; 16];
+        id.copy_from_slice(&hash[..16]);
+        ChallengeId(id)
+    }
+
+    /// Returns the first 8 characters of the formatted id, as stamped by
+    /// [`crate::ChallengePool::stamp_ids`].
+    pub fn short(&self) -> String {
+        self.to_string()[..8].to_string()
+    }
+}
+
+impl fmt::Display for ChallengeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = u128::from_be_bytes(self.0);
+        let mut buf = [0u8; Self::LEN];
+        for (i, c) in buf.iter_mut().enumerate() {
+            *c = ALPHABET[((value >> (125 - 5 * i)) & 31) as usize];
+        }
+        f.write_str(std::str::from_utf8(&buf).expect("the alphabet is ascii"))
+    }
+}
+
+/// Parses the id case-insensitively, `I` and `L` are read as `1` and `O` as `0`.
+impl FromStr for ChallengeId {
+    type Err = CaptchaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.len() != Self::LEN {
+            return Err(CaptchaError::Decode(format!(
+                "expected {} characters, got {}",
+                Self::LEN,
+                s.len()
+            )));
+        }
+
+        let mut value = 0u128;
+        for (i, c) in s.bytes().enumerate() {
+            let c = match c.to_ascii_uppercase() {
+                b'I' | b'L' => b'1',
+                b'O' => b'0',
+                c => c,
+            };
+            let digit = ALPHABET
+                .iter()
+                .position(|a| *a == c)
+                .filter(|d| i > 0 || *d < 8)
+                .ok_or_else(|| CaptchaError::Decode(format!("invalid character at {}", i)))?;
+            value = (value << 5) | digit as u128;
+        }
+        Ok(ChallengeId(value.to_be_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_formats_challenge_ids() {
+        let id = ChallengeId::from_seed(b"seed");
+        assert_eq!(id, ChallengeId::from_seed(b"seed"));
+        assert_ne!(id, ChallengeId::from_seed(b"other seed"));
+
+        let s = id.to_string();
+        assert_eq!(s.len(), ChallengeId::LEN);
+        assert_eq!(s.parse::<ChallengeId>().unwrap(), id);
+        assert_eq!(s.to_lowercase().parse::<ChallengeId>().unwrap(), id);
+        assert_eq!(id.short(), s[..8]);
+
+        assert_eq!(
+            ChallengeId([0xff; 16]).to_string(),
+            "7ZZZZZZZZZZZZZZZZZZZZZZZZZ"
+        );
+        assert_eq!(
+            "0000000000000000000000000O".parse::<ChallengeId>().unwrap(),
+            ChallengeId::default()
+        );
+        assert!("8ZZZZZZZZZZZZZZZZZZZZZZZZZ".parse::<ChallengeId>().is_err());
+        assert!("0000000000000000000000000U".parse::<ChallengeId>().is_err());
+    }
+}
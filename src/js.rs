@@ -0,0 +1,116 @@
+use wasm_bindgen::prelude::*;
+
+use crate::CaptchaBuilder;
+
+/// The options of [`generate`], the defaults are the same as [`CaptchaBuilder::new`].
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct CaptchaOptions {
+    pub length: u8,
+    pub width: u32,
+    pub height: u32,
+    pub mode: u8,
+    pub complexity: u32,
+    pub compression: u8,
+    text: Option<String>,
+}
+
+impl Default for CaptchaOptions {
+    fn default() -> Self {
+        CaptchaOptions {
+            length: 4,
+            width: 140,
+            height: 40,
+            mode: 1,
+            complexity: 5,
+            compression: 30,
+            text: None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl CaptchaOptions {
+    /// Returns the default options.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The text of the captcha, a random text is generated from the seed if not set.
+    #[wasm_bindgen(getter)]
+    pub fn text(&self) -> Option<String> {
+        self.text.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_text(&mut self, text: Option<String>) {
+        self.text = text;
+    }
+}
+
+/// A captcha generated by [`generate`].
+#[wasm_bindgen]
+pub struct GeneratedCaptcha {
+    text: String,
+    image_data_url: String,
+}
+
+#[wasm_bindgen]
+impl GeneratedCaptcha {
+    /// The verification code string.
+    #[wasm_bindgen(getter)]
+    pub fn text(&self) -> String {
+        self.text.clone()
+    }
+
+    /// The verification code image as a JPEG data URL.
+    #[wasm_bindgen(getter, js_name = imageDataUrl)]
+    pub fn image_data_url(&self) -> String {
+        self.image_data_url.clone()
+    }
+}
+
+/// Generates a captcha with the given random seed and options,
+/// bit-for-bit the same as [`CaptchaBuilder::generate`] with the same configuration.
+#[wasm_bindgen]
+pub fn generate(seed: &[u8], options: Option<CaptchaOptions>) -> GeneratedCaptcha {
+    let options = options.unwrap_or_default();
+    let captcha = CaptchaBuilder::new()
+        .length(options.length)
+        .width(options.width)
+        .height(options.height)
+        .mode(options.mode)
+        .complexity(options.complexity)
+        .generate(seed, options.text);
+    GeneratedCaptcha {
+        text: captcha.text(),
+        image_data_url: captcha.to_base64(options.compression),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_generates_same_captcha_as_builder() {
+        let captcha = generate(b"seed", None);
+        let expected = CaptchaBuilder::new().generate(b"seed", None);
+        assert_eq!(captcha.text(), expected.text());
+        assert_eq!(captcha.image_data_url(), expected.to_base64(30));
+
+        let mut options = CaptchaOptions::new();
+        options.mode = 2;
+        options.set_text(Some("abcd".to_string()));
+        let captcha = generate(b"seed", Some(options));
+        assert_eq!(captcha.text(), "abcd");
+        assert_eq!(
+            captcha.image_data_url(),
+            CaptchaBuilder::new()
+                .mode(2)
+                .generate(b"seed", Some("abcd".to_string()))
+                .to_base64(30)
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::OnceLock;
+
+use crate::{Captcha, CaptchaBuilder, ChallengeId, Rnd};
+
+// The deferred render of a lazy captcha, done once on the first access of its image
+pub(crate) struct LazyRender {
+    builder: CaptchaBuilder,
+    seed: Vec<u8>,
+    text: Option<String>,
+    // The label stamped after rendering, see `Captcha::stamp`
+    pub(crate) stamp: Option<String>,
+    rendered: OnceLock<Captcha>,
+}
+
+impl LazyRender {
+    // Returns the rendered captcha, rendering it on the first call
+    pub(crate) fn get(&self) -> &Captcha {
+        self.rendered.get_or_init(|| self.render())
+    }
+
+    pub(crate) fn is_rendered(&self) -> bool {
+        self.rendered.get().is_some()
+    }
+
+    // Consume the render and return the rendered captcha, rendering it if not yet done
+    pub(crate) fn into_rendered(self) -> Captcha {
+        match self.rendered.get() {
+            Some(_) => self.rendered.into_inner().expect("checked above"),
+            None => self.render(),
+        }
+    }
+
+    fn render(&self) -> Captcha {
+        let mut captcha = self.builder.generate(&self.seed, self.text.clone());
+        if let Some(label) = &self.stamp {
+            captcha.stamp(label);
+        }
+        captcha
+    }
+}
+
+impl CaptchaBuilder {
+    /// Generate a [`Captcha`] like [`CaptchaBuilder::generate`], but only the answer text is
+    /// derived now; the image is rendered on the first access of [`Captcha::image`], when it is
+    /// encoded, or explicitly with [`Captcha::render`]. Servers can mint and store challenges
+    /// cheaply and only pay the render cost when the client actually fetches the image.
+    /// The rendered captcha is the same as generated with [`CaptchaBuilder::generate`].
+    pub fn generate_lazy(&self, seed: &[u8], text: Option<String>) -> Captcha {
+        // The same draws as the first stage of the render
+        let mut rnd = Rnd::new(seed);
+        let mut get_rnd = |num: u32| rnd.rnd_32(num);
+        let code = match &text {
+            Some(text) => text.clone(),
+            None => {
+                let mut code = Vec::new();
+                self.random_code(&mut get_rnd, &mut code);
+                code.into_iter().collect()
+            }
+        };
+        let mut captcha = Captcha::lazy(
+            &code,
+            ChallengeId::from_seed(seed),
+            LazyRender {
+                builder: self.clone(),
+                seed: seed.to_vec(),
+                text,
+                stamp: None,
+                rendered: OnceLock::new(),
+            },
+        );
+        captcha.set_answer(self.answer_of(&mut get_rnd, &code));
+        captcha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_renders_lazily() {
+        let builder = CaptchaBuilder::new().length(6);
+        let expected = builder.generate(b"seed", None);
+
+        let mut captcha = builder.generate_lazy(b"seed", None);
+        assert!(!captcha.is_rendered());
+        assert_eq!(captcha.text(), expected.text());
+        assert_eq!(captcha.id(), expected.id());
+        assert_eq!(captcha.image(), expected.image());
+        assert!(captcha.is_rendered());
+        captcha.render();
+        assert_eq!(captcha.image(), expected.image());
+        assert_eq!(captcha.char_colors(), expected.char_colors());
+
+        let mut captcha = builder.generate_lazy(b"seed", Some("LDCLabs".to_string()));
+        assert_eq!(captcha.text(), "LDCLabs");
+        captcha.stamp("abc");
+        assert!(!captcha.is_rendered());
+        let mut expected = builder.generate(b"seed", Some("LDCLabs".to_string()));
+        expected.stamp("abc");
+        captcha.render();
+        assert_eq!(captcha.image(), expected.image());
+    }
+}
@@ -17,22 +17,239 @@
 //! println!("base_img: {}", captcha.to_base64(30));
 //! ```
 
+#[cfg(feature = "animation")]
+mod animation;
+mod answer;
+#[cfg(feature = "tokio")]
+mod asynchronous;
+#[cfg(any(not(target_arch = "wasm32"), feature = "ic"))]
+pub mod bench;
+mod budget;
+mod bundle;
 mod captcha;
+mod challenge;
+mod charset;
+mod diff;
+mod draw;
+mod encode;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod flow;
+mod font;
+mod generator;
+mod glyph;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "ic")]
+mod ic;
+mod id;
+#[cfg(feature = "js")]
+mod js;
+mod lazy;
+mod memory;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+#[cfg(feature = "noise")]
+mod noise;
+mod otp;
+mod pictogram;
+mod pool;
+mod profile;
+#[cfg(feature = "rocket")]
+mod rocket;
+mod shape;
+#[cfg(feature = "shaping")]
+mod shaping;
+mod spelled;
+#[cfg(feature = "stable-structures")]
+mod stable;
+mod stream;
+#[cfg(feature = "svg")]
+mod svg;
+mod theme;
+mod token;
+#[cfg(feature = "tower")]
+mod tower;
+#[cfg(feature = "warp")]
+mod warp;
+mod words;
 
-use captcha::Captcha;
+pub use ab_glyph;
+use ab_glyph::FontArc;
+pub use answer::{AnswerHash, PepperRing};
+use budget::WorkBudget;
+pub use bundle::ChallengeBundle;
+pub use captcha::{Captcha, Interference};
+pub use challenge::{Challenge, ChallengeEvent, ChallengePool};
+pub use charset::{CasePolicy, CharPolicy, ConfusablePolicy};
+pub use diff::diff_score;
+pub use encode::{Base64Alphabet, OutputFormat};
+#[cfg(feature = "png")]
+pub use encode::{PngCompression, PngFilter, PngOptions};
+pub use error::CaptchaError;
+pub use flow::{solve_proof_of_work, ChallengeFlow, FlowChallenge, FlowOutcome, FlowState};
+use font::VariableFace;
+pub use font::{BundledFont, FontAxis, FontRegistry};
+pub use generator::CaptchaGenerator;
+use glyph::GlyphCache;
+#[cfg(feature = "ic")]
+pub use ic::{CaptchaConfig, CertifiedChallenges, HttpResponse, CERTIFICATE_EXPRESSION};
+#[cfg(feature = "ic")]
+pub use ic_certification;
+pub use id::ChallengeId;
+#[cfg(feature = "js")]
+pub use js::{generate, CaptchaOptions, GeneratedCaptcha};
+pub use memory::MemoryUsage;
+#[cfg(feature = "metrics")]
+pub use metrics::CaptchaMetrics;
+pub use otp::OneTimeCode;
+pub use pictogram::{PictogramAnswer, PictogramSet};
+pub use pool::ImagePool;
+pub use profile::ProfileRegistry;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "rocket")]
+pub use rocket::{CaptchaForm, CaptchaGuard};
 use sha3::{Digest, Sha3_256};
+pub use shape::{Border, CanvasShape};
+pub use spelled::NumberWords;
+use std::path::Path;
+use std::sync::Arc;
+pub use stream::CaptchaStream;
+pub use theme::{ColorScheme, Theme};
+pub use token::{ChallengeToken, SpentTokens};
+#[cfg(feature = "tower")]
+pub use tower::{CaptchaLayer, CaptchaService, ResponseFuture};
+#[cfg(feature = "warp")]
+pub use warp::{issue_filter, verify_filter, CaptchaRejection};
+pub use words::WordList;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+/// The default font used to generate the captcha image.
+/// It only contains the glyphs of [`BASIC_CHARS`], enable the `full-font` feature
+/// to embed the full font for custom texts.
+#[cfg(not(feature = "full-font"))]
+pub static FONTS: &[u8] = include_bytes!("../fonts/arial-rounded-bold-subset.ttf");
 
 /// The default font used to generate the captcha image.
+#[cfg(feature = "full-font")]
 pub static FONTS: &[u8] = include_bytes!("../fonts/arial-rounded-bold.ttf");
 
+/// The default characters of the verification code, easily confused characters are excluded.
+pub const BASIC_CHARS: &[char] = &captcha::BASIC_CHAR;
+
+/// The digits of the numeric verification codes, 0 and 1 are excluded as they are easily
+/// confused with O and I, see [`CaptchaBuilder::numeric`].
+pub const DIGIT_CHARS: &[char] = &['2', '3', '4', '5', '6', '7', '8', '9'];
+
+/// Cyrillic characters of localized verification codes, see [`CaptchaBuilder::charset`].
+/// Letters confused with Latin letters or digits, such as `А`, `В`, `Р`, `С`, `З` and `Б`,
+/// are excluded. They need a font covering them, set with [`CaptchaBuilder::fonts_from_bytes`].
+pub const CYRILLIC_CHARS: &[char] = &[
+    'Г', 'Д', 'Ж', 'И', 'Л', 'П', 'Ф', 'Ц', 'Ч', 'Ш', 'Ъ', 'Ы', 'Э', 'Ю', 'Я', 'д', 'ж', 'л', 'ф',
+    'ц', 'ч', 'ш', 'ъ', 'ы', 'э', 'ю', 'я',
+];
+
+/// Greek characters of localized verification codes, see [`CaptchaBuilder::charset`].
+/// Letters confused with Latin letters, such as `Α`, `Η`, `Ρ`, `ο`, `ν` and `χ`, are excluded.
+/// They need a font covering them, set with [`CaptchaBuilder::fonts_from_bytes`].
+pub const GREEK_CHARS: &[char] = &[
+    'Γ', 'Δ', 'Θ', 'Λ', 'Ξ', 'Π', 'Σ', 'Φ', 'Ψ', 'Ω', 'β', 'δ', 'ζ', 'θ', 'λ', 'ξ', 'π', 'σ', 'φ',
+    'ψ',
+];
+
+// The maximum side length of an image supported by the JPEG encoder
+const MAX_SIDE: u32 = u16::MAX as u32;
+
+/// The default maximum width of the verification code image.
+pub const DEFAULT_MAX_WIDTH: u32 = 2048;
+/// The default maximum height of the verification code image.
+pub const DEFAULT_MAX_HEIGHT: u32 = 1024;
+/// The default maximum pixel count of the verification code image, about 3MB of RGB data.
+pub const DEFAULT_MAX_PIXELS: u64 = 1024 * 1024;
+
+/// A captcha with its accessibility-grade variant of the same answer,
+/// see [`CaptchaBuilder::generate_pair`].
+pub struct CaptchaPair {
+    pub standard: Captcha,
+    pub accessible: Captcha,
+}
+
 /// A builder struct for creating a [`Captcha`].
+#[derive(Clone)]
 pub struct CaptchaBuilder {
-    fonts: rusttype::Font<'static>,
+    // The first font is the primary font, the others are only used with mixed fonts
+    fonts: Vec<FontArc>,
+    mixed_fonts: bool,
+    variable_font: Option<VariableFace>,
+    letter_spacing: Option<u32>,
+    // The characters of the random verification codes
+    charset: Arc<[char]>,
+    // The letter case of the random verification codes
+    case_policy: CasePolicy,
+    // The characters excluded from the random verification codes
+    excluded: Arc<[char]>,
+    // The handling of the charset characters confused with each other
+    confusables: ConfusablePolicy,
+    // Re-sample characters identical or similar to the previous one
+    no_adjacent_repeats: bool,
+    // The composition of the random verification codes
+    char_policy: CharPolicy,
+    // Alternate consonants and vowels in the random verification codes
+    pronounceable: bool,
+    // The number of words of the word-based codes, 0 for random codes of the charset
+    words: u8,
+    word_list: Option<WordList>,
+    // The pictograms drawn instead of the charset and the answer expected for them
+    pictograms: Option<(PictogramSet, PictogramAnswer)>,
+    // The words the digits of the spelled numbers are drawn as
+    number_words: Option<NumberWords>,
+    // Draw with the largest glyph scale regardless of the text length
+    large_glyphs: bool,
+    // The minimum visible fraction of every character after the interference stages
+    readability: Option<f32>,
+    // Draw the interference and noise with cheap approximations
+    fast: bool,
+    // Draw every character on its own tinted and rotated tile
+    char_tiles: bool,
+    // Draw the characters as the absence of a dense pattern
+    negative_space: bool,
+    // The interference drawn over the characters
+    interference: Interference,
+    // Render with gray levels only
+    grayscale: bool,
+    // Make the background transparent in the RGBA outputs
+    transparent: bool,
+    // The strength of the JPEG re-encoding pass at the end of the pipeline, 0.0-1.0
+    #[cfg(feature = "jpeg")]
+    quality_jitter: Option<f32>,
+    // The animation frame rendered, frames after the first re-draw the interference
+    #[cfg(feature = "animation")]
+    frame: u32,
+    // The overall difficulty of the pipeline, 0.0-1.0
+    difficulty: Option<f32>,
+    // The colors replacing the palettes of the mode
+    theme: Option<Arc<Theme>>,
+    // The shape of the canvas the captcha is rendered inside
+    shape: CanvasShape,
+    // The border drawn along the outline of the shape
+    border: Option<Border>,
     length: u8,
+    // The maximum length of the random codes, equal to the length unless a range is set
+    max_length: u8,
     width: u32,
     height: u32,
     mode: u8,
     complexity: u32,
+    max_width: u32,
+    max_height: u32,
+    max_pixels: u64,
+    work_budget: Option<u64>,
 }
 
 impl Default for CaptchaBuilder {
@@ -46,35 +263,269 @@ impl CaptchaBuilder {
     pub fn new() -> Self {
         CaptchaBuilder {
             length: 4,
-            fonts: rusttype::Font::try_from_bytes(FONTS).expect("Invalid font for CaptchaBuilder"),
+            max_length: 4,
+            fonts: vec![BundledFont::Default.font()],
+            mixed_fonts: false,
+            variable_font: None,
+            letter_spacing: None,
+            charset: Arc::from(BASIC_CHARS),
+            case_policy: CasePolicy::Mixed,
+            excluded: Arc::from([]),
+            confusables: ConfusablePolicy::Allow,
+            no_adjacent_repeats: false,
+            char_policy: CharPolicy::Any,
+            pronounceable: false,
+            words: 0,
+            word_list: None,
+            pictograms: None,
+            number_words: None,
+            large_glyphs: false,
+            readability: None,
+            fast: false,
+            char_tiles: false,
+            negative_space: false,
+            interference: Interference::Curves,
+            grayscale: false,
+            transparent: false,
+            #[cfg(feature = "jpeg")]
+            quality_jitter: None,
+            #[cfg(feature = "animation")]
+            frame: 0,
+            difficulty: None,
+            theme: None,
+            shape: CanvasShape::Rectangle,
+            border: None,
             width: 140,
             height: 40,
             mode: 1u8,
             complexity: 5,
+            max_width: DEFAULT_MAX_WIDTH,
+            max_height: DEFAULT_MAX_HEIGHT,
+            max_pixels: DEFAULT_MAX_PIXELS,
+            work_budget: None,
+        }
+    }
+
+    /// Returns a [`CaptchaBuilder`] with the accessible preset for users who opt in:
+    /// a plain sans-serif font (DejaVu Sans Bold with the `font-accessible` feature,
+    /// the default font otherwise), dark characters on a light background,
+    /// generous letter spacing without overlap and reduced interference.
+    /// The preset does not make the captcha dyslexia-friendly by itself: no bundled font is
+    /// designed for dyslexic readers, load one with [`CaptchaBuilder::fonts_from_bytes`] if needed.
+    pub fn accessible() -> Self {
+        let builder = Self::new()
+            .width(200)
+            .height(60)
+            .mode(0)
+            .complexity(2)
+            .letter_spacing(Some(8));
+        #[cfg(feature = "font-accessible")]
+        let builder = builder.bundled_font(BundledFont::Accessible);
+        builder
+    }
+
+    /// Set the characters of the random verification codes, default is [`BASIC_CHARS`],
+    /// e.g. to restrict the codes to digits or uppercase letters. Returns an error if the
+    /// charset is empty, has duplicates or a character without a glyph in the fonts, so set
+    /// it after the fonts, or if fewer than 4 characters (or all of a smaller charset) are left
+    /// after the case policy, the excluded characters and the confusable policy. Characters that are easily confused with each other should be avoided.
+    /// CJK charsets need fonts covering them, e.g. loaded with [`CaptchaBuilder::fonts_from_bytes`];
+    /// the wide glyphs are laid out by their measured widths and shrunk to fit the width.
+    pub fn charset(mut self, charset: &[char]) -> Result<Self, CaptchaError> {
+        if charset.is_empty() {
+            return Err(CaptchaError::InvalidConfig("empty charset".to_string()));
+        }
+        for (i, c) in charset.iter().enumerate() {
+            if charset[..i].contains(c) {
+                return Err(CaptchaError::InvalidConfig(format!(
+                    "duplicate character {:?} in charset",
+                    c
+                )));
+            }
+            if c.is_whitespace() || self.fonts.iter().any(|font| !font::has_glyph(font, *c)) {
+                return Err(CaptchaError::Font(format!("no glyph of character {:?}", c)));
+            }
         }
+        self.charset = Arc::from(charset);
+        self.check_charset()?;
+        Ok(self)
+    }
+
+    /// Set the letter case of the random verification codes, default is [`CasePolicy::Mixed`].
+    /// Mixed-case codes fail more often on mobile keyboards, with [`CasePolicy::Upper`] or
+    /// [`CasePolicy::Lower`] the letters of the charset are converted to the case, e.g. the
+    /// digits and uppercase letters of [`BASIC_CHARS`] are sampled with `Upper`.
+    /// Returns an error if the conversion leaves fewer than 4 characters in the charset,
+    /// see [`CaptchaBuilder::charset`].
+    pub fn case_policy(mut self, policy: CasePolicy) -> Result<Self, CaptchaError> {
+        self.case_policy = policy;
+        self.check_charset()?;
+        Ok(self)
+    }
+
+    /// Returns a [`CaptchaBuilder`] with the numeric preset for phone-first users: 6 digits
+    /// of [`DIGIT_CHARS`], entered with the numeric keypad. The digits are narrower than the
+    /// letters, so even 6 of them are drawn with the largest glyph scale, on a 200x50 canvas
+    /// whose slots fit the digits rather than shrunk for the length.
+    pub fn numeric() -> Self {
+        let mut builder = Self::new().length(6).width(200).height(50);
+        builder.charset = Arc::from(DIGIT_CHARS);
+        builder.large_glyphs = true;
+        builder
     }
 
     /// Set the length of the verification code string, default is 4.
     pub fn length(mut self, length: u8) -> Self {
         self.length = if length > 0 { length } else { 4 };
+        self.max_length = self.length;
+        self
+    }
+
+    /// Set whether adjacent characters of the random verification codes must differ, default
+    /// is false. A character identical to the previous one, the same letter in the other case
+    /// or a lookalike such as `5` and `S` is re-sampled from the seed, codes like "ppqq" are
+    /// hard to read. The re-sampling is bounded, a tiny charset of lookalikes may still repeat.
+    pub fn no_adjacent_repeats(mut self, no_adjacent_repeats: bool) -> Self {
+        self.no_adjacent_repeats = no_adjacent_repeats;
+        self
+    }
+
+    /// Set the composition of the random verification codes, default is [`CharPolicy::Any`].
+    /// With [`CharPolicy::DigitAndLetter`], a code of at least 2 characters missing a digit
+    /// or a letter has a character drawn from the seed replaced with one of the missing class,
+    /// so that codes have a predictable shape. It is ignored if the charset lacks the class.
+    pub fn char_policy(mut self, policy: CharPolicy) -> Self {
+        self.char_policy = policy;
+        self
+    }
+
+    /// Set whether the random verification codes are pronounceable pseudo-words, default is
+    /// false. Consonants and vowels of the charset alternate, starting with a consonant, e.g.
+    /// "tavuke", which humans transcribe more easily than random strings while the codes are
+    /// still drawn from the seed. Digits are not drawn, and it is ignored if the charset
+    /// lacks consonants or vowels. Combine it with [`CasePolicy::Lower`] for natural words.
+    pub fn pronounceable(mut self, pronounceable: bool) -> Self {
+        self.pronounceable = pronounceable;
+        self
+    }
+
+    /// Set the range of the length of the random verification code strings, the length of
+    /// every code is drawn from the seed between `min` and `max` inclusive, so that automated
+    /// solvers can not rely on a fixed length. The characters are laid out over the width
+    /// and shrunk for the longer codes as with a fixed length.
+    pub fn length_range(mut self, min: u8, max: u8) -> Self {
+        self.length = if min > 0 { min } else { 4 };
+        self.max_length = max.max(self.length);
         self
     }
 
     /// Set the font used to generate the captcha image, default is arial-rounded-bold.ttf.
+    /// TrueType and OpenType (CFF) fonts are supported.
+    pub fn font(mut self, font: FontArc) -> Self {
+        self.fonts[0] = font;
+        self.variable_font = None;
+        self
+    }
+
+    /// Set the font used to generate the captcha image from TrueType or OpenType font data.
+    /// Returns an error if the data is not a valid font.
+    pub fn fonts_from_bytes(mut self, data: impl Into<Vec<u8>>) -> Result<Self, CaptchaError> {
+        self.fonts[0] = font::parse_font(data.into())?;
+        self.variable_font = None;
+        Ok(self)
+    }
+
+    /// Set the font used to generate the captcha image from a TrueType or OpenType font file.
+    /// Returns an error if the file can not be read or is not a valid font.
+    pub fn fonts_from_path(mut self, path: impl AsRef<Path>) -> Result<Self, CaptchaError> {
+        self.fonts[0] = font::read_font(path.as_ref())?;
+        self.variable_font = None;
+        Ok(self)
+    }
+
+    /// Set one of the fonts bundled with the crate, see [`BundledFont`].
+    /// The font can also be selected by name, e.g. `"serif".parse::<BundledFont>()`.
+    pub fn bundled_font(mut self, font: BundledFont) -> Self {
+        self.fonts[0] = font.font();
+        self.variable_font = None;
+        self
+    }
+
+    /// Set the font with the given name in a [`FontRegistry`], resolved when it is called.
+    /// Returns an error if no font is registered with the name.
+    pub fn registry_font(
+        mut self,
+        registry: &FontRegistry,
+        name: &str,
+    ) -> Result<Self, CaptchaError> {
+        self.fonts[0] = registry
+            .get(name)
+            .ok_or_else(|| CaptchaError::Font(format!("unknown registered font {:?}", name)))?;
+        self.variable_font = None;
+        Ok(self)
+    }
+
+    /// Register an additional font, used when mixed fonts are enabled.
+    pub fn add_font(mut self, font: FontArc) -> Self {
+        self.fonts.push(font);
+        self
+    }
+
+    /// Pick the font of each character from the seed stream among the registered fonts,
+    /// so a single captcha mixes typefaces, default is false.
+    /// It has no effect unless additional fonts are registered with [`CaptchaBuilder::add_font`].
+    pub fn mixed_fonts(mut self, mixed_fonts: bool) -> Self {
+        self.mixed_fonts = mixed_fonts;
+        self
+    }
+
+    /// Set a variable font from TrueType or OpenType font data as the primary font,
+    /// the given axes are randomized per character within their bounds,
+    /// e.g. `FontAxis::weight(400.0, 800.0)`. The bounds are clamped to the axis ranges of the font.
+    /// Returns an error if the data is not a valid font or an axis is not supported by the font.
+    /// Variable glyphs are rasterized per character, so they are not cached by [`CaptchaGenerator`].
+    pub fn variable_font(
+        mut self,
+        data: impl Into<Vec<u8>>,
+        axes: &[FontAxis],
+    ) -> Result<Self, CaptchaError> {
+        let data = data.into();
+        let face = VariableFace::new(data.clone(), axes)?;
+        self.fonts[0] = FontArc::try_from_vec(data).expect("font data is validated");
+        self.variable_font = Some(face);
+        Ok(self)
+    }
+
+    /// Set the font used to generate the captcha image from a `rusttype` font.
+    /// Fonts borrowing their data, created with `rusttype::Font::try_from_bytes`, are rebuilt
+    /// from their tables. Panics if the font can not be converted.
+    #[cfg(feature = "rusttype")]
+    #[deprecated(
+        since = "1.1.0",
+        note = "use `font` with an `ab_glyph::FontArc` instead"
+    )]
     pub fn fonts(mut self, fonts: rusttype::Font<'static>) -> Self {
-        self.fonts = fonts;
+        self.fonts[0] = font::from_rusttype(&fonts)
+            .unwrap_or_else(|err| panic!("failed to convert the rusttype font: {}", err));
+        self.variable_font = None;
         self
     }
 
     /// Set the width of the verification code image, default is 140.
+    /// The width is capped at 65535, the maximum supported by the JPEG encoder.
     pub fn width(mut self, width: u32) -> Self {
-        self.width = if width > 60 { width } else { 140 };
+        self.width = if width > 60 { width.min(MAX_SIDE) } else { 140 };
         self
     }
 
     /// Set the height of the verification code image, default is 40.
+    /// The height is capped at 65535, the maximum supported by the JPEG encoder.
     pub fn height(mut self, height: u32) -> Self {
-        self.height = if height > 20 { height } else { 40 };
+        self.height = if height > 20 {
+            height.min(MAX_SIDE)
+        } else {
+            40
+        };
         self
     }
 
@@ -85,7 +536,66 @@ impl CaptchaBuilder {
         self
     }
 
+    /// Set the color mode of the verification code image from a named scheme,
+    /// see [`ColorScheme::auto_for_background`] to follow the page background.
+    pub fn color_scheme(mut self, scheme: ColorScheme) -> Self {
+        self.mode = scheme.mode();
+        self
+    }
+
+    /// Set the colors of the verification code image, replacing the palettes of the mode,
+    /// default is `None`. See [`Theme::from_brand_color`] to derive a theme from a brand color.
+    pub fn theme(mut self, theme: Option<Theme>) -> Self {
+        self.theme = theme.map(Arc::new);
+        self
+    }
+
+    /// Set the grayscale mode for thermal and e-paper displays and smaller payloads, default is
+    /// false. The whole pipeline is rendered with gray levels only: the colors of the text and
+    /// the interference are mapped to gray levels contrasting with the background, then every
+    /// pixel is reduced to its luma. See [`Captcha::luma_image`] for the single channel image,
+    /// JPEG, PNG and WebP outputs of a grayscale captcha are encoded with a single channel.
+    pub fn grayscale(mut self, grayscale: bool) -> Self {
+        self.grayscale = grayscale;
+        self
+    }
+
+    /// Set the transparent mode to composite the captcha over the background of an app,
+    /// default is false. Only the characters and the interference are kept in
+    /// [`Captcha::rgba_image`], the background color is made fully transparent and the
+    /// anti-aliased edges partially transparent, the colors are unmixed from the background
+    /// so that compositing over the background color gives the opaque image back. PNG and WebP outputs of a transparent captcha
+    /// preserve the alpha channel, JPEG has no alpha channel and keeps the background color.
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// Set the shape of the canvas the verification code image is rendered inside,
+    /// default is [`CanvasShape::Rectangle`]. The pixels outside of the shape are filled
+    /// with the background color, see [`Captcha::alpha_mask`] to render them transparent.
+    /// Characters are laid out on the full canvas, so keep the text clear of the cut corners.
+    pub fn shape(mut self, shape: CanvasShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    /// Round the corners of the verification code image with the given radius in pixels,
+    /// the same as `shape(CanvasShape::RoundedRect { radius })`.
+    pub fn corner_radius(self, radius: u32) -> Self {
+        self.shape(CanvasShape::RoundedRect { radius })
+    }
+
+    /// Set the border drawn along the outline of the canvas shape at the end of the pipeline,
+    /// default is `None`.
+    pub fn border(mut self, border: Option<Border>) -> Self {
+        self.border = border;
+        self
+    }
+
     /// Set the complexity of the verification code image, default is 5.
+    /// Without the `noise` feature, it only controls the interference lines and ellipses.
+    #[allow(clippy::manual_clamp)]
     pub fn complexity(mut self, complexity: u32) -> Self {
         self.complexity = if complexity > 10 {
             10
@@ -97,43 +607,514 @@ impl CaptchaBuilder {
         self
     }
 
+    /// Set the difficulty of the whole pipeline with a single dial from 0.0 (easiest) to 1.0
+    /// (hardest), default is unset. It scales every layer together:
+    ///
+    /// - the complexity, i.e. the interference lines, ellipses and noise, from 1 to 10;
+    /// - the letter spacing, from a gap of 8 pixels at 0.0 to none at 0.5 and above;
+    /// - the overlap, characters are squeezed together up to 30% above 0.5;
+    /// - the vertical jitter of the characters, from half to 1.5 times of the default range.
+    ///
+    /// The complexity and letter spacing can still be overridden by setting them afterwards.
+    pub fn difficulty(mut self, difficulty: f32) -> Self {
+        let d = if difficulty.is_nan() {
+            0.5
+        } else {
+            difficulty.clamp(0.0, 1.0)
+        };
+        self.difficulty = Some(d);
+        self.complexity = 1 + (d * 9.0).round() as u32;
+        self.letter_spacing = if d < 0.5 {
+            Some(((0.5 - d) * 16.0).round() as u32)
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Re-encode the rendered image through a low-quality JPEG pass at the end of the
+    /// pipeline, before the final encoding, default is `None`. The block artifacts around
+    /// the glyph edges degrade OCR while the characters remain legible.
+    /// The strength is clamped to 0.0-1.0, the JPEG quality goes from about 70 down to 10
+    /// with it and is jittered by up to 5 from the seed, so the pass is fully deterministic.
+    #[cfg(feature = "jpeg")]
+    pub fn quality_jitter(mut self, strength: Option<f32>) -> Self {
+        self.quality_jitter = strength.map(|s| if s.is_nan() { 0.5 } else { s.clamp(0.0, 1.0) });
+        self
+    }
+
+    /// Set the maximum width of the verification code image, default is 2048.
+    /// The size limits are enforced by [`CaptchaBuilder::try_generate`] only.
+    pub fn max_width(mut self, max_width: u32) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Set the maximum height of the verification code image, default is 1024.
+    pub fn max_height(mut self, max_height: u32) -> Self {
+        self.max_height = max_height;
+        self
+    }
+
+    /// Set the maximum pixel count (width * height) of the verification code image,
+    /// default is 1048576. It bounds the memory allocated for the image buffer.
+    pub fn max_pixels(mut self, max_pixels: u64) -> Self {
+        self.max_pixels = max_pixels;
+        self
+    }
+
+    /// Set the minimum gap in pixels between the boxes of adjacent characters,
+    /// so that characters never overlap. Default is `None`,
+    /// characters are spread evenly over the width and may overlap.
+    pub fn letter_spacing(mut self, spacing: Option<u32>) -> Self {
+        self.letter_spacing = spacing;
+        self
+    }
+
+    /// Set the readability guard, default is off. After the interference stages, every character
+    /// must keep at least the given fraction (0.0-1.0) of its glyph pixels visible with adequate
+    /// contrast against the background; otherwise the interference is re-drawn from the next
+    /// bytes of the random stream, up to 3 times. If no attempt is readable, the characters are
+    /// kept without interference and noise. The result is still deterministic for a seed.
+    pub fn readability_guard(mut self, min_visible: Option<f32>) -> Self {
+        self.readability = min_visible.map(|v| v.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Draw every character on its own randomly tinted and rotated tile, like classic card
+    /// captchas, default is false. The geometry of the tiles is drawn from the seed.
+    pub fn char_tiles(mut self, char_tiles: bool) -> Self {
+        self.char_tiles = char_tiles;
+        self
+    }
+
+    /// Draw the characters as the absence of a dense pattern, default is false. The canvas is
+    /// filled with short strokes of the interference colors and the glyphs are carved out of
+    /// it with the background color, which humans read easily but defeats simple thresholding.
+    /// The readability guard does not apply to negative space captchas.
+    pub fn negative_space(mut self, negative_space: bool) -> Self {
+        self.negative_space = negative_space;
+        self
+    }
+
+    /// Set the interference drawn over the characters, default is [`Interference::Curves`].
+    /// The number of interference stages is still controlled by the complexity.
+    pub fn interference(mut self, interference: Interference) -> Self {
+        self.interference = interference;
+        self
+    }
+
+    /// Set the fast mode for low-latency hot paths, default is false. The interference lines
+    /// and ellipses are drawn in a single pass instead of doubled, and the gaussian and
+    /// salt-and-pepper noise are replaced by cheap approximations: a pre-drawn tile of gaussian
+    /// samples repeated over the rows, and noise pixels at random positions.
+    ///
+    /// Security trade-off: the tiled noise repeats with a short period, so it is much easier
+    /// to estimate and subtract than independent noise, and the thinner interference is easier
+    /// to remove. Use it where latency matters more than resistance to automated solvers.
+    pub fn fast(mut self, fast: bool) -> Self {
+        self.fast = fast;
+        self
+    }
+
+    /// Set the work budget of [`CaptchaBuilder::try_generate`] in approximate pixel operations,
+    /// default is unlimited. The budget is checked between the pipeline stages, so that
+    /// extreme sizes plus heavy noise fail early instead of exceeding an instruction limit.
+    pub fn work_budget(mut self, steps: Option<u64>) -> Self {
+        self.work_budget = steps;
+        self
+    }
+
+    /// Build a [`CaptchaGenerator`] that pre-rasterizes the charset glyphs at all font scales,
+    /// so that the hot generation path only composites cached bitmaps.
+    /// It generates the same captchas as this builder.
+    pub fn build(self) -> CaptchaGenerator {
+        let glyphs = GlyphCache::new(self.active_fonts(), &self.active_charset());
+        CaptchaGenerator::new(self, glyphs)
+    }
+
     /// Generate a [`Captcha`] with the given random seed and a optional text.
     /// If the text is not provided, a text will be generated from random seed.
     /// The random seed can be used only once. You should use a new seed for each new captcha.
+    /// Neither the size limits nor the work budget are enforced, use
+    /// [`CaptchaBuilder::try_generate`] to get an error instead.
+    /// The default font [`FONTS`] only contains the glyphs of [`BASIC_CHARS`], a custom text
+    /// with other characters needs the `full-font` feature or a font covering them,
+    /// otherwise they are drawn blank.
     pub fn generate(&self, seed: &[u8], text: Option<String>) -> Captcha {
+        self.generate_with(seed, text, None)
+    }
+
+    /// Generate a [`Captcha`] like [`CaptchaBuilder::generate`], but returns an error
+    /// if the configured size exceeds the limits or the work exceeds the budget,
+    /// or [`CaptchaError::Font`] if a character of the custom text has no glyph in the fonts.
+    pub fn try_generate(&self, seed: &[u8], text: Option<String>) -> Result<Captcha, CaptchaError> {
+        self.try_generate_with(seed, text, None)
+    }
+
+    /// Generate a [`CaptchaPair`]: the standard captcha like [`CaptchaBuilder::generate`]
+    /// and an accessibility-grade variant of the same answer, so frontends can toggle between
+    /// them without a server round-trip and a single token verifies both.
+    /// The variant is drawn like [`CaptchaBuilder::accessible`] with the largest glyphs,
+    /// a canvas fitting them and no interference or noise.
+    pub fn generate_pair(&self, seed: &[u8], text: Option<String>) -> CaptchaPair {
+        let standard = self.generate(seed, text);
+        let text = standard.text();
+        let accessible = self
+            .accessible_variant(text.chars().count())
+            .generate(seed, Some(text));
+        CaptchaPair {
+            standard,
+            accessible,
+        }
+    }
+
+    // Return the builder of the accessible variant for a text with the given length,
+    // the canvas fits the largest glyphs
+    pub(crate) fn accessible_variant(&self, len: usize) -> CaptchaBuilder {
+        let mut builder = CaptchaBuilder::accessible()
+            .length_range(self.length, self.max_length)
+            .width((10 + len as u32 * 48).clamp(200, MAX_SIDE))
+            .complexity(1);
+        builder.large_glyphs = true;
+        builder
+    }
+
+    /// Generate a batch of [`Captcha`]s with the given random seeds, one captcha per seed.
+    /// The glyphs are rasterized once for the whole batch, it is useful for pre-filling challenge pools.
+    /// With the `parallel` feature, the batch is generated across threads,
+    /// every captcha only depends on its own seed so the result is the same.
+    pub fn generate_batch(&self, seeds: &[&[u8]]) -> Vec<Captcha> {
+        let glyphs = GlyphCache::new(self.active_fonts(), &self.active_charset());
+        #[cfg(not(feature = "parallel"))]
+        let seeds = seeds.iter();
+        #[cfg(feature = "parallel")]
+        let seeds = seeds.par_iter();
+        seeds
+            .map(|seed| self.generate_with(seed, None, Some(&glyphs)))
+            .collect()
+    }
+
+    /// Generate a batch of [`Captcha`]s with the given random seeds and texts, one captcha per item.
+    pub fn generate_batch_with_texts(&self, items: &[(&[u8], &str)]) -> Vec<Captcha> {
+        let glyphs = GlyphCache::new(self.active_fonts(), &self.active_charset());
+        #[cfg(not(feature = "parallel"))]
+        let items = items.iter();
+        #[cfg(feature = "parallel")]
+        let items = items.par_iter();
+        items
+            .map(|(seed, text)| self.generate_with(seed, Some(text.to_string()), Some(&glyphs)))
+            .collect()
+    }
+
+    /// Generate a captcha into an existing [`Captcha`] like [`CaptchaBuilder::generate`],
+    /// reusing its image buffer instead of allocating a new one.
+    pub fn generate_into(&self, captcha: &mut Captcha, seed: &[u8], text: Option<String>) {
+        self.generate_into_with(captcha, seed, text, None)
+    }
+
+    /// Generate a captcha into an existing [`Captcha`] like [`CaptchaBuilder::try_generate`],
+    /// reusing its image buffer. The captcha content is unspecified if an error is returned.
+    pub fn try_generate_into(
+        &self,
+        captcha: &mut Captcha,
+        seed: &[u8],
+        text: Option<String>,
+    ) -> Result<(), CaptchaError> {
+        self.try_generate_into_with(captcha, seed, text, None)
+    }
+
+    pub(crate) fn generate_with(
+        &self,
+        seed: &[u8],
+        text: Option<String>,
+        glyphs: Option<&GlyphCache>,
+    ) -> Captcha {
+        let mut captcha = Captcha::default();
+        self.generate_into_with(&mut captcha, seed, text, glyphs);
+        captcha
+    }
+
+    pub(crate) fn try_generate_with(
+        &self,
+        seed: &[u8],
+        text: Option<String>,
+        glyphs: Option<&GlyphCache>,
+    ) -> Result<Captcha, CaptchaError> {
+        let mut captcha = Captcha::default();
+        self.try_generate_into_with(&mut captcha, seed, text, glyphs)?;
+        Ok(captcha)
+    }
+
+    pub(crate) fn generate_into_with(
+        &self,
+        captcha: &mut Captcha,
+        seed: &[u8],
+        text: Option<String>,
+        glyphs: Option<&GlyphCache>,
+    ) {
+        let (width, height) = self.dimensions();
+        let mut budget = WorkBudget::new(None);
+        self.render(captcha, seed, text, width, height, glyphs, &mut budget)
+            .expect("generation without a work budget is infallible")
+    }
+
+    pub(crate) fn try_generate_into_with(
+        &self,
+        captcha: &mut Captcha,
+        seed: &[u8],
+        text: Option<String>,
+        glyphs: Option<&GlyphCache>,
+    ) -> Result<(), CaptchaError> {
+        if self.width > self.max_width
+            || self.height > self.max_height
+            || self.width as u64 * self.height as u64 > self.max_pixels
+        {
+            return Err(CaptchaError::TooLarge {
+                width: self.width,
+                height: self.height,
+                max_width: self.max_width,
+                max_height: self.max_height,
+                max_pixels: self.max_pixels,
+            });
+        }
+        self.check_charset()?;
+        self.check_number_words()?;
+        if let Some(text) = &text {
+            self.check_text(text)?;
+        }
+
+        let mut budget = WorkBudget::new(self.work_budget);
+        self.render(
+            captcha,
+            seed,
+            text,
+            self.width,
+            self.height,
+            glyphs,
+            &mut budget,
+        )
+    }
+
+    // Return the configured dimensions of the image
+    pub(crate) fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // Return the fonts characters are drawn with, the font of the pictograms if they are set,
+    // otherwise the primary font only unless mixing fonts
+    fn active_fonts(&self) -> &[FontArc] {
+        if let Some(font) = self.pictogram_font() {
+            std::slice::from_ref(font)
+        } else if self.mixed_fonts {
+            &self.fonts
+        } else {
+            &self.fonts[..1]
+        }
+    }
+
+    // Check that every character of a custom text, except whitespace, has a glyph in the fonts
+    // it is drawn with
+    fn check_text(&self, text: &str) -> Result<(), CaptchaError> {
+        for c in text.chars().filter(|c| !c.is_whitespace()) {
+            if self
+                .active_fonts()
+                .iter()
+                .any(|font| !font::has_glyph(font, c))
+            {
+                return Err(CaptchaError::Font(format!("no glyph of character {:?}", c)));
+            }
+        }
+        Ok(())
+    }
+
+    // Return the answer and the prompt of a captcha with the given text if the answer differs
+    // from the text, for the pictograms and the spelled numbers, see `Captcha::answer`
+    pub(crate) fn answer_of<R>(
+        &self,
+        get_rnd: &mut R,
+        text: &str,
+    ) -> Option<(String, Option<String>)>
+    where
+        R: FnMut(u32) -> u32,
+    {
+        self.pictogram_answer(get_rnd, text)
+            .or_else(|| self.spelled_answer(text).map(|answer| (answer, None)))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "generate",
+            level = "debug",
+            skip_all,
+            fields(
+                width = width,
+                height = height,
+                mode = self.mode,
+                complexity = self.complexity,
+                stages = tracing::field::Empty
+            ),
+            err(level = "warn", Display)
+        )
+    )]
+    fn render(
+        &self,
+        captcha: &mut Captcha,
+        seed: &[u8],
+        text: Option<String>,
+        width: u32,
+        height: u32,
+        glyphs: Option<&GlyphCache>,
+        budget: &mut WorkBudget,
+    ) -> Result<(), CaptchaError> {
+        budget.spend(budget::background_steps(width, height))?;
         let mut rnd = Rnd::new(seed);
         let mut get_rnd_32 = |num: u32| rnd.rnd_32(num);
-        let mut captcha = match text {
-            Some(text) => Captcha::new(text, self.width, self.height, self.mode),
-            None => Captcha::random(
+        captcha.set_theme(self.theme.clone());
+        captcha.set_grayscale(self.grayscale);
+        captcha.set_transparent(self.transparent);
+        #[cfg(feature = "animation")]
+        let source = (self.frame == 0).then(|| animation::FrameSource {
+            builder: self.clone(),
+            seed: seed.to_vec(),
+            text: text.clone(),
+        });
+        match text {
+            Some(text) => captcha.reset(&text, width, height, self.mode),
+            None => captcha.reset_random(&mut get_rnd_32, self, width, height, self.mode),
+        }
+        let answer = self.answer_of(&mut get_rnd_32, &captcha.text());
+        captcha.set_answer(answer);
+        // The pictograms are drawn with their own font at the largest scale that fits
+        let pictograms = self.pictograms.is_some();
+        let large_glyphs = self.large_glyphs || pictograms;
+        captcha.set_id(ChallengeId::from_seed(seed));
+        #[cfg(feature = "animation")]
+        captcha.set_frame_source(source.map(Arc::new));
+
+        if self.char_tiles {
+            budget.spend(budget::background_steps(width, height))?;
+            captcha.draw_char_tiles(
                 &mut get_rnd_32,
-                self.length,
-                self.width,
-                self.height,
-                self.mode,
-            ),
-        };
+                self.active_fonts(),
+                self.letter_spacing,
+                large_glyphs,
+                self.difficulty,
+            );
+        }
 
         // Loop to write the verification code string into the background image
-        captcha.draw_characters(&mut get_rnd_32, &self.fonts);
+        budget.spend(budget::characters_steps(width, height))?;
+        let mut masks = (self.readability.is_some()
+            || self.negative_space
+            || self.interference == Interference::Bars)
+            .then(Vec::new);
+        captcha.draw_characters(
+            &mut get_rnd_32,
+            self.active_fonts(),
+            self.variable_font.as_ref().filter(|_| !pictograms),
+            self.letter_spacing,
+            large_glyphs,
+            self.difficulty,
+            masks.as_mut(),
+            glyphs.filter(|_| !pictograms),
+        );
+
+        // Every frame after the first of an animation re-draws the interference from its own seed
+        #[cfg(feature = "animation")]
+        let mut frame_rnd =
+            (self.frame > 0).then(|| Rnd::new(&animation::frame_seed(seed, self.frame)));
+        #[cfg(feature = "animation")]
+        let mut get_rnd_32 = |num: u32| match frame_rnd.as_mut() {
+            Some(frame_rnd) => frame_rnd.rnd_32(num),
+            None => rnd.rnd_32(num),
+        };
+
+        let glyph_masks = masks.as_deref();
+        match (self.readability, glyph_masks) {
+            (_, Some(masks)) if self.negative_space => {
+                budget.spend(budget::negative_space_steps(width, height))?;
+                captcha.carve_negative_space(&mut get_rnd_32, masks);
+                self.draw_interference(captcha, &mut get_rnd_32, glyph_masks, budget)?;
+            }
+            (Some(min_visible), Some(masks)) => {
+                // Without a readable attempt the characters are kept without interference,
+                // rather than shipping a captcha that can not be solved
+                let drawn = captcha.image().clone();
+                for _ in 0..=READABILITY_RETRIES {
+                    self.draw_interference(captcha, &mut get_rnd_32, glyph_masks, budget)?;
+                    if captcha.is_readable(&drawn, masks, min_visible) {
+                        break;
+                    }
+                    captcha.restore_image(&drawn);
+                }
+            }
+            _ => self.draw_interference(captcha, &mut get_rnd_32, glyph_masks, budget)?,
+        }
+        #[cfg(feature = "jpeg")]
+        if let Some(strength) = self.quality_jitter {
+            budget.spend(budget::quality_jitter_steps(width, height))?;
+            let quality = (70.0 - 60.0 * strength) as i32 + get_rnd_32(11) as i32 - 5;
+            captcha.jitter_quality(quality.clamp(5, 95) as u8)?;
+        }
+        captcha.apply_shape(self.shape, self.border);
+        if self.grayscale {
+            captcha.desaturate();
+        }
+
+        // The characters, interference and noise stages drawn
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record(
+            "stages",
+            self.complexity.max(1) + cfg!(feature = "noise") as u32,
+        );
+        Ok(())
+    }
 
+    // Draw the interference lines, ellipses or bars and the noise of the complexity,
+    // the bars are kept within the occlusion cap of the glyph masks
+    fn draw_interference<R>(
+        &self,
+        captcha: &mut Captcha,
+        get_rnd: &mut R,
+        masks: Option<&[Vec<u32>]>,
+        budget: &mut WorkBudget,
+    ) -> Result<(), CaptchaError>
+    where
+        R: FnMut(u32) -> u32,
+    {
+        let (width, height) = captcha.image().dimensions();
+        let mut covered = Vec::new();
         let mut complexity = 1;
         while complexity < self.complexity {
-            if complexity % 2 == 0 {
-                captcha.draw_interference_line(&mut get_rnd_32);
+            budget.spend(budget::interference_steps(width, height))?;
+            if self.interference == Interference::Bars {
+                captcha.draw_interference_bars(get_rnd, masks.unwrap_or_default(), &mut covered);
+            } else if complexity % 2 == 0 {
+                captcha.draw_interference_line(get_rnd, self.fast);
             } else {
-                captcha.draw_interference_ellipse(&mut get_rnd_32);
+                captcha.draw_interference_ellipse(get_rnd, self.fast);
             }
 
             complexity += 1;
         }
 
-        captcha.draw_interference_noise(&mut get_rnd_32, self.complexity);
-
-        captcha
+        #[cfg(feature = "noise")]
+        {
+            if self.complexity > 1 {
+                budget.spend(budget::noise_steps(width, height))?;
+            }
+            captcha.draw_interference_noise(get_rnd, self.complexity, self.fast);
+        }
+        Ok(())
     }
 }
 
+// The maximum number of times the interference is re-drawn by the readability guard
+const READABILITY_RETRIES: usize = 3;
+
 // A simple random number generator with a fixed seed
 struct Rnd {
     offset: usize,
@@ -148,7 +1129,7 @@ impl Rnd {
         }
     }
 
-    // Generate a random number between 0 and num with the given seed
+    // Generate a random number between 0 and num with the given seed, returns 0 if num is 0
     fn rnd_32(&mut self, num: u32) -> u32 {
         let mut d = [0u8; 4];
         d.copy_from_slice(&self.seed[self.offset..self.offset + 4]);
@@ -157,7 +1138,7 @@ impl Rnd {
             self.seed = next_seed(&self.seed);
             self.offset = 0;
         }
-        u32::from_le_bytes(d) % num
+        u32::from_le_bytes(d).checked_rem(num).unwrap_or(0)
     }
 }
 
@@ -170,9 +1151,11 @@ fn next_seed(seed: &[u8]) -> [u8; 32] {
 
 #[cfg(test)]
 mod tests {
-    use crate::CaptchaBuilder;
+    use crate::*;
+    use proptest::prelude::*;
 
     #[test]
+    #[cfg(feature = "jpeg")]
     fn it_generates_a_captcha() {
         let builder = CaptchaBuilder::new();
 
@@ -193,6 +1176,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "jpeg")]
     fn it_generates_captcha_using_builder() {
         let captcha = CaptchaBuilder::new()
             .length(4)
@@ -208,4 +1192,674 @@ mod tests {
         println!("text: {}", captcha.text());
         println!("base_img: {}", base_img);
     }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn it_generates_captcha_with_empty_text() {
+        let captcha = CaptchaBuilder::new().generate(&[2u8, 32], Some(String::new()));
+        assert_eq!(captcha.text().as_str(), "");
+        assert!(captcha.to_base64(0).starts_with("data:image/jpeg;base64,"));
+        assert_eq!(captcha.try_to_base64(0).unwrap(), captcha.to_base64(0));
+    }
+
+    #[test]
+    fn it_enforces_size_limits() {
+        let builder = CaptchaBuilder::new().width(4000).height(3000);
+        let err = builder.try_generate(&[3u8, 32], None).err().unwrap();
+        assert!(matches!(err, CaptchaError::TooLarge { width: 4000, .. }));
+
+        // generate is not bounded by the limits
+        let captcha = builder
+            .width(300)
+            .height(200)
+            .max_width(200)
+            .generate(&[3u8, 32], None);
+        assert_eq!(captcha.image().dimensions(), (300, 200));
+
+        let builder = CaptchaBuilder::new()
+            .width(200)
+            .height(100)
+            .max_pixels(10_000);
+        assert!(builder.try_generate(&[3u8, 32], None).is_err());
+        assert_eq!(builder.generate(&[3u8, 32], None).image().height(), 100);
+        assert!(CaptchaBuilder::new().try_generate(&[3u8, 32], None).is_ok());
+    }
+
+    #[test]
+    fn it_reports_char_colors() {
+        let captcha = CaptchaBuilder::new()
+            .mode(0)
+            .generate(b"seed", Some("LDCLabs".to_string()));
+        let colors = captcha.char_colors();
+        assert_eq!(colors.iter().map(|(c, _)| c).collect::<String>(), "LDCLabs");
+        assert!(colors
+            .iter()
+            .all(|(_, color)| *color == image::Rgb([18, 18, 18])));
+
+        let captcha = CaptchaBuilder::new().complexity(1).generate(b"seed", None);
+        let colors = captcha.char_colors();
+        assert_eq!(colors.len(), 4);
+        for (_, color) in colors {
+            assert!(captcha.image().pixels().any(|pixel| *pixel == color));
+        }
+    }
+
+    #[test]
+    fn it_generates_in_fast_mode() {
+        let builder = CaptchaBuilder::new().complexity(8);
+        let fast = builder.clone().fast(true);
+        let captcha = fast.generate(b"seed", None);
+        let plain = builder.generate(b"seed", None);
+        assert_eq!(captcha.text(), plain.text());
+        assert_ne!(captcha.image(), plain.image());
+        assert_eq!(captcha.image(), fast.generate(b"seed", None).image());
+    }
+
+    #[test]
+    fn it_generates_with_char_tiles() {
+        let builder = CaptchaBuilder::new().complexity(1).char_tiles(true);
+        let captcha = builder.generate(b"seed", None);
+        let plain = CaptchaBuilder::new().complexity(1).generate(b"seed", None);
+        assert_eq!(captcha.text(), plain.text());
+        assert_eq!(captcha.image(), builder.generate(b"seed", None).image());
+        // The middle row crosses every tile
+        let tinted = (0..140)
+            .filter(|&x| captcha.image().get_pixel(x, 20).0 != [248, 248, 248])
+            .count();
+        assert!(tinted > 70, "{tinted}");
+    }
+
+    #[test]
+    fn it_generates_in_negative_space() {
+        let builder = CaptchaBuilder::new().complexity(1).negative_space(true);
+        let captcha = builder.generate(b"seed", None);
+        let plain = CaptchaBuilder::new().complexity(1).generate(b"seed", None);
+        assert_eq!(captcha.text(), plain.text());
+        assert_eq!(captcha.image(), builder.generate(b"seed", None).image());
+        for (_, color) in captcha.char_colors() {
+            assert_eq!(color.0, [248, 248, 248]);
+        }
+        let background = captcha
+            .image()
+            .pixels()
+            .filter(|pixel| pixel.0 == [248, 248, 248])
+            .count();
+        assert!(background < 140 * 40 / 2);
+    }
+
+    #[test]
+    fn it_generates_with_bars() {
+        let builder = CaptchaBuilder::new()
+            .complexity(8)
+            .interference(Interference::Bars);
+        let captcha = builder.generate(b"seed", None);
+        let plain = CaptchaBuilder::new().complexity(8).generate(b"seed", None);
+        assert_eq!(captcha.text(), plain.text());
+        assert_ne!(captcha.image(), plain.image());
+        assert_eq!(captcha.image(), builder.generate(b"seed", None).image());
+    }
+
+    #[test]
+    fn it_generates_with_custom_charset() {
+        let digits = ['2', '3', '4', '5', '6', '7', '8', '9'];
+        let builder = CaptchaBuilder::new().length(6).charset(&digits).unwrap();
+        for seed in [b"seed 0", b"seed 1", b"seed 2"] {
+            let captcha = builder.generate(seed, None);
+            assert_eq!(captcha.text().len(), 6);
+            assert!(captcha.text().chars().all(|c| digits.contains(&c)));
+            assert_eq!(captcha.text(), builder.generate_lazy(seed, None).text());
+            assert_eq!(
+                captcha.image(),
+                builder.clone().build().generate(seed, None).image()
+            );
+        }
+
+        assert!(matches!(
+            CaptchaBuilder::new().charset(&[]),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            CaptchaBuilder::new().charset(&['A', 'B', 'A']),
+            Err(CaptchaError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            CaptchaBuilder::new().charset(&['A', ' ']),
+            Err(CaptchaError::Font(_))
+        ));
+        #[cfg(not(feature = "full-font"))]
+        assert!(matches!(
+            CaptchaBuilder::new().charset(&['A', '\u{4e2d}']),
+            Err(CaptchaError::Font(_))
+        ));
+    }
+
+    #[test]
+    fn it_generates_numeric_captcha() {
+        let builder = CaptchaBuilder::numeric();
+        let captcha = builder.generate(b"seed", None);
+        assert_eq!(captcha.text().len(), 6);
+        assert!(captcha.text().chars().all(|c| DIGIT_CHARS.contains(&c)));
+        assert_eq!(captcha.image().dimensions(), (200, 50));
+        assert_eq!(captcha.image(), builder.generate(b"seed", None).image());
+
+        // The widest digits fit in the canvas
+        let captcha = builder
+            .mode(0)
+            .complexity(1)
+            .generate(b"seed", Some("888888".to_string()));
+        let image = captcha.image();
+        assert!(image.pixels().any(|pixel| pixel.0 == [18, 18, 18]));
+        for y in 0..50 {
+            assert_ne!(image.get_pixel(0, y).0, [18, 18, 18]);
+            assert_ne!(image.get_pixel(199, y).0, [18, 18, 18]);
+        }
+    }
+
+    #[test]
+    fn it_excludes_latin_lookalikes_from_localized_charsets() {
+        const LOOKALIKES: &str = "АВЕЗКМНОРСТУХЬБЙЩаевзкмнорстухьбйщгип\
+                                  ΑΒΕΖΗΙΚΜΝΟΡΤΥΧαγεηικμνορτυχως";
+        for charset in [CYRILLIC_CHARS, GREEK_CHARS] {
+            assert!(charset.iter().all(|c| !LOOKALIKES.contains(*c)));
+            assert!(charset.iter().all(|c| !c.is_ascii()));
+        }
+
+        // The bundled subset fonts map the characters to glyphs without outlines
+        for font in BundledFont::ALL {
+            assert!(matches!(
+                CaptchaBuilder::new()
+                    .bundled_font(*font)
+                    .charset(CYRILLIC_CHARS),
+                Err(CaptchaError::Font(_))
+            ));
+        }
+        assert_eq!(
+            token::normalize_answer("ДЖЯΣΩ"),
+            token::normalize_answer("джяσω")
+        );
+    }
+
+    #[test]
+    fn it_lays_out_wide_glyphs() {
+        use crate::captcha::{is_wide, wide_slots};
+
+        assert!(is_wide('\u{6c49}'));
+        assert!(is_wide('\u{3042}'));
+        assert!(is_wide('\u{d55c}'));
+        assert!(!is_wide('A'));
+
+        // Four glyphs twice as wide as the Latin ones are shrunk to fit the width
+        let (slots, offset, factor) = wide_slots(&[53.0; 4], 140, None);
+        assert!(factor < 1.0);
+        assert_eq!(offset, 0);
+        assert_eq!(slots[0].0, 5);
+        assert!(slots.iter().all(|(left, w)| *left + *w <= 135));
+        // Narrower glyphs keep their size and share the space left
+        let (slots, offset, factor) = wide_slots(&[53.0, 26.0], 140, None);
+        assert_eq!(factor, 1.0);
+        assert!(offset > 0);
+        assert!(slots[0].1 > slots[1].1);
+        let (squeezed, _, _) = wide_slots(&[53.0, 26.0], 140, Some(1.0));
+        assert!(squeezed[0].0 > slots[0].0);
+
+        let builder = CaptchaBuilder::new().mode(0).complexity(1);
+        let text = "\u{6c49}\u{5b57}\u{9a8c}\u{8bc1}".to_string();
+        let captcha = builder.generate(b"seed", Some(text.clone()));
+        assert_eq!(captcha.text(), text);
+        assert_eq!(
+            captcha.image(),
+            builder.generate(b"seed", Some(text)).image()
+        );
+    }
+
+    #[test]
+    fn it_generates_in_grayscale() {
+        for mode in 0..3 {
+            let builder = CaptchaBuilder::new().mode(mode).grayscale(true);
+            let captcha = builder.generate(b"seed", None);
+            let plain = CaptchaBuilder::new().mode(mode).generate(b"seed", None);
+            assert_eq!(captcha.text(), plain.text());
+            assert!(captcha.is_grayscale());
+            assert!(!plain.is_grayscale());
+            assert!(captcha
+                .image()
+                .pixels()
+                .all(|p| p[0] == p[1] && p[1] == p[2]));
+            assert_eq!(captcha.image(), builder.generate(b"seed", None).image());
+            assert_eq!(
+                captcha.image(),
+                builder.generate_lazy(b"seed", None).image()
+            );
+
+            // The text colors are in the half of the gray scale away from the background
+            let background = captcha.image().get_pixel(0, 0)[0];
+            for (_, color) in captcha.char_colors() {
+                assert_eq!(color[0], color[1]);
+                assert!((color[0] as i32 - background as i32).abs() >= 128 - 110);
+            }
+
+            let luma = captcha.luma_image();
+            assert_eq!(luma.dimensions(), captcha.image().dimensions());
+            assert_eq!(luma.get_pixel(7, 9)[0], captcha.image().get_pixel(7, 9)[0]);
+        }
+
+        #[cfg(feature = "png")]
+        {
+            let captcha = CaptchaBuilder::new()
+                .grayscale(true)
+                .generate(b"seed", None);
+            let plain = CaptchaBuilder::new().generate(b"seed", None);
+            let png = captcha.to_bytes(OutputFormat::Png, 0).unwrap();
+            assert!(png.len() < plain.to_bytes(OutputFormat::Png, 0).unwrap().len());
+            // The color type of the IHDR chunk is grayscale
+            assert_eq!(png[25], 0);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn it_generates_with_quality_jitter() {
+        let builder = CaptchaBuilder::new().quality_jitter(Some(0.8));
+        let captcha = builder.generate(b"seed", None);
+        let plain = CaptchaBuilder::new().generate(b"seed", None);
+        assert_eq!(captcha.text(), plain.text());
+        assert_eq!(captcha.image().dimensions(), plain.image().dimensions());
+        assert_ne!(captcha.image(), plain.image());
+        assert_eq!(captcha.image(), builder.generate(b"seed", None).image());
+    }
+
+    #[test]
+    fn it_scales_difficulty() {
+        let easy = CaptchaBuilder::new().difficulty(0.0);
+        assert_eq!((easy.complexity, easy.letter_spacing), (1, Some(8)));
+        let hard = CaptchaBuilder::new().difficulty(1.0);
+        assert_eq!((hard.complexity, hard.letter_spacing), (10, None));
+        assert_eq!(CaptchaBuilder::new().difficulty(7.0).difficulty, Some(1.0));
+
+        let captcha = hard.generate(b"seed", None);
+        let plain = CaptchaBuilder::new().complexity(10).generate(b"seed", None);
+        assert_eq!(captcha.text(), plain.text());
+        assert_ne!(captcha.image(), plain.image());
+        assert_eq!(captcha.image(), hard.generate(b"seed", None).image());
+    }
+
+    #[test]
+    fn it_guards_readability() {
+        let builder = CaptchaBuilder::new().complexity(10);
+        let plain = builder.generate(b"seed", None);
+        let off = builder
+            .clone()
+            .readability_guard(Some(0.0))
+            .generate(b"seed", None);
+        assert_eq!(off.image(), plain.image());
+
+        let guarded = builder.clone().readability_guard(Some(0.9));
+        let mut redrawn = 0;
+        for i in 0..10u8 {
+            let captcha = guarded.generate(&[i], None);
+            let again = guarded.generate(&[i], None);
+            let plain = builder.generate(&[i], None);
+            assert_eq!(captcha.text(), plain.text());
+            assert_eq!(captcha.image(), again.image());
+            redrawn += (captcha.image() != plain.image()) as usize;
+        }
+        assert!(redrawn > 0);
+
+        // Unreadable attempts fall back to the characters without interference, whatever
+        // the complexity
+        let strict = builder.readability_guard(Some(1.0));
+        let captcha = strict.generate(b"seed", None);
+        assert_ne!(captcha.image(), plain.image());
+        assert_eq!(
+            captcha.image(),
+            strict.clone().complexity(1).generate(b"seed", None).image()
+        );
+    }
+
+    #[test]
+    fn it_enforces_work_budget() {
+        let builder = CaptchaBuilder::new().work_budget(Some(10_000));
+        let err = builder.try_generate(&[4u8, 32], None).err().unwrap();
+        assert!(matches!(
+            err,
+            CaptchaError::BudgetExceeded { budget: 10_000, .. }
+        ));
+        assert_eq!(builder.generate(&[4u8, 32], None).text().len(), 4);
+
+        let builder = builder.work_budget(Some(100_000));
+        let captcha = builder.try_generate(&[4u8, 32], None).unwrap();
+        assert_eq!(captcha.image(), builder.generate(&[4u8, 32], None).image());
+    }
+
+    #[test]
+    fn it_generates_captcha_pairs() {
+        let builder = CaptchaBuilder::new();
+        let pair = builder.generate_pair(b"seed", None);
+        let standard = builder.generate(b"seed", None);
+        assert_eq!(pair.standard.image(), standard.image());
+        assert_eq!(pair.accessible.text(), standard.text());
+        assert_eq!(pair.accessible.image().dimensions(), (202, 60));
+
+        let pair = builder.generate_pair(b"seed", Some("abcdefgh".to_string()));
+        assert_eq!(pair.accessible.text(), "abcdefgh");
+        assert_eq!(pair.accessible.image().dimensions(), (394, 60));
+    }
+
+    #[test]
+    fn it_generates_into_existing_captcha() {
+        let builder = CaptchaBuilder::new();
+        let mut captcha = Captcha::default();
+        builder.generate_into(&mut captcha, &[6u8, 32], None);
+        assert_eq!(captcha.image(), builder.generate(&[6u8, 32], None).image());
+
+        let ptr = captcha.image().as_ptr();
+        builder.generate_into(&mut captcha, &[7u8, 32], Some("LDC".to_string()));
+        assert_eq!(captcha.text().as_str(), "LDC");
+        assert_eq!(captcha.image().as_ptr(), ptr);
+        assert_eq!(
+            captcha.image(),
+            builder
+                .generate(&[7u8, 32], Some("LDC".to_string()))
+                .image()
+        );
+
+        let builder = builder.work_budget(Some(1000));
+        assert!(builder
+            .try_generate_into(&mut captcha, &[8u8, 32], None)
+            .is_err());
+    }
+
+    #[test]
+    fn it_generates_captcha_batch() {
+        let builder = CaptchaBuilder::new().complexity(2);
+        let seeds: [&[u8]; 3] = [b"seed 0", b"seed 1", b"seed 2"];
+        let captchas = builder.generate_batch(&seeds);
+        assert_eq!(captchas.len(), 3);
+        for (captcha, seed) in captchas.iter().zip(seeds) {
+            assert_eq!(captcha.image(), builder.generate(seed, None).image());
+        }
+
+        let captchas = builder.generate_batch_with_texts(&[(b"seed 0", "ABC"), (b"seed 1", "XYZ")]);
+        assert_eq!(captchas[0].text().as_str(), "ABC");
+        assert_eq!(captchas[1].text().as_str(), "XYZ");
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn it_encodes_base64_into_buffer() {
+        let captcha = CaptchaBuilder::new().generate(&[10u8, 32], None);
+        let mut buf = String::new();
+        captcha.to_base64_into(40, &mut buf).unwrap();
+        assert_eq!(buf, captcha.to_base64(40));
+
+        buf.clear();
+        captcha.to_base64_into(0, &mut buf).unwrap();
+        assert_eq!(buf, captcha.to_base64(30));
+
+        // The buffers of the captcha and the base64 are reused across generations
+        let builder = CaptchaBuilder::new();
+        let mut captcha = Captcha::default();
+        buf.reserve(buf.len() * 2);
+        builder.generate_into(&mut captcha, b"seed 0", None);
+        let (ptr, image_ptr) = (buf.as_ptr(), captcha.image().as_ptr());
+        for seed in [b"seed 1", b"seed 2"] {
+            builder.generate_into(&mut captcha, seed, None);
+            buf.clear();
+            captcha.to_base64_into(30, &mut buf).unwrap();
+            assert_eq!(buf, captcha.to_base64(30));
+            assert_eq!(buf.as_ptr(), ptr);
+            assert_eq!(captcha.image().as_ptr(), image_ptr);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rusttype")]
+    #[allow(deprecated)]
+    fn it_accepts_rusttype_fonts() {
+        let expected = CaptchaBuilder::new().generate(&[12u8, 32], None);
+        let serif = || {
+            CaptchaBuilder::new()
+                .fonts_from_path("fonts/dejavu-serif-bold-subset.ttf")
+                .unwrap()
+        };
+        assert_ne!(
+            serif().generate(&[12u8, 32], None).image(),
+            expected.image()
+        );
+
+        let owned = rusttype::Font::try_from_vec(FONTS.to_vec()).unwrap();
+        let captcha = serif().fonts(owned).generate(&[12u8, 32], None);
+        assert_eq!(captcha.image(), expected.image());
+
+        // fonts borrowing their data are rebuilt from their tables
+        let borrowed = rusttype::Font::try_from_bytes(FONTS).unwrap();
+        assert!(matches!(borrowed, rusttype::Font::Ref(_)));
+        let captcha = serif().fonts(borrowed).generate(&[12u8, 32], None);
+        assert_eq!(captcha.image(), expected.image());
+    }
+
+    #[test]
+    fn it_loads_fonts_from_bytes_and_path() {
+        let captcha = CaptchaBuilder::new()
+            .fonts_from_path("fonts/arial-rounded-bold-subset.ttf")
+            .unwrap()
+            .generate(&[12u8, 32], None);
+        let expected = CaptchaBuilder::new().generate(&[12u8, 32], None);
+        assert_eq!(captcha.image(), expected.image());
+
+        assert!(CaptchaBuilder::new().fonts_from_bytes(FONTS).is_ok());
+        let err = CaptchaBuilder::new()
+            .fonts_from_bytes(b"not a font".as_slice())
+            .err()
+            .unwrap();
+        assert!(matches!(err, CaptchaError::Font(_)));
+        let err = CaptchaBuilder::new()
+            .fonts_from_path("fonts/missing.ttf")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("fonts/missing.ttf"), "{}", err);
+    }
+
+    #[test]
+    fn it_mixes_fonts_per_character() {
+        let expected = CaptchaBuilder::new().generate(&[13u8, 32], None);
+        // mixing without additional fonts is a no-op
+        let captcha = CaptchaBuilder::new()
+            .mixed_fonts(true)
+            .generate(&[13u8, 32], None);
+        assert_eq!(captcha.image(), expected.image());
+
+        #[cfg(feature = "font-serif")]
+        {
+            let builder = CaptchaBuilder::new()
+                .length(8)
+                .add_font(BundledFont::Serif.font());
+            let captcha = builder.generate(&[13u8, 32], None);
+            assert_eq!(
+                captcha.image(),
+                CaptchaBuilder::new()
+                    .length(8)
+                    .generate(&[13u8, 32], None)
+                    .image()
+            );
+
+            let builder = builder.mixed_fonts(true);
+            let captcha = builder.generate(&[13u8, 32], None);
+            assert_ne!(captcha.image(), expected.image());
+            assert_eq!(
+                captcha.image(),
+                builder.clone().build().generate(&[13u8, 32], None).image()
+            );
+        }
+    }
+
+    #[test]
+    fn it_randomizes_variable_font_axes() {
+        let data = std::fs::read("fonts/test/Cantarell-VF.otf").unwrap();
+        let builder = CaptchaBuilder::new()
+            .length(6)
+            .variable_font(data.clone(), &[FontAxis::weight(100.0, 800.0)])
+            .unwrap();
+        let captcha = builder.generate(&[14u8, 32], None);
+        assert_eq!(
+            captcha.image(),
+            builder.clone().build().generate(&[14u8, 32], None).image()
+        );
+
+        // randomized weights differ from a fixed weight with the same text
+        let fixed = CaptchaBuilder::new()
+            .length(6)
+            .variable_font(data.clone(), &[FontAxis::weight(400.0, 400.0)])
+            .unwrap()
+            .generate(&[14u8, 32], None);
+        assert_eq!(fixed.text(), captcha.text());
+        assert_ne!(fixed.image(), captcha.image());
+
+        assert!(matches!(
+            CaptchaBuilder::new().variable_font(data, &[FontAxis::width(75.0, 100.0)]),
+            Err(CaptchaError::Font(_))
+        ));
+        assert!(CaptchaBuilder::new().variable_font(FONTS, &[]).is_ok());
+    }
+
+    #[test]
+    fn it_generates_accessible_captcha() {
+        let builder = CaptchaBuilder::accessible();
+        let captcha = builder.generate(&[15u8, 32], None);
+        assert_eq!(captcha.image().dimensions(), (200, 60));
+        assert_eq!(
+            captcha.image(),
+            builder.clone().build().generate(&[15u8, 32], None).image()
+        );
+    }
+
+    #[test]
+    fn it_keeps_spaced_glyphs_on_the_canvas() {
+        let font = FontArc::try_from_slice(FONTS).unwrap();
+        for (text, spacing) in [("WWWW", 4), ("WWWWWW", 8), ("MWMW", 20), ("ab3d", 2)] {
+            let mut captcha = Captcha::default();
+            captcha.reset(text, 140, 80, 1);
+            let mut rnd = Rnd::new(b"seed");
+            let mut masks = Vec::new();
+            captcha.draw_characters(
+                &mut |num| rnd.rnd_32(num),
+                std::slice::from_ref(&font),
+                None,
+                Some(spacing),
+                false,
+                Some(0.0),
+                Some(&mut masks),
+                None,
+            );
+
+            // every glyph box is inside the image, left to right without overlapping
+            let columns: Vec<(u32, u32)> = masks
+                .iter()
+                .map(|mask| {
+                    let xs = mask.iter().map(|i| i % 140);
+                    (xs.clone().min().unwrap(), xs.max().unwrap())
+                })
+                .collect();
+            for (i, (left, right)) in columns.iter().enumerate() {
+                assert!(
+                    *left > 0 && *right < 139,
+                    "{text} glyph {i}: {left}..{right}"
+                );
+                if i > 0 {
+                    assert!(*left > columns[i - 1].1, "{text} glyph {i} overlaps");
+                }
+            }
+            // and no glyph is clipped
+            if text.chars().all(|c| c == 'W') {
+                assert!(
+                    masks.iter().all(|mask| mask.len() == masks[0].len()),
+                    "{text}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_renders_same_with_subset_font() {
+        let full =
+            FontArc::try_from_slice(include_bytes!("../fonts/arial-rounded-bold.ttf")).unwrap();
+        for seed in [b"seed 0", b"seed 1", b"seed 2"] {
+            let captcha = CaptchaBuilder::new().length(8).generate(seed, None);
+            let expected = CaptchaBuilder::new()
+                .length(8)
+                .font(full.clone())
+                .generate(seed, None);
+            assert_eq!(captcha.image(), expected.image());
+        }
+    }
+
+    #[test]
+    fn it_checks_the_glyphs_of_custom_text() {
+        let builder = CaptchaBuilder::new();
+        assert!(builder
+            .try_generate(b"seed", Some("AB CD".to_string()))
+            .is_ok());
+        #[cfg(not(feature = "full-font"))]
+        assert!(matches!(
+            builder.try_generate(b"seed", Some("Hello".to_string())),
+            Err(CaptchaError::Font(_))
+        ));
+        assert!(matches!(
+            builder.try_generate(b"seed", Some("AB\u{4e2d}".to_string())),
+            Err(CaptchaError::Font(_))
+        ));
+
+        let full =
+            FontArc::try_from_slice(include_bytes!("../fonts/arial-rounded-bold.ttf")).unwrap();
+        let captcha = builder
+            .font(full)
+            .try_generate(b"seed", Some("Hello".to_string()))
+            .unwrap();
+        assert_eq!(captcha.text(), "Hello");
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn it_never_panics(
+            seed in proptest::collection::vec(any::<u8>(), 0..64),
+            text in proptest::option::of(".{0,40}"),
+            length in any::<u8>(),
+            width in 0u32..400,
+            height in 0u32..200,
+            mode in any::<u8>(),
+            complexity in any::<u32>(),
+        ) {
+            let captcha = CaptchaBuilder::new()
+                .length(length)
+                .width(width)
+                .height(height)
+                .mode(mode)
+                .complexity(complexity)
+                .generate(&seed, text);
+            #[cfg(feature = "jpeg")]
+            prop_assert!(captcha.to_base64(0).starts_with("data:image/jpeg;base64,"));
+            prop_assert!(captcha.image().width() > 0);
+        }
+
+        #[test]
+        fn it_never_panics_with_exclusions(
+            seed in proptest::collection::vec(any::<u8>(), 0..64),
+            charset in proptest::sample::subsequence(BASIC_CHARS.to_vec(), 1..8),
+            excluded in proptest::collection::vec(proptest::sample::select(BASIC_CHARS), 0..8),
+            exclude_first in any::<bool>(),
+        ) {
+            let builder = CaptchaBuilder::new().complexity(1);
+            let builder = if exclude_first {
+                builder.exclude_chars(&excluded).and_then(|b| b.charset(&charset))
+            } else {
+                builder.charset(&charset).and_then(|b| b.exclude_chars(&excluded))
+            };
+            if let Ok(builder) = builder {
+                prop_assert!(builder.try_generate(&seed, None).is_ok());
+                prop_assert_eq!(builder.generate(&seed, None).text().chars().count(), 4);
+            }
+        }
+    }
 }
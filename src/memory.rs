@@ -0,0 +1,98 @@
+#[cfg(feature = "jpeg")]
+use crate::OutputFormat;
+use crate::{glyph::GlyphCache, CaptchaBuilder};
+
+/// The heap usage of generating a captcha with a configuration, in bytes,
+/// see [`CaptchaBuilder::memory_usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The RGB image buffer of a captcha, also the size of every buffer pooled by
+    /// [`crate::CaptchaGenerator`] (up to its pool size).
+    pub image: u64,
+    /// The pre-rasterized glyphs kept by [`crate::CaptchaGenerator`] and the batch generation.
+    pub glyph_cache: u64,
+    /// The JPEG encoded image of a sample captcha with the given quality.
+    #[cfg(feature = "jpeg")]
+    pub encoded: u64,
+    /// The base64 data URL of the sample captcha returned by [`crate::Captcha::to_base64`].
+    #[cfg(feature = "jpeg")]
+    pub base64: u64,
+}
+
+impl MemoryUsage {
+    /// Returns the working set of generating and encoding a captcha with a
+    /// [`crate::CaptchaGenerator`]: the image buffer and the encoded image.
+    pub fn working(&self) -> u64 {
+        #[cfg(feature = "jpeg")]
+        return self.image + self.encoded;
+        #[cfg(not(feature = "jpeg"))]
+        return self.image;
+    }
+
+    /// Returns the peak usage: the glyph cache, the image buffer and the larger of
+    /// the encoded image and its base64 data URL.
+    pub fn peak(&self) -> u64 {
+        #[cfg(feature = "jpeg")]
+        return self.glyph_cache + self.image + self.encoded.max(self.base64);
+        #[cfg(not(feature = "jpeg"))]
+        return self.glyph_cache + self.image;
+    }
+}
+
+impl CaptchaBuilder {
+    /// Reports the heap usage of the configuration, so embedded and canister users can
+    /// reason about heap pressure before deploying a size change. The image has the
+    /// configured size as with [`CaptchaBuilder::generate`]; the encoded sizes are measured by
+    /// generating and encoding a sample captcha with the given JPEG quality.
+    #[cfg_attr(not(feature = "jpeg"), allow(unused_variables))]
+    pub fn memory_usage(&self, quality: u8) -> MemoryUsage {
+        let (width, height) = self.dimensions();
+        let glyphs = GlyphCache::new(self.active_fonts(), &self.active_charset());
+        #[cfg(feature = "jpeg")]
+        let (encoded, base64) = {
+            let sample = self.generate_with(b"ic-captcha:memory", None, Some(&glyphs));
+            let mut buf = Vec::new();
+            sample
+                .encode_into(&mut buf, OutputFormat::Jpeg, quality)
+                .expect("encoding into a vec is infallible");
+            let mut url = String::new();
+            sample
+                .to_base64_into(quality, &mut url)
+                .expect("encoding into a string is infallible");
+            (buf.len() as u64, url.len() as u64)
+        };
+        MemoryUsage {
+            image: width as u64 * height as u64 * 3,
+            glyph_cache: glyphs.heap_bytes(),
+            #[cfg(feature = "jpeg")]
+            encoded,
+            #[cfg(feature = "jpeg")]
+            base64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_memory_usage() {
+        let usage = CaptchaBuilder::new().memory_usage(30);
+        assert_eq!(usage.image, 140 * 40 * 3);
+        assert!(usage.glyph_cache > 0);
+        assert!(usage.peak() > usage.working());
+
+        let larger = CaptchaBuilder::new().width(280).memory_usage(30);
+        assert_eq!(larger.image, 2 * usage.image);
+        assert_eq!(larger.glyph_cache, usage.glyph_cache);
+        #[cfg(feature = "jpeg")]
+        assert!(larger.encoded > usage.encoded && larger.base64 > larger.encoded);
+
+        let mixed = CaptchaBuilder::new()
+            .add_font(crate::BundledFont::Default.font())
+            .mixed_fonts(true)
+            .memory_usage(30);
+        assert!(mixed.glyph_cache > usage.glyph_cache);
+    }
+}
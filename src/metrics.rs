@@ -0,0 +1,121 @@
+use prometheus::{Histogram, HistogramOpts, IntCounter, Registry};
+
+use crate::CaptchaError;
+
+/// Prometheus metrics of a [`crate::ChallengePool`], set with [`crate::ChallengePool::metrics`]
+/// and registered into the registry of the caller with [`CaptchaMetrics::register`].
+/// The metrics are cheap to clone and share their values between clones.
+#[derive(Clone)]
+pub struct CaptchaMetrics {
+    /// The number of issued challenges.
+    pub issued: IntCounter,
+    /// The number of answers verified successfully.
+    pub verified_ok: IntCounter,
+    /// The number of answers failing the verification, excluding expired tokens.
+    pub verified_fail: IntCounter,
+    /// The number of expired tokens.
+    pub expired: IntCounter,
+    /// The time of rendering a captcha in milliseconds.
+    pub generation_ms: Histogram,
+    /// The size of the encoded captcha images in bytes.
+    pub encode_bytes: Histogram,
+}
+
+impl CaptchaMetrics {
+    /// Returns the metrics with names prefixed by `captcha_`.
+    pub fn new() -> Result<Self, CaptchaError> {
+        Self::with_prefix("captcha")
+    }
+
+    /// Returns the metrics with names prefixed by the given prefix and an underscore.
+    pub fn with_prefix(prefix: &str) -> Result<Self, CaptchaError> {
+        let counter = |name: &str, help: &str| {
+            IntCounter::new(format!("{}_{}", prefix, name), help).map_err(internal)
+        };
+        let histogram = |name: &str, help: &str, buckets: Vec<f64>| {
+            Histogram::with_opts(
+                HistogramOpts::new(format!("{}_{}", prefix, name), help).buckets(buckets),
+            )
+            .map_err(internal)
+        };
+        Ok(CaptchaMetrics {
+            issued: counter("issued_total", "The number of issued challenges")?,
+            verified_ok: counter(
+                "verified_ok_total",
+                "The number of answers verified successfully",
+            )?,
+            verified_fail: counter(
+                "verified_fail_total",
+                "The number of answers failing the verification",
+            )?,
+            expired: counter("expired_total", "The number of expired tokens")?,
+            generation_ms: histogram(
+                "generation_ms",
+                "The time of rendering a captcha in milliseconds",
+                vec![0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0],
+            )?,
+            encode_bytes: histogram(
+                "encode_bytes",
+                "The size of the encoded captcha images in bytes",
+                vec![1024.0, 2048.0, 4096.0, 8192.0, 16384.0, 32768.0, 65536.0],
+            )?,
+        })
+    }
+
+    /// Registers the metrics into the registry.
+    pub fn register(&self, registry: &Registry) -> Result<(), CaptchaError> {
+        registry
+            .register(Box::new(self.issued.clone()))
+            .and_then(|_| registry.register(Box::new(self.verified_ok.clone())))
+            .and_then(|_| registry.register(Box::new(self.verified_fail.clone())))
+            .and_then(|_| registry.register(Box::new(self.expired.clone())))
+            .and_then(|_| registry.register(Box::new(self.generation_ms.clone())))
+            .and_then(|_| registry.register(Box::new(self.encode_bytes.clone())))
+            .map_err(internal)
+    }
+
+    // Count the outcome of a verification
+    pub(crate) fn observe_verify(&self, res: &Result<(), CaptchaError>) {
+        match res {
+            Ok(()) => self.verified_ok.inc(),
+            Err(CaptchaError::TokenExpired) => self.expired.inc(),
+            Err(_) => self.verified_fail.inc(),
+        }
+    }
+}
+
+fn internal(err: prometheus::Error) -> CaptchaError {
+    CaptchaError::Internal(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaptchaBuilder, ChallengePool};
+
+    #[test]
+    fn it_records_pool_metrics() {
+        let metrics = CaptchaMetrics::new().unwrap();
+        let registry = Registry::new();
+        metrics.register(&registry).unwrap();
+        assert!(metrics.register(&registry).is_err());
+
+        let generator = CaptchaBuilder::new().complexity(1).build();
+        let pool = ChallengePool::new(generator, b"secret", b"master seed", 1, 60_000)
+            .metrics(metrics.clone());
+        pool.refill();
+        let c = pool.issue(1000);
+        let _ = pool.verify(&c.token, &c.captcha.text(), 2000);
+        let _ = pool.verify(&c.token, "wrong", 2000);
+        let _ = pool.verify(&c.token, "wrong", 62_000);
+        pool.observe_encoded(2000);
+
+        assert_eq!(metrics.issued.get(), 1);
+        assert_eq!(metrics.verified_ok.get(), 1);
+        assert_eq!(metrics.verified_fail.get(), 1);
+        assert_eq!(metrics.expired.get(), 1);
+        assert_eq!(metrics.generation_ms.get_sample_count(), 1);
+        assert_eq!(metrics.encode_bytes.get_sample_sum(), 2000.0);
+        assert_eq!(registry.gather().len(), 6);
+    }
+}
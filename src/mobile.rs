@@ -0,0 +1,199 @@
+//! A UniFFI interface of challenge generation and verification for mobile
+//! backends-for-frontends and on-device tooling. Generate the Kotlin or Swift bindings
+//! from the built library with the `uniffi-bindgen` binary:
+//!
+//! ```sh
+//! cargo rustc --release --lib --features uniffi --crate-type cdylib
+//! cargo run --features uniffi-cli --bin uniffi-bindgen -- generate \
+//!     --library target/release/libic_captcha.so --language kotlin --out-dir out
+//! ```
+
+use std::sync::Arc;
+
+use crate::{CaptchaBuilder, CaptchaError, ChallengePool, ChallengeToken, OutputFormat};
+
+/// The configuration of the generated captchas, the defaults are the same as
+/// [`CaptchaBuilder::new`].
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct CaptchaConfig {
+    #[uniffi(default = 4)]
+    pub length: u8,
+    #[uniffi(default = 140)]
+    pub width: u32,
+    #[uniffi(default = 40)]
+    pub height: u32,
+    #[uniffi(default = 1)]
+    pub mode: u8,
+    #[uniffi(default = 5)]
+    pub complexity: u32,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        CaptchaConfig {
+            length: 4,
+            width: 140,
+            height: 40,
+            mode: 1,
+            complexity: 5,
+        }
+    }
+}
+
+impl CaptchaConfig {
+    fn builder(&self) -> CaptchaBuilder {
+        CaptchaBuilder::new()
+            .length(self.length)
+            .width(self.width)
+            .height(self.height)
+            .mode(self.mode)
+            .complexity(self.complexity)
+    }
+}
+
+/// A generated captcha with its JPEG image.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct GeneratedImage {
+    pub text: String,
+    pub image: Vec<u8>,
+}
+
+/// An issued challenge: the JPEG image and the challenge token to verify the answer with.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct IssuedChallenge {
+    pub image: Vec<u8>,
+    pub mime_type: String,
+    pub token: String,
+    pub expire_at: u64,
+}
+
+/// Generates a captcha with the given random seed and text, the image is encoded
+/// in JPEG with the given quality.
+#[uniffi::export]
+pub fn generate_captcha(
+    seed: Vec<u8>,
+    config: CaptchaConfig,
+    text: Option<String>,
+    quality: u8,
+) -> Result<GeneratedImage, CaptchaError> {
+    let captcha = config.builder().try_generate(&seed, text)?;
+    let mut image = Vec::new();
+    captcha.encode_into(&mut image, OutputFormat::Jpeg, quality)?;
+    Ok(GeneratedImage {
+        text: captcha.text(),
+        image,
+    })
+}
+
+/// Issues challenges from a [`ChallengePool`] and verifies their answers.
+#[derive(uniffi::Object)]
+pub struct ChallengeIssuer {
+    pool: ChallengePool,
+}
+
+#[uniffi::export]
+impl ChallengeIssuer {
+    /// Returns an issuer, see [`ChallengePool::new`] for the arguments.
+    #[uniffi::constructor]
+    pub fn new(
+        config: CaptchaConfig,
+        key: Vec<u8>,
+        master_seed: Vec<u8>,
+        capacity: u32,
+        ttl_ms: u64,
+    ) -> Arc<Self> {
+        Arc::new(ChallengeIssuer {
+            pool: ChallengePool::new(
+                config.builder().build(),
+                &key,
+                &master_seed,
+                capacity as usize,
+                ttl_ms,
+            ),
+        })
+    }
+
+    /// Renders captchas until the pool is full, returns the number of new captchas.
+    pub fn refill(&self) -> u32 {
+        self.pool.refill() as u32
+    }
+
+    /// Issues a challenge at the given time in unix milliseconds.
+    pub fn issue(&self, now_ms: u64) -> Result<IssuedChallenge, CaptchaError> {
+        let challenge = self.pool.issue(now_ms);
+        let mut image = Vec::new();
+        challenge
+            .captcha
+            .encode_into(&mut image, OutputFormat::Jpeg, 30)?;
+        self.pool.observe_encoded(image.len());
+        Ok(IssuedChallenge {
+            image,
+            mime_type: OutputFormat::Jpeg.mime_type().to_string(),
+            token: challenge.token.to_string(),
+            expire_at: challenge.token.expire_at,
+        })
+    }
+
+    /// Verifies the answer of a challenge token at the given time in unix milliseconds.
+    pub fn verify(&self, token: String, answer: String, now_ms: u64) -> Result<(), CaptchaError> {
+        let token: ChallengeToken = token.parse()?;
+        self.pool.verify(&token, &answer, now_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_generates_and_verifies_challenges() {
+        let image = generate_captcha(b"seed".to_vec(), CaptchaConfig::default(), None, 30).unwrap();
+        assert_eq!(
+            image.text,
+            CaptchaBuilder::new().generate(b"seed", None).text()
+        );
+        assert!(generate_captcha(
+            b"seed".to_vec(),
+            CaptchaConfig {
+                width: 100_000,
+                ..Default::default()
+            },
+            None,
+            30
+        )
+        .is_err());
+
+        let issuer = ChallengeIssuer::new(
+            CaptchaConfig::default(),
+            b"secret".to_vec(),
+            vec![7u8; 32],
+            1,
+            60_000,
+        );
+        assert_eq!(issuer.refill(), 1);
+        let challenge = issuer.issue(0).unwrap();
+        assert_eq!(challenge.mime_type, "image/jpeg");
+        assert_eq!(challenge.expire_at, 60_000);
+        let answer = ChallengePool::new(
+            CaptchaBuilder::new().build(),
+            b"secret",
+            &[7u8; 32],
+            1,
+            60_000,
+        )
+        .issue(0)
+        .captcha
+        .text();
+        assert!(issuer
+            .verify(challenge.token.clone(), answer.clone(), 1)
+            .is_ok());
+        assert_eq!(
+            issuer.verify(challenge.token.clone(), "wrong".to_string(), 1),
+            Err(CaptchaError::AnswerMismatch)
+        );
+        assert_eq!(
+            issuer.verify(challenge.token, answer, 60_001),
+            Err(CaptchaError::TokenExpired)
+        );
+    }
+}
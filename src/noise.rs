@@ -0,0 +1,157 @@
+use image::{ImageBuffer, Rgb};
+
+// The number of gaussian samples in the tile of the fast noise
+const TILE_LEN: usize = 256;
+
+// Add gaussian noise with the given mean and standard deviation to every channel of the image.
+// All samples are drawn from the given random stream, so the result is fully deterministic.
+pub(crate) fn gaussian_noise_mut<R>(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    get_rnd: &mut R,
+    mean: f64,
+    stddev: f64,
+) where
+    R: FnMut(u32) -> u32,
+{
+    // Box-Muller transform generates two samples at a time, keep the spare one
+    let mut spare: Option<f64> = None;
+    for v in image.iter_mut() {
+        let n = match spare.take() {
+            Some(n) => n,
+            None => {
+                let (n0, n1) = normal_pair(get_rnd);
+                spare = Some(n1);
+                n0
+            }
+        };
+        *v = (*v as f64 + mean + stddev * n).round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+// Set pixels to black or white with the given probability.
+pub(crate) fn salt_and_pepper_noise_mut<R>(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    get_rnd: &mut R,
+    rate: f64,
+) where
+    R: FnMut(u32) -> u32,
+{
+    if rate <= 0.0 {
+        return;
+    }
+
+    let threshold = (rate.min(1.0) * u32::MAX as f64) as u32;
+    for p in image.pixels_mut() {
+        if get_rnd(u32::MAX) < threshold {
+            *p = if get_rnd(2) == 0 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            };
+        }
+    }
+}
+
+// A cheap approximation of `gaussian_noise_mut` for the fast mode: a tile of gaussian samples
+// is drawn once and repeated over every row from a random offset, so only one random number
+// is drawn per row instead of one per channel value.
+pub(crate) fn tiled_gaussian_noise_mut<R>(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    get_rnd: &mut R,
+    mean: f64,
+    stddev: f64,
+) where
+    R: FnMut(u32) -> u32,
+{
+    let mut tile = [0i16; TILE_LEN];
+    for pair in tile.chunks_exact_mut(2) {
+        let (a, b) = normal_pair(get_rnd);
+        pair[0] = (mean + stddev * a).round().clamp(-255.0, 255.0) as i16;
+        pair[1] = (mean + stddev * b).round().clamp(-255.0, 255.0) as i16;
+    }
+
+    let row_len = image.width() as usize * 3;
+    if row_len == 0 {
+        return;
+    }
+    for row in image.chunks_mut(row_len) {
+        let offset = get_rnd(TILE_LEN as u32) as usize;
+        for (i, v) in row.iter_mut().enumerate() {
+            *v = (*v as i16 + tile[(offset + i) % TILE_LEN]).clamp(0, 255) as u8;
+        }
+    }
+}
+
+// A cheap approximation of `salt_and_pepper_noise_mut` for the fast mode: the expected number
+// of pixels are picked at random positions, instead of drawing a random number per pixel.
+pub(crate) fn sparse_salt_and_pepper_noise_mut<R>(
+    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    get_rnd: &mut R,
+    rate: f64,
+) where
+    R: FnMut(u32) -> u32,
+{
+    let (width, height) = image.dimensions();
+    if rate <= 0.0 || width == 0 || height == 0 {
+        return;
+    }
+
+    let count = (rate.min(1.0) * width as f64 * height as f64).round() as u64;
+    for _ in 0..count {
+        let (x, y) = (get_rnd(width), get_rnd(height));
+        let color = if get_rnd(2) == 0 { 0 } else { 255 };
+        image.put_pixel(x, y, Rgb([color; 3]));
+    }
+}
+
+// Return two independent standard normal samples
+fn normal_pair<R>(get_rnd: &mut R) -> (f64, f64)
+where
+    R: FnMut(u32) -> u32,
+{
+    // u1 must be in (0, 1] to keep ln(u1) finite
+    let u1 = (get_rnd(u32::MAX) as f64 + 1.0) / u32::MAX as f64;
+    let u2 = get_rnd(u32::MAX) as f64 / u32::MAX as f64;
+    let r = (-2.0 * u1.min(1.0).ln()).sqrt();
+    let theta = 2.0 * std::f64::consts::PI * u2;
+    (r * theta.cos(), r * theta.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rnd;
+
+    #[test]
+    fn it_adds_deterministic_noise() {
+        let render = |seed: &[u8]| {
+            let mut rnd = Rnd::new(seed);
+            let mut get_rnd = |num: u32| rnd.rnd_32(num);
+            let mut image = ImageBuffer::from_pixel(40, 20, Rgb([128u8, 128, 128]));
+            gaussian_noise_mut(&mut image, &mut get_rnd, 0.0, 20.0);
+            salt_and_pepper_noise_mut(&mut image, &mut get_rnd, 0.05);
+            image
+        };
+
+        let a = render(b"seed");
+        assert_eq!(a, render(b"seed"));
+        assert_ne!(a, render(b"other seed"));
+
+        let mean = a.iter().map(|v| *v as f64).sum::<f64>() / a.len() as f64;
+        assert!((mean - 128.0).abs() < 10.0, "mean: {}", mean);
+
+        let render_fast = |seed: &[u8]| {
+            let mut rnd = Rnd::new(seed);
+            let mut get_rnd = |num: u32| rnd.rnd_32(num);
+            let mut image = ImageBuffer::from_pixel(40, 20, Rgb([128u8, 128, 128]));
+            tiled_gaussian_noise_mut(&mut image, &mut get_rnd, 0.0, 20.0);
+            sparse_salt_and_pepper_noise_mut(&mut image, &mut get_rnd, 0.05);
+            image
+        };
+        let b = render_fast(b"seed");
+        assert_eq!(b, render_fast(b"seed"));
+        assert_ne!(b, a);
+        let mean = b.iter().map(|v| *v as f64).sum::<f64>() / b.len() as f64;
+        assert!((mean - 128.0).abs() < 10.0, "mean: {}", mean);
+    }
+}
@@ -0,0 +1,138 @@
+use sha3::{Digest, Sha3_256};
+
+use crate::{token::constant_time_eq, Captcha, CaptchaBuilder, CaptchaError};
+
+// Domain separation for the values derived from the secret key
+const CODE_DOMAIN: &[u8] = b"ic-captcha:otp-code";
+const SEED_DOMAIN: &[u8] = b"ic-captcha:otp-seed";
+
+// The digits of the codes, 0 and 1 are excluded as they are easily confused with O and l
+const DIGITS: &[u8; 8] = b"23456789";
+
+/// A time-bound one-time code, a low-friction fallback when the image challenge fails repeatedly.
+/// Like TOTP, the code is derived from a secret key, the time window and a context
+/// (e.g. the user or session id), so nothing is stored between issuing and verifying.
+/// The code is rendered as a captcha image and verified with clock-skew tolerance.
+/// Codes only contain the digits 2-9.
+#[derive(Clone)]
+pub struct OneTimeCode {
+    key: Vec<u8>,
+    window_ms: u64,
+    digits: u8,
+    skew: u64,
+}
+
+impl OneTimeCode {
+    /// Returns a generator of 6-digit codes valid for a window of `window_ms` milliseconds,
+    /// tolerating one window of clock skew.
+    pub fn new(key: &[u8], window_ms: u64) -> Self {
+        OneTimeCode {
+            key: key.to_vec(),
+            window_ms: window_ms.max(1),
+            digits: 6,
+            skew: 1,
+        }
+    }
+
+    /// Set the number of digits of the codes, 4-10, default is 6.
+    pub fn digits(mut self, digits: u8) -> Self {
+        self.digits = digits.clamp(4, 10);
+        self
+    }
+
+    /// Set the number of windows before and after the current one accepted by
+    /// [`OneTimeCode::verify`], default is 1.
+    pub fn skew(mut self, windows: u64) -> Self {
+        self.skew = windows;
+        self
+    }
+
+    /// Returns the code of the context at the given time in unix milliseconds.
+    pub fn code(&self, context: &[u8], now_ms: u64) -> String {
+        self.code_at(context, now_ms / self.window_ms)
+    }
+
+    /// Renders the code of the context at the given time as a captcha with the builder.
+    /// The image is derived from the key, the window and the context, so it is the same
+    /// within a window.
+    pub fn captcha(&self, builder: &CaptchaBuilder, context: &[u8], now_ms: u64) -> Captcha {
+        let window = now_ms / self.window_ms;
+        let seed = self.derive(SEED_DOMAIN, context, window);
+        builder.generate(&seed, Some(self.code_at(context, window)))
+    }
+
+    /// Verifies a code of the context at the given time in unix milliseconds,
+    /// accepting the codes of the windows within the skew. Whitespace is ignored.
+    pub fn verify(&self, context: &[u8], code: &str, now_ms: u64) -> Result<(), CaptchaError> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let window = now_ms / self.window_ms;
+        let mut valid = false;
+        for w in window.saturating_sub(self.skew)..=window.saturating_add(self.skew) {
+            valid |= constant_time_eq(self.code_at(context, w).as_bytes(), code.as_bytes());
+        }
+        if valid {
+            Ok(())
+        } else {
+            Err(CaptchaError::AnswerMismatch)
+        }
+    }
+
+    fn code_at(&self, context: &[u8], window: u64) -> String {
+        let hash = self.derive(CODE_DOMAIN, context, window);
+        hash[..self.digits as usize]
+            .iter()
+            .map(|b| DIGITS[(*b as usize) % DIGITS.len()] as char)
+            .collect()
+    }
+
+    fn derive(&self, domain: &[u8], context: &[u8], window: u64) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(domain);
+        hasher.update((self.key.len() as u64).to_be_bytes());
+        hasher.update(&self.key);
+        hasher.update((context.len() as u64).to_be_bytes());
+        hasher.update(context);
+        hasher.update(window.to_be_bytes());
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_verifies_codes_with_skew() {
+        let otp = OneTimeCode::new(b"secret", 60_000);
+        let code = otp.code(b"user-1", 120_000);
+        assert_eq!(code.len(), 6);
+        assert!(code.bytes().all(|b| DIGITS.contains(&b)));
+        assert_eq!(otp.code(b"user-1", 179_999), code);
+        assert_ne!(otp.code(b"user-2", 120_000), code);
+
+        assert!(otp.verify(b"user-1", &code, 150_000).is_ok());
+        assert!(otp
+            .verify(b"user-1", &format!(" {} ", code), 60_000)
+            .is_ok());
+        assert!(otp.verify(b"user-1", &code, 239_999).is_ok());
+        assert_eq!(
+            otp.verify(b"user-1", &code, 240_000),
+            Err(CaptchaError::AnswerMismatch)
+        );
+        assert_eq!(
+            otp.verify(b"user-2", &code, 120_000),
+            Err(CaptchaError::AnswerMismatch)
+        );
+        assert!(otp
+            .clone()
+            .skew(0)
+            .verify(b"user-1", &code, 60_000)
+            .is_err());
+
+        let captcha = otp.captcha(&CaptchaBuilder::new(), b"user-1", 130_000);
+        assert_eq!(captcha.text(), code);
+        let same = otp.captcha(&CaptchaBuilder::new(), b"user-1", 170_000);
+        assert_eq!(captcha.image(), same.image());
+        assert_eq!(otp.digits(8).code(b"user-1", 0).len(), 8);
+    }
+}
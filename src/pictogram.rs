@@ -0,0 +1,272 @@
+use ab_glyph::{Font, FontArc};
+use std::sync::Arc;
+
+use crate::font::has_glyph;
+use crate::token::normalize_answer;
+use crate::{CaptchaBuilder, CaptchaError};
+
+/// A set of named pictograms, e.g. emoji, with the font they are drawn with,
+/// see [`CaptchaBuilder::pictograms`].
+#[derive(Clone)]
+pub struct PictogramSet {
+    font: FontArc,
+    pictograms: Arc<[(char, Box<str>)]>,
+}
+
+/// The expected answer of the pictogram captchas, see [`crate::Captcha::answer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PictogramAnswer {
+    /// The names of the pictograms in the order they are drawn, e.g. "cat sun key".
+    #[default]
+    Names,
+    /// The position of the pictogram named by [`crate::Captcha::prompt`], starting from 1.
+    Position,
+}
+
+impl PictogramSet {
+    /// Returns a set of the given pictograms and their names, drawn with the font. Returns an
+    /// error if there are fewer than 2 pictograms, duplicate pictograms or names, or a pictogram
+    /// without an outline in the font.
+    ///
+    /// Pictograms are single code points drawn as outlines in the text colors, like the
+    /// characters. Color glyphs are out of scope: bitmap glyphs (CBDT, sbix) are rejected and
+    /// only the base outline of layered glyphs (COLR) is drawn, so use a font with outlines such
+    /// as Noto Emoji rather than Noto Color Emoji. Emoji made of several code points (ZWJ
+    /// sequences, flags, skin tones) are not supported either.
+    pub fn new<S: AsRef<str>>(
+        font: FontArc,
+        pictograms: &[(char, S)],
+    ) -> Result<Self, CaptchaError> {
+        if pictograms.len() < 2 {
+            return Err(CaptchaError::InvalidConfig(
+                "at least 2 pictograms required".to_string(),
+            ));
+        }
+        for (i, (symbol, name)) in pictograms.iter().enumerate() {
+            let name = name.as_ref();
+            if normalize_answer(name).is_empty() {
+                return Err(CaptchaError::InvalidConfig(format!(
+                    "empty name of pictogram {:?}",
+                    symbol
+                )));
+            }
+            if pictograms[..i]
+                .iter()
+                .any(|(s, n)| s == symbol || normalize_answer(n.as_ref()) == normalize_answer(name))
+            {
+                return Err(CaptchaError::InvalidConfig(format!(
+                    "duplicate pictogram {:?} or name {:?}",
+                    symbol, name
+                )));
+            }
+            if !has_glyph(&font, *symbol) {
+                let reason = if font.glyph_id(*symbol).0 == 0 {
+                    "no glyph"
+                } else {
+                    "no outline, color bitmap glyphs are not supported,"
+                };
+                return Err(CaptchaError::Font(format!(
+                    "{} of pictogram {:?}",
+                    reason, symbol
+                )));
+            }
+        }
+        Ok(PictogramSet {
+            font,
+            pictograms: pictograms
+                .iter()
+                .map(|(symbol, name)| (*symbol, Box::from(name.as_ref())))
+                .collect(),
+        })
+    }
+
+    /// Returns the name of the pictogram.
+    pub fn name(&self, symbol: char) -> Option<&str> {
+        self.pictograms
+            .iter()
+            .find(|(s, _)| *s == symbol)
+            .map(|(_, name)| &**name)
+    }
+}
+
+impl CaptchaBuilder {
+    /// Draw pictograms of the set instead of the characters of the charset, with the font of
+    /// the set. The pictograms are drawn from the seed without repeats, as many as the length
+    /// up to the size of the set, and laid out by their measured widths. The expected answer
+    /// is returned by [`crate::Captcha::answer`] rather than [`crate::Captcha::text`], and is
+    /// the one the challenge tokens are issued for.
+    pub fn pictograms(mut self, set: PictogramSet, answer: PictogramAnswer) -> Self {
+        self.pictograms = Some((set, answer));
+        self
+    }
+
+    // Fill the code with pictograms drawn from the random stream, returns false
+    // if no pictograms are set
+    pub(crate) fn random_pictograms<R>(&self, get_rnd: &mut R, code: &mut Vec<char>) -> bool
+    where
+        R: FnMut(u32) -> u32,
+    {
+        let Some((set, _)) = &self.pictograms else {
+            return false;
+        };
+
+        let mut symbols: Vec<char> = set.pictograms.iter().map(|(s, _)| *s).collect();
+        let n = (self.length as usize).min(symbols.len());
+        for i in 0..n {
+            let j = i + get_rnd((symbols.len() - i) as u32) as usize;
+            symbols.swap(i, j);
+        }
+        code.clear();
+        code.extend(&symbols[..n]);
+        true
+    }
+
+    // Return the answer and the prompt of a pictogram captcha with the given text, the target
+    // of the position is drawn from the random stream. Returns None without pictograms.
+    pub(crate) fn pictogram_answer<R>(
+        &self,
+        get_rnd: &mut R,
+        text: &str,
+    ) -> Option<(String, Option<String>)>
+    where
+        R: FnMut(u32) -> u32,
+    {
+        let (set, answer) = self.pictograms.as_ref()?;
+        let name = |c: char| set.name(c).map_or_else(|| c.to_string(), str::to_string);
+        match answer {
+            PictogramAnswer::Names => {
+                Some((text.chars().map(name).collect::<Vec<_>>().join(" "), None))
+            }
+            PictogramAnswer::Position => {
+                let len = text.chars().count();
+                if len == 0 {
+                    return Some((String::new(), None));
+                }
+                let target = get_rnd(len as u32) as usize;
+                let symbol = text.chars().nth(target).expect("target within the text");
+                Some(((target + 1).to_string(), Some(name(symbol))))
+            }
+        }
+    }
+
+    // Return the font of the pictograms
+    pub(crate) fn pictogram_font(&self) -> Option<&FontArc> {
+        self.pictograms.as_ref().map(|(set, _)| &set.font)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FONTS;
+
+    fn set() -> PictogramSet {
+        let font = FontArc::try_from_slice(FONTS).unwrap();
+        PictogramSet::new(
+            font,
+            &[
+                ('A', "ant"),
+                ('B', "bee"),
+                ('C', "cat"),
+                ('D', "dog"),
+                ('E', "eel"),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_generates_pictogram_captchas() {
+        let builder = CaptchaBuilder::new()
+            .length(3)
+            .pictograms(set(), PictogramAnswer::Names);
+        for seed in [b"seed 0", b"seed 1", b"seed 2"] {
+            let captcha = builder.generate(seed, None);
+            let text: Vec<char> = captcha.text().chars().collect();
+            assert_eq!(text.len(), 3);
+            assert!(text.iter().all(|c| "ABCDE".contains(*c)));
+            assert!(text.windows(2).all(|w| w[0] != w[1]));
+            let names: Vec<String> = text
+                .iter()
+                .map(|c| set().name(*c).unwrap().to_string())
+                .collect();
+            assert_eq!(captcha.answer(), names.join(" "));
+            assert_eq!(captcha.prompt(), None);
+
+            let lazy = builder.generate_lazy(seed, None);
+            assert_eq!(lazy.answer(), captcha.answer());
+            assert_eq!(lazy.image(), captcha.image());
+        }
+
+        let builder = builder
+            .length(8)
+            .pictograms(set(), PictogramAnswer::Position);
+        let captcha = builder.generate(b"seed", None);
+        assert_eq!(captcha.text().chars().count(), 5);
+        let position: usize = captcha.answer().parse().unwrap();
+        let target = captcha.text().chars().nth(position - 1).unwrap();
+        assert_eq!(captcha.prompt(), set().name(target));
+        let lazy = builder.generate_lazy(b"seed", None);
+        assert_eq!(lazy.answer(), captcha.answer());
+        assert_eq!(lazy.prompt(), captcha.prompt());
+
+        // The challenge tokens are issued for the answer
+        let pool = crate::ChallengePool::new(builder.build(), b"secret", b"master seed", 0, 60_000);
+        let challenge = pool.issue(1000);
+        assert!(pool
+            .verify(&challenge.token, &challenge.captcha.answer(), 2000)
+            .is_ok());
+
+        // Other captchas are answered with their text
+        let captcha = CaptchaBuilder::new().generate(b"seed", None);
+        assert_eq!(captcha.answer(), captcha.text());
+    }
+
+    #[test]
+    fn it_validates_pictogram_sets() {
+        let font = FontArc::try_from_slice(FONTS).unwrap();
+        assert!(PictogramSet::new(font.clone(), &[('A', "ant")]).is_err());
+        assert!(PictogramSet::new(font.clone(), &[('A', "ant"), ('B', " ")]).is_err());
+        assert!(PictogramSet::new(font.clone(), &[('A', "ant"), ('B', "Ant")]).is_err());
+        assert!(PictogramSet::new(font.clone(), &[('A', "ant"), ('A', "bee")]).is_err());
+        assert!(matches!(
+            PictogramSet::new(font, &[('A', "ant"), ('\u{1f41d}', "bee")]),
+            Err(CaptchaError::Font(_))
+        ));
+    }
+
+    #[test]
+    fn it_draws_emoji_pictograms() {
+        let data = std::fs::read("fonts/test/NotoEmoji-Regular.ttf").unwrap();
+        let font = FontArc::try_from_vec(data).unwrap();
+        let set = PictogramSet::new(
+            font.clone(),
+            &[
+                ('\u{1f431}', "cat"),
+                ('\u{2600}', "sun"),
+                ('\u{1f511}', "key"),
+                ('\u{1f41d}', "bee"),
+                ('\u{1f41f}', "fish"),
+            ],
+        )
+        .unwrap();
+        let builder = CaptchaBuilder::new()
+            .length(4)
+            .complexity(1)
+            .pictograms(set.clone(), PictogramAnswer::Names);
+        let captcha = builder.generate(b"seed", None);
+        let names: Vec<&str> = captcha
+            .text()
+            .chars()
+            .map(|c| set.name(c).unwrap())
+            .collect();
+        assert_eq!(names.len(), 4);
+        assert_eq!(captcha.answer(), names.join(" "));
+
+        // letters are not in the emoji font
+        assert!(matches!(
+            PictogramSet::new(font, &[('\u{1f431}', "cat"), ('A', "ant")]),
+            Err(CaptchaError::Font(_))
+        ));
+    }
+}
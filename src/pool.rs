@@ -0,0 +1,68 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+// Pooled buffers keyed by image dimensions
+type Buffers = HashMap<(u32, u32), Vec<Vec<u8>>>;
+
+/// A pool of reusable image buffers keyed by image dimensions.
+/// It is used by [`crate::CaptchaGenerator`] to avoid allocating a new buffer for every captcha,
+/// captchas are returned to the pool with [`crate::CaptchaGenerator::recycle`].
+pub struct ImagePool {
+    max_per_size: usize,
+    buffers: Mutex<Buffers>,
+}
+
+impl ImagePool {
+    /// Returns a pool that keeps at most `max_per_size` buffers for each image dimensions.
+    pub fn new(max_per_size: usize) -> Self {
+        ImagePool {
+            max_per_size,
+            buffers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a buffer for an image with the given dimensions from the pool,
+    /// or returns an empty buffer if there is none.
+    pub fn take(&self, width: u32, height: u32) -> Vec<u8> {
+        self.lock()
+            .get_mut(&(width, height))
+            .and_then(|list| list.pop())
+            .unwrap_or_default()
+    }
+
+    /// Puts a buffer of an image with the given dimensions back to the pool.
+    /// The buffer is dropped if the pool is full for the dimensions.
+    pub fn put(&self, width: u32, height: u32, buf: Vec<u8>) {
+        if self.max_per_size == 0 || buf.capacity() == 0 {
+            return;
+        }
+
+        let mut buffers = self.lock();
+        let list = buffers.entry((width, height)).or_default();
+        if list.len() < self.max_per_size {
+            list.push(buf);
+        }
+    }
+
+    /// Returns the number of pooled buffers for the given dimensions.
+    pub fn len(&self, width: u32, height: u32) -> usize {
+        self.lock()
+            .get(&(width, height))
+            .map_or(0, |list| list.len())
+    }
+
+    /// Returns true if there is no pooled buffer for the given dimensions.
+    pub fn is_empty(&self, width: u32, height: u32) -> bool {
+        self.len(width, height) == 0
+    }
+
+    // The pooled buffers stay valid even if a thread panicked while holding the lock
+    fn lock(&self) -> MutexGuard<'_, Buffers> {
+        match self.buffers.lock() {
+            Ok(buffers) => buffers,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::{Captcha, CaptchaBuilder, CaptchaError, CaptchaGenerator};
+
+/// A registry of per-tenant profiles, so one service can serve differently branded
+/// and differently hardened captchas. A profile is a [`CaptchaBuilder`]: its size, length
+/// and complexity are the configuration, its color mode and fonts are the theme, and
+/// its size limits and work budget are the policy enforced by [`ProfileRegistry::generate_for`].
+/// Every profile is built into a [`CaptchaGenerator`] once, tenants without a profile
+/// use the default profile.
+pub struct ProfileRegistry {
+    default: Arc<CaptchaGenerator>,
+    profiles: RwLock<HashMap<String, Arc<CaptchaGenerator>>>,
+}
+
+impl Default for ProfileRegistry {
+    fn default() -> Self {
+        Self::new(CaptchaBuilder::new())
+    }
+}
+
+impl ProfileRegistry {
+    /// Returns a registry with the given default profile.
+    pub fn new(default: CaptchaBuilder) -> Self {
+        ProfileRegistry {
+            default: Arc::new(default.build()),
+            profiles: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Inserts or replaces the profile of a tenant.
+    pub fn insert(&self, tenant: impl Into<String>, profile: CaptchaBuilder) {
+        let generator = Arc::new(profile.build());
+        self.profiles
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(tenant.into(), generator);
+    }
+
+    /// Removes the profile of a tenant, returns true if it was registered.
+    pub fn remove(&self, tenant: &str) -> bool {
+        self.profiles
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(tenant)
+            .is_some()
+    }
+
+    /// Returns true if the tenant has its own profile.
+    pub fn contains(&self, tenant: &str) -> bool {
+        self.profiles
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .contains_key(tenant)
+    }
+
+    /// Returns the generator of a tenant, or the default one if the tenant has no profile.
+    pub fn get(&self, tenant: &str) -> Arc<CaptchaGenerator> {
+        self.profiles
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(tenant)
+            .unwrap_or(&self.default)
+            .clone()
+    }
+
+    /// Generate a [`Captcha`] with the profile of a tenant and the given random seed,
+    /// returns an error if the limits or the work budget of the profile are exceeded.
+    pub fn generate_for(&self, tenant: &str, seed: &[u8]) -> Result<Captcha, CaptchaError> {
+        self.get(tenant).try_generate(seed, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_generates_with_tenant_profiles() {
+        let registry = ProfileRegistry::default();
+        registry.insert("dark", CaptchaBuilder::new().mode(2).length(6));
+        registry.insert("strict", CaptchaBuilder::new().work_budget(Some(1)));
+        assert!(registry.contains("dark"));
+        assert!(!registry.contains("unknown"));
+
+        let captcha = registry.generate_for("dark", b"seed").unwrap();
+        let expected = CaptchaBuilder::new()
+            .mode(2)
+            .length(6)
+            .generate(b"seed", None);
+        assert_eq!(captcha.text(), expected.text());
+        assert_eq!(captcha.image(), expected.image());
+
+        let captcha = registry.generate_for("unknown", b"seed").unwrap();
+        let expected = CaptchaBuilder::new().generate(b"seed", None);
+        assert_eq!(captcha.image(), expected.image());
+
+        assert!(matches!(
+            registry.generate_for("strict", b"seed"),
+            Err(CaptchaError::BudgetExceeded { .. })
+        ));
+        assert!(registry.remove("strict"));
+        assert!(registry.generate_for("strict", b"seed").is_ok());
+    }
+}
@@ -0,0 +1,357 @@
+use rocket::{
+    data::{self, Data, FromData, Limits, ToByteUnit},
+    form::{Form, FromForm},
+    http::{ContentType, Header, RawStr, Status},
+    request::{FromRequest, Outcome, Request},
+    response::{self, Responder, Response},
+};
+use std::io::Cursor;
+
+use crate::{token::now_ms, CaptchaError, Challenge, ChallengePool, ChallengeToken, OutputFormat};
+
+/// A request guard passing only requests with a valid captcha answer.
+/// The token and the answer are read from the [`ChallengeToken::HEADER`] and
+/// [`ChallengeToken::ANSWER_HEADER`] headers, or the [`ChallengeToken::PARAM`] and
+/// [`ChallengeToken::ANSWER_PARAM`] query parameters, and verified with the managed [`ChallengePool`].
+///
+/// ```rust,ignore
+/// #[post("/signup")]
+/// fn signup(captcha: CaptchaGuard) -> &'static str {
+///     "welcome"
+/// }
+/// ```
+///
+/// A token passes only one request, it is spent by [`ChallengePool::verify_once`].
+/// Requests with a missing or malformed token fail with `400 Bad Request`,
+/// expired or used tokens and wrong answers with `403 Forbidden`.
+/// The body is not read, use [`CaptchaForm`] for forms submitted with POST.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptchaGuard(pub ChallengeToken);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CaptchaGuard {
+    type Error = CaptchaError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let pool = match managed_pool(req) {
+            Ok(pool) => pool,
+            Err(err) => return Outcome::Error(err),
+        };
+
+        let token = value(req, ChallengeToken::HEADER, ChallengeToken::PARAM);
+        let answer = value(
+            req,
+            ChallengeToken::ANSWER_HEADER,
+            ChallengeToken::ANSWER_PARAM,
+        );
+        match verify(token, answer, pool) {
+            Ok(token) => Outcome::Success(CaptchaGuard(token)),
+            Err(err) => Outcome::Error(with_status(err)),
+        }
+    }
+}
+
+/// A data guard parsing a form body into `T` and passing only forms with a valid captcha
+/// answer, for HTML forms submitted with POST. The token and the answer are read from the
+/// [`ChallengeToken::PARAM`] and [`ChallengeToken::ANSWER_PARAM`] fields of the form, or the
+/// [`ChallengeToken::HEADER`] and [`ChallengeToken::ANSWER_HEADER`] headers, and verified with
+/// the managed [`ChallengePool`]. Other fields are parsed into `T` leniently.
+///
+/// ```rust,ignore
+/// #[derive(FromForm)]
+/// struct Signup {
+///     email: String,
+/// }
+///
+/// #[post("/signup", data = "<signup>")]
+/// fn signup(signup: CaptchaForm<Signup>) -> String {
+///     format!("welcome {}", signup.form.email)
+/// }
+/// ```
+///
+/// The body is limited by the `form` limit of rocket. Requests without a form content type
+/// are forwarded. A form failing to parse into `T` fails with `422 Unprocessable Entity`
+/// before the token is verified, so the token is not spent. Otherwise the outcomes are
+/// those of [`CaptchaGuard`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptchaForm<T> {
+    /// The verified token.
+    pub token: ChallengeToken,
+    /// The parsed form.
+    pub form: T,
+}
+
+// The captcha fields of a form
+#[derive(FromForm)]
+struct CaptchaFields {
+    #[field(name = "captcha_token")]
+    token: Option<String>,
+    #[field(name = "captcha_answer")]
+    answer: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r, T> FromData<'r> for CaptchaForm<T>
+where
+    T: for<'a> FromForm<'a> + Send + 'static,
+{
+    type Error = CaptchaError;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        if !req.content_type().is_some_and(|ct| ct.is_form()) {
+            return data::Outcome::Forward((data, Status::UnsupportedMediaType));
+        }
+        let pool = match managed_pool(req) {
+            Ok(pool) => pool,
+            Err(err) => return data::Outcome::Error(err),
+        };
+
+        let limit = req.limits().get("form").unwrap_or(Limits::FORM);
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(body) => {
+                return data::Outcome::Error((
+                    Status::PayloadTooLarge,
+                    CaptchaError::PayloadTooLarge {
+                        limit: limit.as_u64() as usize,
+                        size: body.n.written.bytes().as_u64() as usize,
+                    },
+                ))
+            }
+            Err(err) => {
+                return data::Outcome::Error((
+                    Status::BadRequest,
+                    CaptchaError::Decode(err.to_string()),
+                ))
+            }
+        };
+
+        let body = RawStr::new(&body);
+        let form = match Form::<T>::parse_encoded(body) {
+            Ok(form) => form,
+            Err(errs) => {
+                return data::Outcome::Error((
+                    Status::UnprocessableEntity,
+                    CaptchaError::Decode(errs.to_string()),
+                ))
+            }
+        };
+        let fields = Form::<CaptchaFields>::parse_encoded(body).unwrap_or(CaptchaFields {
+            token: None,
+            answer: None,
+        });
+        let header = |name| req.headers().get_one(name).map(str::to_string);
+        let token = fields.token.or_else(|| header(ChallengeToken::HEADER));
+        let answer = fields
+            .answer
+            .or_else(|| header(ChallengeToken::ANSWER_HEADER));
+        match verify(token, answer, pool) {
+            Ok(token) => data::Outcome::Success(CaptchaForm { token, form }),
+            Err(err) => data::Outcome::Error(with_status(err)),
+        }
+    }
+}
+
+/// Responds with the captcha image in JPEG and the token in the [`ChallengeToken::HEADER`] header,
+/// so a handler can return `pool.issue(now_ms)` directly.
+impl<'r> Responder<'r, 'static> for Challenge {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        let mut buf = Vec::new();
+        self.captcha
+            .encode_into(&mut buf, OutputFormat::Jpeg, 30)
+            .map_err(|_| Status::InternalServerError)?;
+        Response::build()
+            .header(ContentType::JPEG)
+            .header(Header::new("Cache-Control", "no-store"))
+            .header(Header::new(ChallengeToken::HEADER, self.token.to_string()))
+            .sized_body(buf.len(), Cursor::new(buf))
+            .ok()
+    }
+}
+
+// Return the managed challenge pool
+fn managed_pool<'r>(req: &'r Request<'_>) -> Result<&'r ChallengePool, (Status, CaptchaError)> {
+    req.rocket().state::<ChallengePool>().ok_or_else(|| {
+        (
+            Status::InternalServerError,
+            CaptchaError::Internal("ChallengePool is not managed".to_string()),
+        )
+    })
+}
+
+// Verify the token and the answer carried by the request
+fn verify(
+    token: Option<String>,
+    answer: Option<String>,
+    pool: &ChallengePool,
+) -> Result<ChallengeToken, CaptchaError> {
+    let token = token.ok_or_else(|| CaptchaError::InvalidToken("missing token".to_string()))?;
+    let answer = answer.ok_or(CaptchaError::AnswerMismatch)?;
+    let token: ChallengeToken = token.parse()?;
+    pool.verify_once(&token, &answer, now_ms())?;
+    Ok(token)
+}
+
+fn with_status(err: CaptchaError) -> (Status, CaptchaError) {
+    let status = Status::from_code(err.status_code()).unwrap_or(Status::BadRequest);
+    (status, err)
+}
+
+// Return the value of the header, or the query parameter if the header is absent
+fn value(req: &Request<'_>, header: &str, param: &str) -> Option<String> {
+    if let Some(value) = req.headers().get_one(header) {
+        return Some(value.to_string());
+    }
+    req.query_value::<String>(param).and_then(Result::ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CaptchaBuilder;
+    use rocket::{get, local::blocking::Client, post, routes};
+
+    #[get("/challenge")]
+    fn challenge(pool: &rocket::State<ChallengePool>) -> Challenge {
+        pool.issue(now_ms())
+    }
+
+    #[get("/protected")]
+    fn protected(captcha: CaptchaGuard) -> String {
+        captcha.0.to_string()
+    }
+
+    #[derive(FromForm)]
+    struct Signup {
+        email: String,
+    }
+
+    #[post("/signup", data = "<signup>")]
+    fn signup(signup: CaptchaForm<Signup>) -> String {
+        format!("{} {}", signup.form.email, signup.token)
+    }
+
+    #[test]
+    fn it_guards_forms() {
+        let new_pool = || {
+            ChallengePool::new(
+                CaptchaBuilder::new().build(),
+                b"secret",
+                &[8u8; 32],
+                1,
+                60_000,
+            )
+        };
+        let rocket = rocket::build()
+            .manage(new_pool())
+            .mount("/", routes![challenge, signup]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let res = client.get("/challenge").dispatch();
+        let token = res
+            .headers()
+            .get_one(ChallengeToken::HEADER)
+            .unwrap()
+            .to_string();
+        let answer = new_pool().issue(0).captcha.text();
+        let post = |body: String| {
+            client
+                .post("/signup")
+                .header(ContentType::Form)
+                .body(body)
+                .dispatch()
+        };
+
+        // the form is parsed before the token is verified, so the token is not spent
+        let res = post(format!("captcha_token={}&captcha_answer={}", token, answer));
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let res = post(format!(
+            "email=a%40b.c&captcha_token={}&captcha_answer=+{}",
+            token,
+            answer.to_lowercase()
+        ));
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_string().unwrap(), format!("a@b.c {}", token));
+
+        // the token passes only once
+        let res = post(format!(
+            "email=a%40b.c&captcha_token={}&captcha_answer={}",
+            token, answer
+        ));
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = post("email=a%40b.c".to_string());
+        assert_eq!(res.status(), Status::BadRequest);
+
+        // not a form
+        let res = client
+            .post("/signup")
+            .header(ContentType::JSON)
+            .body("{}")
+            .dispatch();
+        assert_eq!(res.status(), Status::UnsupportedMediaType);
+    }
+
+    #[test]
+    fn it_guards_routes() {
+        let pool = ChallengePool::new(
+            CaptchaBuilder::new().build(),
+            b"secret",
+            &[7u8; 32],
+            1,
+            60_000,
+        );
+        let rocket = rocket::build()
+            .manage(pool)
+            .mount("/", routes![challenge, protected]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let res = client.get("/challenge").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::JPEG));
+        let token = res
+            .headers()
+            .get_one(ChallengeToken::HEADER)
+            .unwrap()
+            .to_string();
+
+        // find the answer by regenerating the same challenge
+        let expected = ChallengePool::new(
+            CaptchaBuilder::new().build(),
+            b"secret",
+            &[7u8; 32],
+            1,
+            60_000,
+        )
+        .issue(0);
+        let answer = expected.captcha.text();
+
+        let res = client
+            .get(format!(
+                "/protected?captcha_token={}&captcha_answer={}",
+                token, answer
+            ))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_string().unwrap(), token);
+
+        // the token passes only once
+        let res = client
+            .get("/protected")
+            .header(Header::new(ChallengeToken::HEADER, token.clone()))
+            .header(Header::new(ChallengeToken::ANSWER_HEADER, answer))
+            .dispatch();
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = client
+            .get("/protected")
+            .header(Header::new(ChallengeToken::HEADER, token))
+            .header(Header::new(ChallengeToken::ANSWER_HEADER, "wrong"))
+            .dispatch();
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = client.get("/protected").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+}
@@ -0,0 +1,261 @@
+use image::{ImageBuffer, Luma, Rgb, Rgba};
+
+use crate::Captcha;
+
+/// The shape of the canvas a captcha is rendered inside, see [`crate::CaptchaBuilder::shape`].
+/// Pixels outside of the shape are filled with the background color, and
+/// [`Captcha::alpha_mask`] marks them transparent for clients compositing the image.
+/// The outside is also transparent in [`Captcha::rgba_image`] of a transparent captcha.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CanvasShape {
+    /// The full rectangular canvas.
+    #[default]
+    Rectangle,
+    /// The canvas with its corners rounded with the given radius in pixels.
+    RoundedRect { radius: u32 },
+    /// The ellipse inscribed in the canvas, a circle on a square canvas.
+    Circle,
+    /// The hexagon inscribed in the canvas with flat top and bottom sides,
+    /// a regular hexagon when the width is about 1.15 times the height.
+    Hexagon,
+}
+
+/// The border drawn along the outline of the canvas shape, see [`crate::CaptchaBuilder::border`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Border {
+    /// The width of the border in pixels.
+    pub width: u32,
+    /// The color of the border.
+    pub color: Rgb<u8>,
+}
+
+impl CanvasShape {
+    /// Returns true if the center of the pixel at (x, y) of a canvas of the given size
+    /// is inside of the shape.
+    pub fn contains(&self, width: u32, height: u32, x: u32, y: u32) -> bool {
+        let (w, h) = (width as f32, height as f32);
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        match *self {
+            CanvasShape::Rectangle => x < width && y < height,
+            CanvasShape::RoundedRect { radius } => {
+                let r = (radius as f32).min(w / 2.0).min(h / 2.0);
+                let cx = px.clamp(r, w - r);
+                let cy = py.clamp(r, h - r);
+                (px - cx).powi(2) + (py - cy).powi(2) <= r * r
+            }
+            CanvasShape::Circle => {
+                let (rx, ry) = (w / 2.0, h / 2.0);
+                ((px - rx) / rx).powi(2) + ((py - ry) / ry).powi(2) <= 1.0
+            }
+            CanvasShape::Hexagon => {
+                // The horizontal inset of the top and bottom vertices
+                let inset = (h / (2.0 * 3f32.sqrt())).min(w / 2.0);
+                let dy = (py - h / 2.0).abs() / (h / 2.0);
+                let dx = inset * dy;
+                dy <= 1.0 && px >= dx && px <= w - dx
+            }
+        }
+    }
+
+    // Returns true if the pixel at (x, y) is inside of the shape shrunk by the given inset
+    // on every side, the pixels inside of the shape but not of the inset shape are its outline
+    pub(crate) fn contains_inset(
+        &self,
+        width: u32,
+        height: u32,
+        x: u32,
+        y: u32,
+        inset: u32,
+    ) -> bool {
+        if x < inset || y < inset || width <= inset * 2 || height <= inset * 2 {
+            return false;
+        }
+        let shape = match *self {
+            CanvasShape::RoundedRect { radius } => CanvasShape::RoundedRect {
+                radius: radius.saturating_sub(inset),
+            },
+            shape => shape,
+        };
+        shape.contains(width - inset * 2, height - inset * 2, x - inset, y - inset)
+    }
+}
+
+impl Captcha {
+    /// Returns the alpha mask of the canvas shape, 255 inside of the shape and 0 outside,
+    /// with the same size as [`Captcha::image`]. Clients can apply it to render the outside
+    /// transparent, e.g. for circular avatar-style widgets.
+    pub fn alpha_mask(&self) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let (width, height) = self.image().dimensions();
+        let shape = self.shape();
+        ImageBuffer::from_fn(width, height, |x, y| {
+            Luma([if shape.contains(width, height, x, y) {
+                255
+            } else {
+                0
+            }])
+        })
+    }
+
+    /// Returns the verification code image with an alpha channel. The background of a
+    /// transparent captcha is fully transparent, see [`crate::CaptchaBuilder::transparent`],
+    /// other captchas are opaque.
+    pub fn rgba_image(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let image = self.image();
+        let background = self.background_color();
+        let transparent = self.is_transparent();
+        ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let pixel = image.get_pixel(x, y);
+            match transparent {
+                true => color_to_alpha(*pixel, background),
+                false => Rgba([pixel[0], pixel[1], pixel[2], 255]),
+            }
+        })
+    }
+}
+
+// Return the most transparent color that gives the pixel when composited over the background,
+// the color to alpha of image editors, so that the anti-aliased edges of the glyphs blend
+// over any other background
+fn color_to_alpha(pixel: Rgb<u8>, background: [u8; 3]) -> Rgba<u8> {
+    let alpha = (0..3)
+        .map(|c| {
+            let (p, b) = (pixel[c] as f32, background[c] as f32);
+            match p.total_cmp(&b) {
+                std::cmp::Ordering::Greater => (p - b) / (255.0 - b),
+                std::cmp::Ordering::Less => (b - p) / b,
+                std::cmp::Ordering::Equal => 0.0,
+            }
+        })
+        .fold(0.0f32, f32::max);
+    if alpha == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let color = |c: usize| {
+        let b = background[c] as f32;
+        (b + (pixel[c] as f32 - b) / alpha)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    Rgba([color(0), color(1), color(2), (alpha * 255.0).round() as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CaptchaBuilder;
+
+    #[test]
+    fn it_renders_inside_shapes() {
+        assert!(CanvasShape::Rectangle.contains(10, 10, 0, 0));
+        assert!(!CanvasShape::Circle.contains(10, 10, 0, 0));
+        assert!(CanvasShape::Circle.contains(10, 10, 5, 0));
+        assert!(!CanvasShape::RoundedRect { radius: 4 }.contains(10, 10, 0, 0));
+        assert!(CanvasShape::RoundedRect { radius: 4 }.contains(10, 10, 4, 0));
+        assert!(!CanvasShape::Hexagon.contains(46, 40, 0, 0));
+        assert!(CanvasShape::Hexagon.contains(46, 40, 0, 20));
+        assert!(CanvasShape::Hexagon.contains(46, 40, 23, 0));
+
+        for shape in [
+            CanvasShape::RoundedRect { radius: 12 },
+            CanvasShape::Circle,
+            CanvasShape::Hexagon,
+        ] {
+            let captcha = CaptchaBuilder::new()
+                .width(120)
+                .height(120)
+                .shape(shape)
+                .generate(b"seed", None);
+            let mask = captcha.alpha_mask();
+            assert_eq!(mask.dimensions(), captcha.image().dimensions());
+            assert_eq!(mask.get_pixel(0, 0), &Luma([0]));
+            assert_eq!(mask.get_pixel(60, 60), &Luma([255]));
+            for (x, y, alpha) in mask.enumerate_pixels() {
+                if alpha[0] == 0 {
+                    assert_eq!(captcha.image().get_pixel(x, y).0, [248, 248, 248]);
+                }
+            }
+
+            let lazy = CaptchaBuilder::new()
+                .width(120)
+                .height(120)
+                .shape(shape)
+                .generate_lazy(b"seed", None);
+            assert_eq!(lazy.alpha_mask(), mask);
+        }
+    }
+
+    #[test]
+    fn it_draws_borders() {
+        let border = Border {
+            width: 2,
+            color: Rgb([0, 102, 204]),
+        };
+        let captcha = CaptchaBuilder::new()
+            .corner_radius(8)
+            .border(Some(border))
+            .generate(b"seed", None);
+        let image = captcha.image();
+        assert_eq!(image.get_pixel(0, 0).0, [248, 248, 248]);
+        assert_eq!(image.get_pixel(70, 0), &border.color);
+        assert_eq!(image.get_pixel(70, 1), &border.color);
+        assert_eq!(image.get_pixel(0, 20), &border.color);
+        assert_eq!(image.get_pixel(139, 20), &border.color);
+        assert_eq!(image.get_pixel(70, 39), &border.color);
+        assert_ne!(image.get_pixel(70, 2), &border.color);
+
+        let captcha = CaptchaBuilder::new()
+            .border(Some(border))
+            .generate(b"seed", None);
+        assert_eq!(captcha.image().get_pixel(0, 0), &border.color);
+        assert_eq!(captcha.alpha_mask().get_pixel(0, 0), &Luma([255]));
+    }
+
+    #[test]
+    fn it_renders_transparent_background() {
+        let builder = CaptchaBuilder::new()
+            .complexity(1)
+            .transparent(true)
+            .shape(CanvasShape::Circle);
+        let captcha = builder.generate(b"seed", None);
+        assert!(captcha.is_transparent());
+        let plain = CaptchaBuilder::new()
+            .complexity(1)
+            .shape(CanvasShape::Circle)
+            .generate(b"seed", None);
+        assert_eq!(captcha.image(), plain.image());
+        assert!(plain.rgba_image().pixels().all(|pixel| pixel[3] == 255));
+
+        let rgba = captcha.rgba_image();
+        assert_eq!(rgba.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(rgba, builder.generate_lazy(b"seed", None).rgba_image());
+        for ((_, _, pixel), (_, _, alpha)) in rgba
+            .enumerate_pixels()
+            .zip(captcha.alpha_mask().enumerate_pixels())
+        {
+            if alpha[0] == 0 {
+                assert_eq!(pixel[3], 0);
+            }
+        }
+        let drawn = rgba.pixels().filter(|pixel| pixel[3] >= 128).count();
+        assert!(drawn > 200, "{drawn}");
+
+        // Compositing over the background gives the image back
+        for (pixel, original) in rgba.pixels().zip(captcha.image().pixels()) {
+            let alpha = pixel[3] as f32 / 255.0;
+            for c in 0..3 {
+                let composited = 248.0 * (1.0 - alpha) + pixel[c] as f32 * alpha;
+                assert!((composited - original[c] as f32).abs() <= 2.0);
+            }
+        }
+
+        #[cfg(feature = "png")]
+        {
+            let png = captcha.to_bytes(crate::OutputFormat::Png, 0).unwrap();
+            // The color type of the IHDR chunk is RGBA
+            assert_eq!(png[25], 6);
+        }
+        #[cfg(feature = "svg")]
+        assert!(!captcha.to_svg().contains("<rect"));
+    }
+}
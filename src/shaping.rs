@@ -0,0 +1,108 @@
+use ab_glyph::{Font, FontArc, GlyphId, PxScale, ScaleFont};
+
+// A glyph of a shaped run, positioned from the origin of the run in pixels
+pub(crate) struct ShapedGlyph {
+    pub(crate) id: GlyphId,
+    // The byte offset of the first character of the glyph in the text
+    pub(crate) cluster: u32,
+    pub(crate) x: f32,
+    // The offset above the baseline
+    pub(crate) y: f32,
+}
+
+// Returns true if the text has characters of a script whose letters join each other or that
+// is written right to left, Arabic, Hebrew, Syriac, Thaana and N'Ko, so that it is drawn
+// as one shaped run rather than character by character
+pub(crate) fn needs_shaping(chars: &[char]) -> bool {
+    chars.iter().any(|c| {
+        matches!(
+            *c as u32,
+            0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10E60..=0x10E7F
+        )
+    })
+}
+
+// Shape the text with the font at the scale, returns the glyphs in visual order, left to
+// right, and the advance of the run in pixels. Returns None if rustybuzz can not parse the font.
+pub(crate) fn shape(font: &FontArc, scale: PxScale, text: &str) -> Option<(Vec<ShapedGlyph>, f32)> {
+    let face = rustybuzz::Face::from_slice(font.font_data(), 0)?;
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let output = rustybuzz::shape(&face, &[], buffer);
+
+    let scaled = font.as_scaled(scale);
+    let (sx, sy) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+    let mut advance = 0.0;
+    let glyphs = output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, position)| {
+            let glyph = ShapedGlyph {
+                id: GlyphId(info.glyph_id as u16),
+                cluster: info.cluster,
+                x: advance + position.x_offset as f32 * sx,
+                y: position.y_offset as f32 * sy,
+            };
+            advance += position.x_advance as f32 * sx;
+            glyph
+        })
+        .collect();
+    Some((glyphs, advance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaptchaBuilder, FONTS};
+
+    #[test]
+    fn it_shapes_right_to_left_text() {
+        assert!(needs_shaping(&['\u{627}', '\u{644}']));
+        assert!(needs_shaping(&['\u{5d0}']));
+        assert!(!needs_shaping(&['A', '\u{6c49}']));
+
+        let font = FontArc::try_from_slice(FONTS).unwrap();
+        let scale = PxScale::from(50.0);
+        let (glyphs, advance) = shape(&font, scale, "ABC").unwrap();
+        assert_eq!(
+            glyphs.iter().map(|g| g.cluster).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(glyphs[0].id, font.glyph_id('A'));
+        assert!(glyphs.windows(2).all(|w| w[0].x < w[1].x));
+        let expected: f32 = "ABC"
+            .chars()
+            .map(|c| font.as_scaled(scale).h_advance(font.glyph_id(c)))
+            .sum();
+        assert!((advance - expected).abs() < 1.0, "{advance} {expected}");
+
+        // The right-to-left run is in visual order, its first character is drawn rightmost
+        let (glyphs, _) = shape(&font, scale, "\u{5d0}\u{5d1}\u{5d2}").unwrap();
+        assert_eq!(
+            glyphs.iter().map(|g| g.cluster).collect::<Vec<_>>(),
+            [4, 2, 0]
+        );
+
+        // The joined Arabic letters take their contextual forms, not the isolated ones
+        let arabic = std::fs::read("fonts/test/Amiri-Regular.ttf").unwrap();
+        let font = FontArc::try_from_vec(arabic.clone()).unwrap();
+        let text = "\u{633}\u{644}\u{645}";
+        let isolated: Vec<GlyphId> = text.chars().map(|c| font.glyph_id(c)).collect();
+        assert!(isolated.iter().all(|id| id.0 != 0));
+        let (glyphs, _) = shape(&font, scale, text).unwrap();
+        assert!(!glyphs.is_empty());
+        assert!(glyphs.iter().all(|g| !isolated.contains(&g.id)));
+
+        let builder = CaptchaBuilder::new().fonts_from_bytes(arabic).unwrap();
+        let text = "\u{627}\u{644}\u{633}\u{644}\u{627}\u{645}".to_string();
+        let captcha = builder.generate(b"seed", Some(text.clone()));
+        assert_eq!(captcha.text(), text);
+        assert_eq!(captcha.char_colors().len(), 6);
+        assert_eq!(
+            captcha.image(),
+            builder.generate_lazy(b"seed", Some(text)).image()
+        );
+    }
+}
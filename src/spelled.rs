@@ -0,0 +1,214 @@
+use std::sync::Arc;
+
+use crate::font::has_glyph;
+use crate::token::normalize_answer;
+use crate::{CaptchaBuilder, CaptchaError};
+
+// The English words of the digits
+const ENGLISH: [&str; 10] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+
+/// The words of the digits 0 to 9 in a locale, see [`CaptchaBuilder::spelled_numbers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberWords {
+    words: Arc<[Box<str>]>,
+}
+
+impl NumberWords {
+    /// Returns the table of the given words of the digits 0 to 9. Returns an error if a word
+    /// is empty, contains whitespace or is the same as another one ignoring case.
+    pub fn new<S: AsRef<str>>(words: [S; 10]) -> Result<Self, CaptchaError> {
+        for (i, word) in words.iter().enumerate() {
+            let word = word.as_ref();
+            if word.is_empty() || word.contains(char::is_whitespace) {
+                return Err(CaptchaError::InvalidConfig(format!(
+                    "invalid word {:?} of digit {}",
+                    word, i
+                )));
+            }
+            if words[..i]
+                .iter()
+                .any(|other| normalize_answer(other.as_ref()) == normalize_answer(word))
+            {
+                return Err(CaptchaError::InvalidConfig(format!(
+                    "duplicate word {:?} of digit {}",
+                    word, i
+                )));
+            }
+        }
+        Ok(NumberWords {
+            words: words.iter().map(|word| Box::from(word.as_ref())).collect(),
+        })
+    }
+
+    /// Returns the English words of the digits, "zero" to "nine". They are spelled with
+    /// letters the default font subset lacks, so they need the `full-font` feature or a font
+    /// covering them.
+    pub fn english() -> Self {
+        NumberWords::new(ENGLISH).expect("the English words are valid")
+    }
+
+    /// Returns the word of the digit, None if it is not a digit.
+    pub fn word(&self, digit: u8) -> Option<&str> {
+        self.words.get(digit as usize).map(|word| &**word)
+    }
+
+    // Return the digit of the word, ignoring case
+    fn digit(&self, word: &str) -> Option<usize> {
+        let word = normalize_answer(word);
+        self.words.iter().position(|w| normalize_answer(w) == word)
+    }
+}
+
+impl CaptchaBuilder {
+    /// Draw random numbers spelled out as the words of the table, e.g. "seven three one",
+    /// while the expected answer returned by [`crate::Captcha::answer`] is the numeric string
+    /// "731". Naive OCR pipelines answer the words, and the words are easier to read than
+    /// distorted digits for users zooming in. The number has as many digits as the length,
+    /// and the words are laid out by their measured widths, so a wider canvas keeps them
+    /// large, e.g. 320 pixels. Returns an error if a character of the words has no glyph in
+    /// the fonts, so set it after the fonts. [`CaptchaBuilder::try_generate`] checks it again
+    /// in case the fonts are changed afterwards.
+    pub fn spelled_numbers(mut self, words: NumberWords) -> Result<Self, CaptchaError> {
+        self.number_words = Some(words);
+        self.check_number_words()?;
+        Ok(self)
+    }
+
+    // Check that every character of the number words has a glyph in the fonts
+    pub(crate) fn check_number_words(&self) -> Result<(), CaptchaError> {
+        let Some(words) = &self.number_words else {
+            return Ok(());
+        };
+
+        for c in words.words.iter().flat_map(|word| word.chars()) {
+            if self.fonts.iter().any(|font| !has_glyph(font, c)) {
+                return Err(CaptchaError::Font(format!("no glyph of character {:?}", c)));
+            }
+        }
+        Ok(())
+    }
+
+    // Fill the code with the words of a number drawn from the random stream, returns false
+    // if no number words are set
+    pub(crate) fn random_spelled<R>(&self, get_rnd: &mut R, code: &mut Vec<char>) -> bool
+    where
+        R: FnMut(u32) -> u32,
+    {
+        let Some(words) = &self.number_words else {
+            return false;
+        };
+
+        code.clear();
+        for i in 0..self.length {
+            if i > 0 {
+                code.push(' ');
+            }
+            code.extend(words.words[get_rnd(10) as usize].chars());
+        }
+        true
+    }
+
+    // Return the numeric answer of a spelled number, the words of the table are replaced with
+    // their digits and other words are kept. Returns None without number words.
+    pub(crate) fn spelled_answer(&self, text: &str) -> Option<String> {
+        let words = self.number_words.as_ref()?;
+        Some(
+            text.split_whitespace()
+                .map(|word| match words.digit(word) {
+                    Some(digit) => digit.to_string(),
+                    None => word.to_string(),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ab_glyph::FontArc;
+
+    #[test]
+    fn it_generates_spelled_numbers() {
+        let words =
+            NumberWords::new(["zr", "wn", "tw", "th", "fr", "fv", "sx", "sv", "et", "nn"]).unwrap();
+        let builder = CaptchaBuilder::new()
+            .width(320)
+            .length(3)
+            .spelled_numbers(words.clone())
+            .unwrap();
+        for seed in [b"seed 0", b"seed 1", b"seed 2"] {
+            let captcha = builder.generate(seed, None);
+            let answer = captcha.answer();
+            assert_eq!(answer.len(), 3);
+            assert!(answer.chars().all(|c| c.is_ascii_digit()));
+            let spelled: Vec<&str> = answer
+                .bytes()
+                .map(|b| words.word(b - b'0').unwrap())
+                .collect();
+            assert_eq!(captcha.text(), spelled.join(" "));
+
+            let lazy = builder.generate_lazy(seed, None);
+            assert_eq!(lazy.answer(), answer);
+            assert_eq!(lazy.image(), captcha.image());
+        }
+        assert_eq!(builder.spelled_answer("SV th wn"), Some("731".to_string()));
+
+        assert!(NumberWords::new(["a", "b", "c", "d", "e", "f", "g", "h", "j", "A"]).is_err());
+        assert!(NumberWords::new(["a b", "b", "c", "d", "e", "f", "g", "h", "j", "k"]).is_err());
+        assert_eq!(NumberWords::english().word(7), Some("seven"));
+        assert_eq!(NumberWords::english().word(10), None);
+        #[cfg(not(feature = "full-font"))]
+        assert!(matches!(
+            CaptchaBuilder::new().spelled_numbers(NumberWords::english()),
+            Err(CaptchaError::Font(_))
+        ));
+    }
+
+    #[test]
+    fn it_renders_english_spelled_numbers() {
+        let full =
+            FontArc::try_from_slice(include_bytes!("../fonts/arial-rounded-bold.ttf")).unwrap();
+        let builder = CaptchaBuilder::new()
+            .width(320)
+            .mode(0)
+            .complexity(1)
+            .font(full)
+            .spelled_numbers(NumberWords::english())
+            .unwrap();
+        let captcha = builder.try_generate(b"seed", None).unwrap();
+        let answer = captcha.answer();
+        assert_eq!(answer.len(), 4);
+        let spelled: Vec<&str> = answer
+            .bytes()
+            .map(|b| ENGLISH[(b - b'0') as usize])
+            .collect();
+        assert_eq!(captcha.text(), spelled.join(" "));
+
+        // Every letter is drawn with its outline, the same text drawn with letters the
+        // font lacks would leave the glyph boxes blank
+        let dark = |captcha: &crate::Captcha| {
+            captcha
+                .image()
+                .pixels()
+                .filter(|pixel| pixel.0.iter().all(|v| *v < 100))
+                .count()
+        };
+        let blank = builder
+            .clone()
+            .try_generate(b"seed", Some(" ".repeat(captcha.text().len())))
+            .unwrap();
+        assert!(dark(&captcha) > dark(&blank) + 1000);
+
+        // The fonts changed after the number words are checked again
+        #[cfg(not(feature = "full-font"))]
+        assert!(matches!(
+            builder
+                .bundled_font(crate::BundledFont::Default)
+                .try_generate(b"seed", None),
+            Err(CaptchaError::Font(_))
+        ));
+    }
+}
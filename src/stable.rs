@@ -0,0 +1,77 @@
+use ic_stable_structures::{storable::Bound, Storable};
+use std::borrow::Cow;
+
+use crate::{AnswerHash, ChallengeToken};
+
+// Tokens and answer hashes have a fixed size, so they can be the values of the stable
+// structures directly, keyed by e.g. the token nonce. Stored bytes are written by `to_bytes`, a decoding failure
+// means corrupted stable memory and traps.
+
+impl Storable for ChallengeToken {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: ChallengeToken::LEN as u32,
+        is_fixed_size: true,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(ChallengeToken::to_bytes(self).to_vec())
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        ChallengeToken::to_bytes(&self).to_vec()
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        ChallengeToken::from_bytes(&bytes).expect("invalid stored challenge token")
+    }
+}
+
+impl Storable for AnswerHash {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: AnswerHash::LEN as u32,
+        is_fixed_size: true,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(AnswerHash::to_bytes(self).to_vec())
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        AnswerHash::to_bytes(&self).to_vec()
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        AnswerHash::from_bytes(&bytes).expect("invalid stored answer hash")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
+
+    #[test]
+    fn it_stores_tokens_and_answer_hashes() {
+        let token = ChallengeToken::new(b"secret", [3u8; 16], 1000, "Ab3d");
+        let hash = AnswerHash {
+            key_id: 7,
+            hash: [9u8; 32],
+        };
+
+        let mut tokens: StableBTreeMap<[u8; 16], ChallengeToken, _> =
+            StableBTreeMap::init(DefaultMemoryImpl::default());
+        tokens.insert(token.nonce, token.clone());
+        assert_eq!(tokens.get(&token.nonce), Some(token.clone()));
+
+        let mut hashes: StableBTreeMap<[u8; 16], AnswerHash, _> =
+            StableBTreeMap::init(DefaultMemoryImpl::default());
+        hashes.insert(token.nonce, hash);
+        assert_eq!(hashes.get(&token.nonce), Some(hash));
+        assert_eq!(
+            Storable::to_bytes(&token).len(),
+            ChallengeToken::LEN,
+            "fixed size"
+        );
+        assert_eq!(Storable::into_bytes(hash).len(), AnswerHash::LEN);
+    }
+}
@@ -0,0 +1,97 @@
+use sha3::{Digest, Sha3_256};
+
+use crate::{Captcha, CaptchaBuilder, CaptchaGenerator};
+
+// Domain separation for the sub-seeds derived from the master seed
+const STREAM_DOMAIN: &[u8] = b"ic-captcha:stream-seed";
+
+/// An endless iterator of captchas from a master seed, see [`CaptchaBuilder::stream`].
+pub struct CaptchaStream {
+    generator: CaptchaGenerator,
+    master_seed: [u8; 32],
+    counter: u64,
+}
+
+impl CaptchaBuilder {
+    /// Returns an endless stream of captchas, every captcha is generated with a sub-seed
+    /// derived from the master seed and its index in the stream, with domain separation.
+    /// Batch jobs and pools can mint unlimited distinct and reproducible challenges from one
+    /// `master_seed`, which should come from a secure random source (e.g. `raw_rand` in a
+    /// canister). The same master seed always yields the same captchas.
+    pub fn stream(&self, master_seed: &[u8]) -> CaptchaStream {
+        CaptchaStream {
+            generator: self.clone().build(),
+            master_seed: Sha3_256::digest(master_seed).into(),
+            counter: 0,
+        }
+    }
+}
+
+impl CaptchaStream {
+    /// Returns the sub-seed of the captcha at the given index of the stream.
+    pub fn seed_at(&self, index: u64) -> [u8; 32] {
+        derive_seed(STREAM_DOMAIN, &self.master_seed, index)
+    }
+
+    /// Returns the index of the next captcha of the stream.
+    pub fn position(&self) -> u64 {
+        self.counter
+    }
+}
+
+// Derive the sub-seed at the index from the master seed, separated by the domain of the user,
+// e.g. the streams and the challenge pools
+pub(crate) fn derive_seed(domain: &[u8], master_seed: &[u8; 32], index: u64) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(domain);
+    hasher.update(master_seed);
+    hasher.update(index.to_be_bytes());
+    hasher.finalize().into()
+}
+
+impl Iterator for CaptchaStream {
+    type Item = Captcha;
+
+    fn next(&mut self) -> Option<Captcha> {
+        let seed = self.seed_at(self.counter);
+        self.counter += 1;
+        Some(self.generator.generate(&seed, None))
+    }
+
+    // Skip the captchas without rendering them
+    fn nth(&mut self, n: usize) -> Option<Captcha> {
+        self.counter = self.counter.saturating_add(n as u64);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_streams_captchas_from_a_master_seed() {
+        let builder = CaptchaBuilder::new().complexity(1);
+        let captchas: Vec<Captcha> = builder.stream(b"master seed").take(4).collect();
+        let ids: std::collections::HashSet<_> = captchas.iter().map(|c| c.id()).collect();
+        assert_eq!(ids.len(), 4);
+
+        let mut stream = builder.stream(b"master seed");
+        assert_eq!(stream.next().unwrap().id(), captchas[0].id());
+        let third = stream.nth(1).unwrap();
+        assert_eq!(third.text(), captchas[2].text());
+        assert_eq!(third.image(), captchas[2].image());
+        assert_eq!(stream.position(), 3);
+
+        let seed = stream.seed_at(3);
+        assert_eq!(builder.generate(&seed, None).text(), captchas[3].text());
+        assert_ne!(
+            builder.stream(b"other seed").next().unwrap().id(),
+            captchas[0].id()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use ab_glyph::{Font, GlyphId, OutlineCurve, PxScale, ScaleFont};
+use image::Rgb;
+use std::fmt::Write;
+
+use crate::Captcha;
+
+// A shape of the vector layout recorded along the raster pipeline
+#[derive(Clone)]
+pub(crate) enum VectorShape {
+    // A glyph outline drawn at the origin, see `outline_path`
+    Glyph {
+        path: String,
+        x: i32,
+        y: i32,
+        color: Rgb<u8>,
+    },
+    // A cubic bezier curve from start to end
+    Curve {
+        start: (i32, i32),
+        end: (i32, i32),
+        control_a: (i32, i32),
+        control_b: (i32, i32),
+        color: Rgb<u8>,
+    },
+    // The outline of an ellipse
+    Ellipse {
+        center: (i32, i32),
+        radius: (i32, i32),
+        color: Rgb<u8>,
+    },
+}
+
+// Return the SVG path data of a glyph outline at the given scale, relative to the same
+// drawing origin as `Glyph::rasterize`, or an empty string if the glyph has no outline
+pub(crate) fn outline_path<F: Font>(font: &F, scale: PxScale, id: GlyphId) -> String {
+    let scaled = font.as_scaled(scale);
+    let (sx, sy, ascent) = (
+        scaled.h_scale_factor(),
+        scaled.v_scale_factor(),
+        scaled.ascent(),
+    );
+    let mut path = String::new();
+    let Some(outline) = font.outline(id) else {
+        return path;
+    };
+
+    let mut last = None;
+    let point = |path: &mut String, p: ab_glyph::Point| {
+        let _ = write!(path, "{:.1} {:.1} ", p.x * sx, ascent - p.y * sy);
+    };
+    for curve in &outline.curves {
+        let (start, end) = match curve {
+            OutlineCurve::Line(p0, p1) => (*p0, *p1),
+            OutlineCurve::Quad(p0, _, p2) => (*p0, *p2),
+            OutlineCurve::Cubic(p0, _, _, p3) => (*p0, *p3),
+        };
+        if last != Some(start) {
+            if last.is_some() {
+                path.push_str("Z ");
+            }
+            path.push('M');
+            point(&mut path, start);
+        }
+        match curve {
+            OutlineCurve::Line(_, p1) => {
+                path.push('L');
+                point(&mut path, *p1);
+            }
+            OutlineCurve::Quad(_, p1, p2) => {
+                path.push('Q');
+                point(&mut path, *p1);
+                point(&mut path, *p2);
+            }
+            OutlineCurve::Cubic(_, p1, p2, p3) => {
+                path.push('C');
+                point(&mut path, *p1);
+                point(&mut path, *p2);
+                point(&mut path, *p3);
+            }
+        }
+        last = Some(end);
+    }
+    if last.is_some() {
+        path.push('Z');
+    }
+    path
+}
+
+impl Captcha {
+    /// Returns the verification code image as an SVG document with the same deterministic
+    /// layout as the raster image: the background, the glyph outlines as paths, and the
+    /// interference curves and ellipses as strokes. The noise and the other raster-only
+    /// stages (tiles, bars, negative space, quality jitter, canvas shapes and stamps)
+    /// are not part of the vector output, so it is much smaller than the raster image.
+    pub fn to_svg(&self) -> String {
+        let (width, height) = self.image().dimensions();
+        let hex = |c: Rgb<u8>| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        // A transparent captcha has no background, see `CaptchaBuilder::transparent`
+        if !self.is_transparent() {
+            let _ = write!(
+                svg,
+                r#"<rect width="{width}" height="{height}" fill="{}"/>"#,
+                hex(Rgb(self.background_color()))
+            );
+        }
+        for shape in self.vectors() {
+            let _ = match shape {
+                VectorShape::Glyph { path, x, y, color } => write!(
+                    svg,
+                    r#"<path transform="translate({x} {y})" fill="{}" d="{path}"/>"#,
+                    hex(*color)
+                ),
+                VectorShape::Curve {
+                    start,
+                    end,
+                    control_a,
+                    control_b,
+                    color,
+                } => write!(
+                    svg,
+                    r#"<path fill="none" stroke="{}" d="M{} {} C{} {} {} {} {} {}"/>"#,
+                    hex(*color),
+                    start.0,
+                    start.1,
+                    control_a.0,
+                    control_a.1,
+                    control_b.0,
+                    control_b.1,
+                    end.0,
+                    end.1
+                ),
+                VectorShape::Ellipse {
+                    center,
+                    radius,
+                    color,
+                } => write!(
+                    svg,
+                    r#"<ellipse fill="none" stroke="{}" cx="{}" cy="{}" rx="{}" ry="{}"/>"#,
+                    hex(*color),
+                    center.0,
+                    center.1,
+                    radius.0,
+                    radius.1
+                ),
+            };
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CaptchaBuilder;
+
+    #[test]
+    fn it_renders_svg() {
+        let builder = CaptchaBuilder::new().complexity(5);
+        let captcha = builder.generate(b"seed", None);
+        let svg = captcha.to_svg();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="140""#));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<path transform").count(), 4);
+        assert_eq!(svg.matches("<ellipse").count(), 4);
+        assert_eq!(svg.matches(r#"<path fill="none""#).count(), 4);
+        assert_eq!(svg, builder.generate(b"seed", None).to_svg());
+        assert_eq!(svg, builder.generate_lazy(b"seed", None).to_svg());
+        assert_eq!(
+            svg,
+            builder.clone().build().generate(b"seed", None).to_svg()
+        );
+
+        let fast = builder.fast(true).generate(b"seed", None).to_svg();
+        assert_eq!(fast.matches("<ellipse").count(), 2);
+    }
+}
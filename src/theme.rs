@@ -0,0 +1,208 @@
+use image::Rgb;
+
+// The minimum contrast ratio of the text colors against the background, WCAG AA for text
+const TEXT_CONTRAST: f32 = 4.5;
+// The minimum contrast ratio of the interference colors against the background
+const INTERFERENCE_CONTRAST: f32 = 3.0;
+// The hue offsets of the palette from the brand hue, in degrees
+const HUE_OFFSETS: [f32; 5] = [0.0, -30.0, 30.0, 150.0, 180.0];
+// The relative luminance where black and white text have the same contrast ratio
+const LUMINANCE_CROSSOVER: f32 = 0.179;
+
+/// The color scheme of a captcha, a named color mode, see [`crate::CaptchaBuilder::color_scheme`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    /// Dark on light, color mode 0.
+    Monochrome,
+    /// Colorful on light, color mode 1.
+    Light,
+    /// Colorful on dark, color mode 2.
+    Dark,
+}
+
+impl ColorScheme {
+    /// Picks the scheme blending into the page background the client reports:
+    /// [`ColorScheme::Light`] on light pages and [`ColorScheme::Dark`] on dark pages.
+    pub fn auto_for_background(background: Rgb<u8>) -> Self {
+        Self::auto_for_luminance(relative_luminance(background))
+    }
+
+    /// Like [`ColorScheme::auto_for_background`], with the WCAG relative luminance
+    /// of the page background, range 0.0-1.0.
+    pub fn auto_for_luminance(luminance: f32) -> Self {
+        if luminance >= LUMINANCE_CROSSOVER {
+            ColorScheme::Light
+        } else {
+            ColorScheme::Dark
+        }
+    }
+
+    /// Returns the color mode of the scheme, see [`crate::CaptchaBuilder::mode`].
+    pub fn mode(self) -> u8 {
+        match self {
+            ColorScheme::Monochrome => 0,
+            ColorScheme::Light => 1,
+            ColorScheme::Dark => 2,
+        }
+    }
+}
+
+/// The colors of a captcha, replacing the palettes of the color mode,
+/// see [`crate::CaptchaBuilder::theme`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// The background color.
+    pub background: Rgb<u8>,
+    /// The colors characters are drawn with, picked at random per character.
+    pub text: Vec<Rgb<u8>>,
+    /// The colors of the interference lines and ellipses, picked at random per stage.
+    pub interference: Vec<Rgb<u8>>,
+}
+
+impl Theme {
+    /// Derives an on-brand theme from a single brand color: a light tint of the brand hue as
+    /// the background, and text and interference colors from the brand hue, its analogous
+    /// hues and its complements. Every text color has a contrast ratio of at least 4.5
+    /// against the background, and every interference color at least 3.0.
+    pub fn from_brand_color(brand: Rgb<u8>) -> Self {
+        let (hue, saturation, _) = rgb_to_hsl(brand);
+        let background = hsl_to_rgb(hue, saturation.min(0.6), 0.96);
+        let saturation = saturation.clamp(0.45, 0.9);
+        let palette = |min_contrast: f32, lightness: f32| -> Vec<Rgb<u8>> {
+            HUE_OFFSETS
+                .iter()
+                .map(|offset| {
+                    let hue = (hue + offset).rem_euclid(360.0);
+                    let mut lightness = lightness;
+                    let mut color = hsl_to_rgb(hue, saturation, lightness);
+                    while Self::contrast(color, background) < min_contrast && lightness > 0.0 {
+                        lightness = (lightness - 0.02).max(0.0);
+                        color = hsl_to_rgb(hue, saturation, lightness);
+                    }
+                    color
+                })
+                .collect()
+        };
+
+        Theme {
+            background,
+            text: palette(TEXT_CONTRAST, 0.45),
+            interference: palette(INTERFERENCE_CONTRAST, 0.6),
+        }
+    }
+
+    /// Returns the WCAG contrast ratio of two colors, range 1.0-21.0.
+    pub fn contrast(a: Rgb<u8>, b: Rgb<u8>) -> f32 {
+        let (la, lb) = (relative_luminance(a), relative_luminance(b));
+        (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+    }
+}
+
+// Return the WCAG relative luminance of a color
+fn relative_luminance(color: Rgb<u8>) -> f32 {
+    let channel = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(color[0]) + 0.7152 * channel(color[1]) + 0.0722 * channel(color[2])
+}
+
+// Return the hue in degrees, the saturation and the lightness of a color
+fn rgb_to_hsl(color: Rgb<u8>) -> (f32, f32, f32) {
+    let [r, g, b] = color.0.map(|c| c as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, saturation, lightness)
+}
+
+// Return the color of a hue in degrees, a saturation and a lightness
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> Rgb<u8> {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    Rgb([r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CaptchaBuilder;
+
+    #[test]
+    fn it_derives_themes_from_brand_colors() {
+        for brand in [
+            Rgb([29, 161, 242]),
+            Rgb([255, 204, 0]),
+            Rgb([0, 0, 0]),
+            Rgb([255, 255, 255]),
+            Rgb([120, 40, 200]),
+        ] {
+            let theme = Theme::from_brand_color(brand);
+            assert_eq!(theme.text.len(), 5);
+            for color in &theme.text {
+                assert!(Theme::contrast(*color, theme.background) >= TEXT_CONTRAST);
+            }
+            for color in &theme.interference {
+                assert!(Theme::contrast(*color, theme.background) >= INTERFERENCE_CONTRAST);
+            }
+        }
+        assert_eq!(rgb_to_hsl(hsl_to_rgb(200.0, 0.5, 0.4)).0.round(), 200.0);
+
+        let theme = Theme::from_brand_color(Rgb([29, 161, 242]));
+        let captcha = CaptchaBuilder::new()
+            .complexity(1)
+            .theme(Some(theme.clone()))
+            .generate(b"seed", None);
+        assert_eq!(captcha.image().get_pixel(0, 0), &theme.background);
+        for (_, color) in captcha.char_colors() {
+            assert!(theme.text.contains(&color));
+        }
+    }
+
+    #[test]
+    fn it_picks_color_schemes_for_backgrounds() {
+        for (background, scheme) in [
+            (Rgb([255, 255, 255]), ColorScheme::Light),
+            (Rgb([240, 236, 228]), ColorScheme::Light),
+            (Rgb([128, 128, 128]), ColorScheme::Light),
+            (Rgb([0, 0, 0]), ColorScheme::Dark),
+            (Rgb([32, 33, 36]), ColorScheme::Dark),
+            (Rgb([0, 51, 102]), ColorScheme::Dark),
+        ] {
+            assert_eq!(ColorScheme::auto_for_background(background), scheme);
+        }
+        assert_eq!(ColorScheme::auto_for_luminance(1.0), ColorScheme::Light);
+        assert_eq!(ColorScheme::auto_for_luminance(0.0), ColorScheme::Dark);
+
+        let captcha = CaptchaBuilder::new()
+            .color_scheme(ColorScheme::auto_for_background(Rgb([10, 10, 10])))
+            .complexity(1)
+            .generate(b"seed", None);
+        assert_eq!(captcha.image().get_pixel(0, 0), &Rgb([18, 18, 18]));
+    }
+}
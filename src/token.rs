@@ -0,0 +1,323 @@
+use base64::{engine::general_purpose, Engine};
+use sha3::{Digest, Sha3_256};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use crate::{CaptchaError, ChallengeId};
+
+// Domain separation for the token MAC
+const TOKEN_DOMAIN: &[u8] = b"ic-captcha:token";
+
+/// A stateless challenge token binding the answer of a captcha with a server secret key.
+/// The server gives the token to the client together with the captcha image,
+/// the client sends it back with the answer and the server verifies them with the same key.
+/// The answer is case-insensitive.
+///
+/// Being stateless, a token verifies again and again with its answer until it expires, so one
+/// solved captcha could be replayed by a bot for the whole TTL. Servers must enforce single use,
+/// e.g. with [`ChallengeToken::verify_once`] and a [`SpentTokens`] set, or
+/// [`crate::ChallengePool::verify_once`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChallengeToken {
+    /// A unique nonce of the challenge.
+    pub nonce: [u8; 16],
+    /// The expiration time of the challenge in unix milliseconds.
+    pub expire_at: u64,
+    /// The MAC over the nonce, expiration time and answer.
+    pub mac: [u8; 32],
+}
+
+impl ChallengeToken {
+    /// The length of the token in bytes.
+    pub const LEN: usize = 16 + 8 + 32;
+
+    /// The default header carrying the token in the web framework integrations.
+    pub const HEADER: &'static str = "x-captcha-token";
+    /// The default header carrying the answer in the web framework integrations.
+    pub const ANSWER_HEADER: &'static str = "x-captcha-answer";
+    /// The default query parameter carrying the token in the web framework integrations.
+    pub const PARAM: &'static str = "captcha_token";
+    /// The default query parameter carrying the answer in the web framework integrations.
+    pub const ANSWER_PARAM: &'static str = "captcha_answer";
+
+    /// Returns a token for the given answer.
+    pub fn new(key: &[u8], nonce: [u8; 16], expire_at: u64, answer: &str) -> Self {
+        ChallengeToken {
+            nonce,
+            expire_at,
+            mac: token_mac(key, &nonce, expire_at, answer),
+        }
+    }
+
+    /// Returns the nonce as the id of the challenge, see [`ChallengeId`].
+    pub fn id(&self) -> ChallengeId {
+        ChallengeId(self.nonce)
+    }
+
+    /// Verifies the answer with the given key at the given time in unix milliseconds.
+    /// It does not track the used tokens, a verified token verifies again until it expires,
+    /// see [`ChallengeToken::verify_once`].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "verify",
+            level = "debug",
+            skip_all,
+            fields(
+                id = %self.id(),
+                expire_at = self.expire_at,
+                now_ms = now_ms,
+                outcome = tracing::field::Empty
+            )
+        )
+    )]
+    pub fn verify(&self, key: &[u8], answer: &str, now_ms: u64) -> Result<(), CaptchaError> {
+        let res = if now_ms > self.expire_at {
+            Err(CaptchaError::TokenExpired)
+        } else if !constant_time_eq(
+            &token_mac(key, &self.nonce, self.expire_at, answer),
+            &self.mac,
+        ) {
+            Err(CaptchaError::AnswerMismatch)
+        } else {
+            Ok(())
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record(
+            "outcome",
+            match &res {
+                Ok(()) => "valid",
+                Err(CaptchaError::TokenExpired) => "expired",
+                Err(_) => "mismatch",
+            },
+        );
+        res
+    }
+
+    /// Verifies the answer like [`ChallengeToken::verify`] and spends the token in the set,
+    /// so it verifies only once. Returns [`CaptchaError::TokenSpent`] if the token was
+    /// verified before.
+    pub fn verify_once(
+        &self,
+        key: &[u8],
+        answer: &str,
+        now_ms: u64,
+        spent: &SpentTokens,
+    ) -> Result<(), CaptchaError> {
+        self.verify(key, answer, now_ms)?;
+        spent.spend(self, now_ms)
+    }
+
+    /// Returns the token in bytes.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut buf = [0u8; Self::LEN];
+        buf[..16].copy_from_slice(&self.nonce);
+        buf[16..24].copy_from_slice(&self.expire_at.to_be_bytes());
+        buf[24..].copy_from_slice(&self.mac);
+        buf
+    }
+
+    /// Parses a token from bytes.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CaptchaError> {
+        if data.len() != Self::LEN {
+            return Err(CaptchaError::InvalidToken(format!(
+                "expected {} bytes, got {}",
+                Self::LEN,
+                data.len()
+            )));
+        }
+
+        let mut token = ChallengeToken {
+            nonce: [0u8; 16],
+            expire_at: 0,
+            mac: [0u8; 32],
+        };
+        token.nonce.copy_from_slice(&data[..16]);
+        let mut expire_at = [0u8; 8];
+        expire_at.copy_from_slice(&data[16..24]);
+        token.expire_at = u64::from_be_bytes(expire_at);
+        token.mac.copy_from_slice(&data[24..]);
+        Ok(token)
+    }
+}
+
+/// Formats the token in URL-safe base64 without padding.
+impl fmt::Display for ChallengeToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&general_purpose::URL_SAFE_NO_PAD.encode(self.to_bytes()))
+    }
+}
+
+/// Parses the token from URL-safe base64 without padding.
+impl FromStr for ChallengeToken {
+    type Err = CaptchaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = general_purpose::URL_SAFE_NO_PAD
+            .decode(s.trim())
+            .map_err(|err| CaptchaError::InvalidToken(err.to_string()))?;
+        Self::from_bytes(&data)
+    }
+}
+
+/// A set of the spent challenge tokens making them single-use, see
+/// [`ChallengeToken::verify_once`]. A token is kept until it expires, then it is pruned,
+/// so the set holds at most the tokens spent within a TTL.
+#[derive(Debug, Default)]
+pub struct SpentTokens {
+    inner: Mutex<Spent>,
+}
+
+#[derive(Debug, Default)]
+struct Spent {
+    nonces: HashSet<[u8; 16]>,
+    expiry: BinaryHeap<Reverse<(u64, [u8; 16])>>,
+}
+
+impl SpentTokens {
+    /// Returns an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spends the token at the given time in unix milliseconds, pruning the expired tokens.
+    /// Returns [`CaptchaError::TokenSpent`] if it was spent before, or
+    /// [`CaptchaError::TokenExpired`] if it has expired.
+    pub fn spend(&self, token: &ChallengeToken, now_ms: u64) -> Result<(), CaptchaError> {
+        if now_ms > token.expire_at {
+            return Err(CaptchaError::TokenExpired);
+        }
+        let mut spent = match self.inner.lock() {
+            Ok(spent) => spent,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        while let Some(Reverse((expire_at, nonce))) = spent.expiry.peek().copied() {
+            if expire_at >= now_ms {
+                break;
+            }
+            spent.expiry.pop();
+            spent.nonces.remove(&nonce);
+        }
+        if !spent.nonces.insert(token.nonce) {
+            return Err(CaptchaError::TokenSpent);
+        }
+        spent.expiry.push(Reverse((token.expire_at, token.nonce)));
+        Ok(())
+    }
+
+    /// Returns the number of the spent tokens not pruned yet.
+    pub fn len(&self) -> usize {
+        match self.inner.lock() {
+            Ok(spent) => spent.nonces.len(),
+            Err(poisoned) => poisoned.into_inner().nonces.len(),
+        }
+    }
+
+    /// Returns true if no token is kept.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Return the current time in unix milliseconds for the web framework integrations
+#[cfg(any(
+    feature = "grpc",
+    feature = "rocket",
+    feature = "tower",
+    feature = "warp"
+))]
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Normalize the answer for comparing, the answer is case-insensitive and whitespace is
+// ignored, e.g. between the words of the word-based codes
+pub(crate) fn normalize_answer(answer: &str) -> String {
+    answer.split_whitespace().collect::<String>().to_lowercase()
+}
+
+fn token_mac(key: &[u8], nonce: &[u8; 16], expire_at: u64, answer: &str) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.update(TOKEN_DOMAIN);
+    hasher.update((key.len() as u64).to_be_bytes());
+    hasher.update(key);
+    hasher.update(nonce);
+    hasher.update(expire_at.to_be_bytes());
+    hasher.update(normalize_answer(answer).as_bytes());
+    hasher.finalize().into()
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_verifies_token() {
+        let token = ChallengeToken::new(b"secret", [1u8; 16], 1000, "AbCd");
+        assert!(token.verify(b"secret", "abcd", 999).is_ok());
+        assert!(token.verify(b"secret", " ABCD ", 1000).is_ok());
+        assert_eq!(
+            token.verify(b"secret", "abce", 999),
+            Err(CaptchaError::AnswerMismatch)
+        );
+        assert_eq!(
+            token.verify(b"other", "abcd", 999),
+            Err(CaptchaError::AnswerMismatch)
+        );
+        assert_eq!(
+            token.verify(b"secret", "abcd", 1001),
+            Err(CaptchaError::TokenExpired)
+        );
+
+        let s = token.to_string();
+        assert_eq!(s.parse::<ChallengeToken>().unwrap(), token);
+        assert!(matches!(
+            "abc".parse::<ChallengeToken>(),
+            Err(CaptchaError::InvalidToken(_))
+        ));
+    }
+
+    #[test]
+    fn it_verifies_token_once() {
+        let spent = SpentTokens::new();
+        let token = ChallengeToken::new(b"secret", [1u8; 16], 1000, "abcd");
+        assert_eq!(
+            token.verify_once(b"secret", "abce", 500, &spent),
+            Err(CaptchaError::AnswerMismatch)
+        );
+        assert!(token.verify_once(b"secret", "abcd", 500, &spent).is_ok());
+        assert_eq!(
+            token.verify_once(b"secret", "abcd", 600, &spent),
+            Err(CaptchaError::TokenSpent)
+        );
+        assert_eq!(spent.len(), 1);
+
+        // expired tokens are pruned
+        let other = ChallengeToken::new(b"secret", [2u8; 16], 3000, "abcd");
+        assert!(other.verify_once(b"secret", "abcd", 2000, &spent).is_ok());
+        assert_eq!(spent.len(), 1);
+        assert_eq!(
+            token.verify_once(b"secret", "abcd", 2000, &spent),
+            Err(CaptchaError::TokenExpired)
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use http::{HeaderName, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{token::now_ms, CaptchaError, ChallengeToken, SpentTokens};
+
+/// A [`Layer`] gating the inner service with a captcha.
+/// Requests must carry a valid [`ChallengeToken`] and its answer, in the [`ChallengeToken::HEADER`]
+/// and [`ChallengeToken::ANSWER_HEADER`] headers or in the [`ChallengeToken::PARAM`]
+/// and [`ChallengeToken::ANSWER_PARAM`] query parameters
+/// (e.g. a form submitted with GET). The body is not read, so form fields in a POST body
+/// should be moved to the headers by the client.
+///
+/// A token passes only one request: verified tokens are spent until they expire, see
+/// [`SpentTokens`]. Requests with a missing or malformed token are rejected with
+/// `400 Bad Request`, expired or used tokens and wrong answers with `403 Forbidden`.
+/// Verified tokens are inserted into the request extensions for the inner service.
+#[derive(Clone)]
+pub struct CaptchaLayer {
+    config: Arc<Config>,
+}
+
+#[derive(Clone)]
+struct Config {
+    key: Vec<u8>,
+    spent: Arc<SpentTokens>,
+    token_header: HeaderName,
+    answer_header: HeaderName,
+    token_param: String,
+    answer_param: String,
+}
+
+impl CaptchaLayer {
+    /// The default header carrying the challenge token, [`ChallengeToken::HEADER`].
+    pub const TOKEN_HEADER: &'static str = ChallengeToken::HEADER;
+    /// The default header carrying the answer, [`ChallengeToken::ANSWER_HEADER`].
+    pub const ANSWER_HEADER: &'static str = ChallengeToken::ANSWER_HEADER;
+    /// The default query parameter carrying the challenge token, [`ChallengeToken::PARAM`].
+    pub const TOKEN_PARAM: &'static str = ChallengeToken::PARAM;
+    /// The default query parameter carrying the answer, [`ChallengeToken::ANSWER_PARAM`].
+    pub const ANSWER_PARAM: &'static str = ChallengeToken::ANSWER_PARAM;
+
+    /// Returns a layer verifying tokens with the given secret key.
+    pub fn new(key: &[u8]) -> Self {
+        CaptchaLayer {
+            config: Arc::new(Config {
+                key: key.to_vec(),
+                spent: Arc::new(SpentTokens::new()),
+                token_header: HeaderName::from_static(Self::TOKEN_HEADER),
+                answer_header: HeaderName::from_static(Self::ANSWER_HEADER),
+                token_param: Self::TOKEN_PARAM.to_string(),
+                answer_param: Self::ANSWER_PARAM.to_string(),
+            }),
+        }
+    }
+
+    /// Set the set of the spent tokens, e.g. to share it between layers gating different
+    /// routes with the same key.
+    pub fn spent_tokens(mut self, spent: Arc<SpentTokens>) -> Self {
+        Arc::make_mut(&mut self.config).spent = spent;
+        self
+    }
+
+    /// Set the headers carrying the token and the answer.
+    pub fn headers(mut self, token: HeaderName, answer: HeaderName) -> Self {
+        let config = Arc::make_mut(&mut self.config);
+        config.token_header = token;
+        config.answer_header = answer;
+        self
+    }
+
+    /// Set the query parameters carrying the token and the answer.
+    pub fn params(mut self, token: &str, answer: &str) -> Self {
+        let config = Arc::make_mut(&mut self.config);
+        config.token_param = token.to_string();
+        config.answer_param = answer.to_string();
+        self
+    }
+}
+
+impl<S> Layer<S> for CaptchaLayer {
+    type Service = CaptchaService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CaptchaService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// The [`Service`] created by [`CaptchaLayer`].
+#[derive(Clone)]
+pub struct CaptchaService<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CaptchaService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        match self.config.verify(&req) {
+            Ok(token) => {
+                req.extensions_mut().insert(token);
+                ResponseFuture::Forward {
+                    future: self.inner.call(req),
+                }
+            }
+            Err(err) => {
+                let status =
+                    StatusCode::from_u16(err.status_code()).unwrap_or(StatusCode::BAD_REQUEST);
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = status;
+                ResponseFuture::Reject {
+                    response: Some(res),
+                }
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of [`CaptchaService`].
+    #[project = ResponseFutureProj]
+    pub enum ResponseFuture<F, B> {
+        Forward { #[pin] future: F },
+        Reject { response: Option<Response<B>> },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Forward { future } => future.poll(cx),
+            ResponseFutureProj::Reject { response } => {
+                Poll::Ready(Ok(response.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+impl Config {
+    // Verify the token and the answer carried by the request
+    fn verify<B>(&self, req: &Request<B>) -> Result<ChallengeToken, CaptchaError> {
+        let token = self
+            .value(req, &self.token_header, &self.token_param)
+            .ok_or_else(|| CaptchaError::InvalidToken("missing token".to_string()))?;
+        let answer = self
+            .value(req, &self.answer_header, &self.answer_param)
+            .ok_or(CaptchaError::AnswerMismatch)?;
+        let token: ChallengeToken = token.parse()?;
+        token.verify_once(&self.key, &answer, now_ms(), &self.spent)?;
+        Ok(token)
+    }
+
+    // Return the value of the header, or the query parameter if the header is absent
+    fn value<B>(&self, req: &Request<B>, header: &HeaderName, param: &str) -> Option<String> {
+        if let Some(value) = req.headers().get(header) {
+            return value.to_str().ok().map(str::to_string);
+        }
+        req.uri()
+            .query()
+            .and_then(|query| query_param(query, param))
+    }
+}
+
+// Return the percent-decoded value of a form-urlencoded query parameter
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (percent_decode(key) == name).then(|| percent_decode(value))
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn hex(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    // An inner service echoing the verified token
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request<()>) -> Self::Future {
+            let token = req.extensions().get::<ChallengeToken>().unwrap();
+            std::future::ready(Ok(Response::new(token.to_string())))
+        }
+    }
+
+    #[tokio::test]
+    async fn it_gates_requests() {
+        let token = ChallengeToken::new(b"secret", [3u8; 16], u64::MAX, "Ab3d");
+        let mut service = CaptchaLayer::new(b"secret").layer(Echo);
+
+        let req = Request::builder()
+            .header(CaptchaLayer::TOKEN_HEADER, token.to_string())
+            .header(CaptchaLayer::ANSWER_HEADER, "ab3d")
+            .body(())
+            .unwrap();
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), &token.to_string());
+
+        // the token passes only once
+        let req = Request::builder()
+            .uri(format!(
+                "/submit?captcha_token={}&captcha_answer=+AB3D",
+                token
+            ))
+            .body(())
+            .unwrap();
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let token = ChallengeToken::new(b"secret", [4u8; 16], u64::MAX, "Ab3d");
+        let req = Request::builder()
+            .uri(format!(
+                "/submit?captcha_token={}&captcha_answer=+AB3D",
+                token
+            ))
+            .body(())
+            .unwrap();
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = Request::builder()
+            .header(ChallengeToken::HEADER, token.to_string())
+            .header(ChallengeToken::ANSWER_HEADER, "abcd")
+            .body(())
+            .unwrap();
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = Request::builder().body(()).unwrap();
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn it_decodes_query_params() {
+        assert_eq!(
+            query_param("a=1&captcha_answer=x%2By+z", "captcha_answer").as_deref(),
+            Some("x+y z")
+        );
+        assert_eq!(query_param("a=%zz%4", "a").as_deref(), Some("%zz%4"));
+        assert_eq!(query_param("a=1", "b"), None);
+    }
+}
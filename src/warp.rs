@@ -0,0 +1,183 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+use warp::{
+    http::{header, Response, StatusCode},
+    reject::{Reject, Rejection},
+    Filter, Reply,
+};
+
+use crate::{token::now_ms, CaptchaError, ChallengePool, ChallengeToken, OutputFormat};
+
+/// The rejection of [`verify_filter`], carrying the verification error.
+/// Convert it to a response in a `recover` handler with [`CaptchaRejection::into_response`].
+#[derive(Debug)]
+pub struct CaptchaRejection(pub CaptchaError);
+
+impl Reject for CaptchaRejection {}
+
+impl CaptchaRejection {
+    /// Returns a response with the status code of the error and the error message.
+    pub fn into_response(self) -> warp::reply::Response {
+        let status = StatusCode::from_u16(self.0.status_code()).unwrap_or(StatusCode::BAD_REQUEST);
+        warp::reply::with_status(self.0.to_string(), status).into_response()
+    }
+}
+
+/// A filter issuing a challenge from the pool: it responds with the captcha image in JPEG
+/// and the token in the [`ChallengeToken::HEADER`] header. The captcha is rendered and encoded
+/// on tokio's blocking thread pool.
+pub fn issue_filter(
+    pool: Arc<ChallengePool>,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Infallible> + Clone {
+    warp::any().then(move || {
+        let pool = pool.clone();
+        async move {
+            // render and encode on tokio's blocking thread pool, not on the executor
+            tokio::task::spawn_blocking(move || issue_response(&pool))
+                .await
+                .unwrap_or_else(|err| {
+                    CaptchaRejection(CaptchaError::Internal(err.to_string())).into_response()
+                })
+        }
+    })
+}
+
+fn issue_response(pool: &ChallengePool) -> warp::reply::Response {
+    let challenge = pool.issue(now_ms());
+    let mut buf = Vec::new();
+    match challenge
+        .captcha
+        .encode_into(&mut buf, OutputFormat::Jpeg, 30)
+    {
+        Ok(()) => {
+            pool.observe_encoded(buf.len());
+            Response::builder()
+                .header(header::CONTENT_TYPE, OutputFormat::Jpeg.mime_type())
+                .header(header::CACHE_CONTROL, "no-store")
+                .header(ChallengeToken::HEADER, challenge.token.to_string())
+                .body(buf.into())
+                .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+        Err(err) => CaptchaRejection(err).into_response(),
+    }
+}
+
+/// A filter extracting the verified [`ChallengeToken`] of a request.
+/// The token and the answer are read from the [`ChallengeToken::HEADER`] and
+/// [`ChallengeToken::ANSWER_HEADER`] headers, or the [`ChallengeToken::PARAM`] and
+/// [`ChallengeToken::ANSWER_PARAM`] query parameters. A token passes only one request,
+/// it is spent by [`ChallengePool::verify_once`]. Requests that fail the verification
+/// are rejected with a [`CaptchaRejection`].
+pub fn verify_filter(
+    pool: Arc<ChallengePool>,
+) -> impl Filter<Extract = (ChallengeToken,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(ChallengeToken::HEADER)
+        .and(warp::header::optional::<String>(
+            ChallengeToken::ANSWER_HEADER,
+        ))
+        .and(
+            warp::query::<HashMap<String, String>>()
+                .or(warp::any().map(HashMap::new))
+                .unify(),
+        )
+        .and_then(
+            move |token: Option<String>,
+                  answer: Option<String>,
+                  mut query: HashMap<String, String>| {
+                let result = verify(
+                    &pool,
+                    token.or_else(|| query.remove(ChallengeToken::PARAM)),
+                    answer.or_else(|| query.remove(ChallengeToken::ANSWER_PARAM)),
+                )
+                .map_err(|err| warp::reject::custom(CaptchaRejection(err)));
+                async move { result }
+            },
+        )
+}
+
+fn verify(
+    pool: &ChallengePool,
+    token: Option<String>,
+    answer: Option<String>,
+) -> Result<ChallengeToken, CaptchaError> {
+    let token: ChallengeToken = token
+        .ok_or_else(|| CaptchaError::InvalidToken("missing token".to_string()))?
+        .parse()?;
+    let answer = answer.ok_or(CaptchaError::AnswerMismatch)?;
+    pool.verify_once(&token, &answer, now_ms())?;
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CaptchaBuilder;
+
+    fn new_pool() -> ChallengePool {
+        ChallengePool::new(
+            CaptchaBuilder::new().build(),
+            b"secret",
+            &[8u8; 32],
+            1,
+            60_000,
+        )
+    }
+
+    #[tokio::test]
+    async fn it_issues_and_verifies_challenges() {
+        let pool = Arc::new(new_pool());
+        let issue = warp::path("challenge").and(issue_filter(pool.clone()));
+        let protected = warp::path("protected")
+            .and(verify_filter(pool.clone()))
+            .map(|token: ChallengeToken| token.to_string());
+
+        let res = warp::test::request().path("/challenge").reply(&issue).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "image/jpeg");
+        let token = res.headers()[ChallengeToken::HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let answer = new_pool().issue(0).captcha.text();
+
+        let res = warp::test::request()
+            .path(&format!(
+                "/protected?captcha_token={}&captcha_answer={}",
+                token, answer
+            ))
+            .reply(&protected)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), token.as_bytes());
+
+        // the token passes only once
+        let rejection = warp::test::request()
+            .path("/protected")
+            .header(ChallengeToken::HEADER, &token)
+            .header(ChallengeToken::ANSWER_HEADER, &answer)
+            .filter(&verify_filter(pool.clone()))
+            .await
+            .unwrap_err();
+        let rejection = rejection.find::<CaptchaRejection>().unwrap();
+        assert_eq!(rejection.0, CaptchaError::TokenSpent);
+
+        let rejection = warp::test::request()
+            .path("/protected")
+            .header(ChallengeToken::HEADER, &token)
+            .header(ChallengeToken::ANSWER_HEADER, "wrong")
+            .filter(&verify_filter(pool.clone()))
+            .await
+            .unwrap_err();
+        let rejection = rejection.find::<CaptchaRejection>().unwrap();
+        assert_eq!(rejection.0, CaptchaError::AnswerMismatch);
+
+        let rejection = warp::test::request()
+            .path("/protected")
+            .filter(&verify_filter(pool))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            rejection.find::<CaptchaRejection>(),
+            Some(CaptchaRejection(CaptchaError::InvalidToken(_)))
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use crate::{CaptchaBuilder, CaptchaError};
+
+// Common English words spelled with the letters of the default charset only, without the
+// easily confused i, l and o, so that they can be rendered with the default font subset
+#[cfg(feature = "english-words")]
+const ENGLISH_WORDS: [&str; 103] = [
+    "after", "amber", "badge", "bake", "banana", "barn", "basket", "beach", "brave", "bread",
+    "cake", "candy", "carpet", "cedar", "center", "charm", "chase", "cherry", "crane", "cream",
+    "dance", "date", "desert", "eager", "earth", "ember", "fancy", "father", "feast", "feather",
+    "fern", "frame", "fresh", "garden", "gate", "gather", "grape", "green", "hammer", "happy",
+    "heart", "hunter", "jacket", "judge", "karma", "keeper", "kept", "knack", "march", "market",
+    "mast", "mercy", "merry", "nectar", "nest", "number", "nurse", "paper", "parade", "party",
+    "path", "peach", "pepper", "queen", "quest", "raven", "ready", "saber", "scene", "secret",
+    "shade", "shape", "sharp", "smart", "spark", "stand", "star", "steam", "sugar", "summer",
+    "sunny", "super", "sweet", "taste", "teach", "temper", "tent", "thank", "theme", "thunder",
+    "tread", "truck", "trust", "under", "vest", "wander", "waste", "water", "wheat", "yacht",
+    "yard", "zebra", "zesty",
+];
+
+/// A list of words the word-based verification codes are drawn from,
+/// see [`CaptchaBuilder::words`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordList {
+    words: Arc<[Box<str>]>,
+}
+
+impl WordList {
+    /// Returns a word list of the given words. Returns an error if the list is empty or a word
+    /// is empty or contains whitespace.
+    pub fn new<S: AsRef<str>>(words: &[S]) -> Result<Self, CaptchaError> {
+        if words.is_empty() {
+            return Err(CaptchaError::InvalidConfig("empty word list".to_string()));
+        }
+        let words = words
+            .iter()
+            .map(|word| {
+                let word = word.as_ref();
+                if word.is_empty() || word.contains(char::is_whitespace) {
+                    return Err(CaptchaError::InvalidConfig(format!(
+                        "invalid word {:?} in word list",
+                        word
+                    )));
+                }
+                Ok(Box::from(word))
+            })
+            .collect::<Result<Vec<Box<str>>, CaptchaError>>()?;
+        Ok(WordList {
+            words: words.into(),
+        })
+    }
+
+    /// Returns the built-in list of 103 common English words, spelled with the letters of
+    /// [`crate::BASIC_CHARS`] only so that they render with the default font.
+    #[cfg(feature = "english-words")]
+    pub fn english() -> Self {
+        WordList::new(&ENGLISH_WORDS).expect("the built-in words are valid")
+    }
+
+    /// Returns the words of the list.
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.words.iter().map(|word| &**word)
+    }
+}
+
+impl CaptchaBuilder {
+    /// Set the list of the word-based verification codes, see [`CaptchaBuilder::words`].
+    /// Returns an error if a character of the words has no glyph in the fonts,
+    /// so set it after the fonts.
+    pub fn word_list(mut self, list: WordList) -> Result<Self, CaptchaError> {
+        for c in list.words().flat_map(str::chars) {
+            if self
+                .fonts
+                .iter()
+                .any(|font| !crate::font::has_glyph(font, c))
+            {
+                return Err(CaptchaError::Font(format!("no glyph of character {:?}", c)));
+            }
+        }
+        self.word_list = Some(list);
+        Ok(self)
+    }
+
+    /// Set the number of words of the word-based verification codes, 1 or 2, default is 0
+    /// for random codes of the charset. Humans read real words far faster than random strings.
+    /// The words are drawn from the seed out of the list set by [`CaptchaBuilder::word_list`],
+    /// or the built-in English list with the `english-words` feature, and joined with a space.
+    /// Whitespace is ignored when verifying the answers. Two words need a wider canvas than
+    /// the default, e.g. 240 pixels.
+    pub fn words(mut self, n: u8) -> Self {
+        self.words = n.min(2);
+        #[cfg(feature = "english-words")]
+        if self.word_list.is_none() {
+            self.word_list = Some(WordList::english());
+        }
+        self
+    }
+
+    // Fill the code with the words drawn from the random stream, returns false
+    // if the codes are not word-based
+    pub(crate) fn random_words<R>(&self, get_rnd: &mut R, code: &mut Vec<char>) -> bool
+    where
+        R: FnMut(u32) -> u32,
+    {
+        let Some(list) = self.word_list.as_ref().filter(|_| self.words > 0) else {
+            return false;
+        };
+
+        code.clear();
+        for i in 0..self.words {
+            if i > 0 {
+                code.push(' ');
+            }
+            let word = &list.words[get_rnd(list.words.len() as u32) as usize];
+            code.extend(word.chars());
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::normalize_answer;
+
+    #[test]
+    fn it_generates_word_captchas() {
+        let list = WordList::new(&["brave", "green", "water"]).unwrap();
+        let builder = CaptchaBuilder::new()
+            .width(240)
+            .word_list(list.clone())
+            .unwrap()
+            .words(2);
+        for seed in [b"seed 0", b"seed 1", b"seed 2"] {
+            let text = builder.generate_lazy(seed, None).text();
+            let words: Vec<&str> = text.split(' ').collect();
+            assert_eq!(words.len(), 2);
+            assert!(words.iter().all(|word| list.words().any(|w| w == *word)));
+        }
+        let captcha = builder.generate(b"seed", None);
+        assert_eq!(captcha.text(), builder.generate_lazy(b"seed", None).text());
+        assert_eq!(
+            normalize_answer(&captcha.text()),
+            normalize_answer(&captcha.text().replace(' ', ""))
+        );
+
+        let one = builder.clone().words(1).generate_lazy(b"seed", None).text();
+        assert!(list.words().any(|w| w == one));
+        // Without words the random codes are drawn
+        assert_eq!(
+            builder.words(0).generate_lazy(b"seed", None).text(),
+            CaptchaBuilder::new().generate_lazy(b"seed", None).text()
+        );
+
+        assert!(WordList::new::<&str>(&[]).is_err());
+        assert!(WordList::new(&["two words"]).is_err());
+        #[cfg(not(feature = "full-font"))]
+        assert!(matches!(
+            CaptchaBuilder::new().word_list(WordList::new(&["\u{4e2d}"]).unwrap()),
+            Err(CaptchaError::Font(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "english-words")]
+    fn it_renders_english_words() {
+        let english = WordList::english();
+        assert!(english
+            .words()
+            .flat_map(str::chars)
+            .all(|c| crate::BASIC_CHARS.contains(&c)));
+        let text = CaptchaBuilder::new()
+            .words(1)
+            .generate_lazy(b"seed", None)
+            .text();
+        assert!(english.words().any(|w| w == text));
+    }
+}